@@ -1,19 +1,25 @@
 use console_engine::{
     pixel, rect_style::BorderStyle, screen::Screen, Color, ConsoleEngine, KeyCode, KeyModifiers,
 };
+use std::process;
 
 pub use crate::{
-    emulator::{
-        arch::{
-            flag::{Flag, FlagType},
-            opcodes::Opcode,
-        },
-        utils::join_bytes,
+    emulator::arch::{
+        flag::Flag,
+        i8085::{Input, Pins},
+        instruction::decode_as,
+        z80,
     },
-    State,
+    Cpu, CpuError, Interrupt, State, WritePolicy,
 };
 
-use crate::{machine::video::graphics, IO};
+use crate::{
+    machine::{
+        savestate::{SlotKey, SLOTS},
+        video::graphics,
+    },
+    Board, Memory, Session, IO,
+};
 
 pub const WIDTH: u32 = 50;
 pub const HEIGHT: u32 = 20;
@@ -60,14 +66,17 @@ pub const HIGHLIGHT: Color = Color::Rgb {
     b: 0,
 };
 
+#[allow(clippy::too_many_arguments)]
 pub async fn process_input(
     engine: &ConsoleEngine,
     state: &mut State,
-    io: &mut IO,
+    board: &mut Board,
     kb: &mut bool,
     mv: &mut i32,
     debug: &mut bool,
     live: &mut bool,
+    fault: &mut Option<CpuError>,
+    session: &mut Session,
 ) -> bool {
     if engine.is_key_pressed(KeyCode::Char('q'))
         || engine.is_key_pressed(KeyCode::Char('Q'))
@@ -86,29 +95,68 @@ pub async fn process_input(
         return true;
     }
 
-    if engine.is_key_held(KeyCode::Char('r')) {
+    // A movie only has the inputs for whole frames, nothing else may move
+    // the machine while one is recorded
+    let recording = session.recording();
+
+    if engine.is_key_held(KeyCode::Char('r')) && !recording {
         *mv = 0;
-        state.run_op(io);
+        *fault = session.step(state, board, 1).err();
         if *live {
-            graphics::graphics(&state.mem, io).await;
+            graphics::graphics(board).await;
         }
     }
     
-    if engine.is_key_held(KeyCode::Char('R')) {
+    if engine.is_key_held(KeyCode::Char('R')) && !recording {
         *mv = 0;
-        for _ in 0..100 {
-            state.run_op(io);
-        }
+        *fault = session.step(state, board, 100).err();
         if *live {
-            graphics::graphics(&state.mem, io).await;
+            graphics::graphics(board).await;
         }
     }
 
-    if engine.is_key_held(KeyCode::Char('i')) {
-        state.generate_interrupt(2);
+    if engine.is_key_held(KeyCode::Char('b')) && session.step_back(state, board) {
+        *mv = 0;
+        *fault = None;
+    }
+    if engine.is_key_held(KeyCode::Char('B')) && session.rewind_frame(state, board) {
+        *mv = 0;
+        *fault = None;
+    }
+
+    if engine.is_key_held(KeyCode::Char('i')) && !recording {
+        state.request_interrupt(Interrupt::Rst(2));
+        session.rewind.mark(state, board);
     }
-    if engine.is_key_pressed(KeyCode::Char('I')) {
-        state.generate_interrupt(1);
+    if engine.is_key_pressed(KeyCode::Char('I')) && !recording {
+        state.request_interrupt(Interrupt::Rst(1));
+        session.rewind.mark(state, board);
+    }
+
+    if state.cpu == Cpu::I8085 && !recording {
+        let pins = state.pins;
+        if engine.is_key_pressed(KeyCode::Char('t')) || engine.is_key_pressed(KeyCode::Char('T')) {
+            state.pins.set_input(Input::Trap, true);
+        }
+        // RST 7.5 latches on the edge, 6.5 and 5.5 follow the key
+        state.pins.set_input(Input::Rst7_5, engine.is_key_held(KeyCode::Char('7')));
+        state.pins.set_input(Input::Rst6_5, engine.is_key_held(KeyCode::Char('6')));
+        state.pins.set_input(Input::Rst5_5, engine.is_key_held(KeyCode::Char('5')));
+        if engine.is_key_pressed(KeyCode::Char('x')) || engine.is_key_pressed(KeyCode::Char('X')) {
+            state.pins.sid = !state.pins.sid;
+        }
+        // The pins are not logged, so snapshot whenever one changes
+        let level = |p: &Pins| (p.trap, p.rst7_5, p.rst7_5_line, p.rst6_5, p.rst5_5, p.sid);
+        if level(&pins) != level(&state.pins) {
+            session.rewind.mark(state, board);
+        }
+    }
+    if state.cpu == Cpu::Z80
+        && !recording
+        && (engine.is_key_pressed(KeyCode::Char('n')) || engine.is_key_pressed(KeyCode::Char('N')))
+    {
+        state.z80.nmi = true;
+        session.rewind.mark(state, board);
     }
 
     if engine.is_key_held(KeyCode::Char('d')) || engine.is_key_pressed(KeyCode::Char('D')) {
@@ -119,8 +167,32 @@ pub async fn process_input(
         *live = !*live;
     }
 
+    if engine.is_key_pressed(KeyCode::Char('u')) || engine.is_key_pressed(KeyCode::Char('U')) {
+        state.trap_undocumented = !state.trap_undocumented;
+    }
+
+    if engine.is_key_pressed(KeyCode::Char('w')) || engine.is_key_pressed(KeyCode::Char('W')) {
+        board.mem.map.policy = match board.mem.map.policy {
+            WritePolicy::Trap => WritePolicy::Ignore,
+            _ => WritePolicy::Trap,
+        };
+    }
+
     if engine.is_key_held(KeyCode::Char('s')) || engine.is_key_pressed(KeyCode::Char('S')) {
-        graphics::graphics(&state.mem, io).await;
+        graphics::graphics(board).await;
+    }
+
+    for slot in 1..=SLOTS {
+        if engine.is_key_pressed(KeyCode::F(slot)) {
+            session.slots.select(slot);
+        }
+    }
+    if engine.is_key_pressed(KeyCode::F(5)) {
+        session.slots.save(state, board);
+    }
+    if engine.is_key_pressed(KeyCode::F(9)) && session.load(state, board) {
+        *mv = 0;
+        *fault = None;
     }
 
     if engine.is_key_pressed(KeyCode::Char('h'))
@@ -134,10 +206,10 @@ pub async fn process_input(
     }
 
     if engine.is_key_held(KeyCode::Up) && *mv + state.pc as i32 > 0 {
-        *mv = *mv - 1;
+        *mv -= 1;
     }
     if engine.is_key_held(KeyCode::Down) {
-        *mv = *mv + Opcode::cycles(state.mem[(state.pc as i32 + *mv) as usize]) as i32;
+        *mv += decode_as(state.cpu, &board.mem.data, (state.pc as i32 + *mv) as u16).length as i32;
     }
     if engine.is_key_pressed(KeyCode::Char(' ')) {
         *mv = 0;
@@ -158,6 +230,17 @@ pub fn keybinds() -> Screen {
         "d     - toggle debug",
         "L     - toggle live",
         "s     - sync screen",
+        "u     - trap undoc ops",
+        "w     - trap ROM writes",
+        "t     - 8085 TRAP",
+        "5/6/7 - 8085 RST n.5",
+        "x     - 8085 toggle SID",
+        "n     - Z80 NMI",
+        "b     - step back",
+        "B     - back a frame",
+        "F1-F4 - save slot",
+        "F5    - save state",
+        "F9    - load state",
         "q     - quit",
         "tab   - keybinds",
     ];
@@ -179,27 +262,42 @@ pub fn keybinds() -> Screen {
     scr
 }
 
-pub fn disass(state: &State, io: &IO, height: u32, width: u32, line: &i32) -> Screen {
+pub fn disass(state: &State, board: &Board, height: u32, width: u32, line: &i32) -> Screen {
     let mut scr = Screen::new(width - 6, height - 4);
 
-    scr.print_screen(1, 0, &display_status(state, io));
-    scr.print_screen(54, 1, &display_ports(&io));
-    scr.print_screen(1, 6, &display_ops(state, height, line));
+    scr.print_screen(1, 0, &display_status(state, board));
+    scr.print_screen(54, 1, &display_ports(&board.io));
+    match state.cpu {
+        Cpu::I8080 => (),
+        Cpu::I8085 => scr.print_screen(54, 7, &display_pins(&state.pins)),
+        Cpu::Z80 => scr.print_screen(54, 7, &display_z80(&state.z80)),
+    }
+    scr.print_screen(1, 6, &display_ops(state, &board.mem, height, line));
 
     scr
 }
 
-fn display_status(state: &State, io: &IO) -> Screen {
+fn display_status(state: &State, board: &Board) -> Screen {
     let mut scr = Screen::new(83, 5);
 
     scr.print_fbg(1, 0, &format!("Flags: INT EN - {:01x}", state.enable), NORMAL, Color::Reset);
-    scr.print_screen(0, 1, &display_flags(&state.flags));
+    scr.print_screen(0, 1, &display_flags(&state.flags, state.cpu));
 
     scr.print_fbg(22, 0, "Registers:", NORMAL, Color::Reset);
-    scr.print_screen(21, 1, &display_regs(&state));
+    scr.print_screen(21, 1, &display_regs(state));
 
     scr.print_fbg(55, 0, "Ports:", NORMAL, Color::Reset);
-    scr.print_fbg(62, 0, &format!("SH-R: {:04x}", io.shift), DARKENAB, Color::Reset);
+    scr.print_fbg(62, 0, &format!("SH-R: {:04x}", board.io.shift), DARKENAB, Color::Reset);
+
+    if state.halted {
+        scr.print_fbg(74, 0, "HALT", HIGHLIGHT, Color::Reset);
+    }
+    if state.trap_undocumented {
+        scr.print_fbg(79, 0, "TRAP", DARKENAB, Color::Reset);
+    }
+    if board.mem.map.policy == WritePolicy::Trap {
+        scr.print_fbg(34, 0, "WR TRAP", DARKENAB, Color::Reset);
+    }
     scr
 }
 
@@ -279,7 +377,7 @@ fn display_regs(state: &State) -> Screen {
     scr
 }
 
-fn display_flags(flags: &Flag) -> Screen {
+fn display_flags(flags: &Flag, cpu: Cpu) -> Screen {
     let mut scr = Screen::new(19, 4);
     scr.rect_border(
         0,
@@ -288,43 +386,39 @@ fn display_flags(flags: &Flag) -> Screen {
         scr.get_height() as i32 - 1,
         BorderStyle::new_heavy().with_colors(DARK, Color::Reset),
     );
-    scr.print_fbg(2, 1, "Z S P C A", NORMAL, Color::Reset);
-    scr.print_fbg(
-        2,
-        2,
-        format!(
-            "{} {} {} {} {}",
-            flags.get(FlagType::Z),
-            flags.get(FlagType::S),
-            flags.get(FlagType::P),
-            flags.get(FlagType::CY),
-            flags.get(FlagType::AC),
-        )
-        .as_str(),
-        ENABLED,
-        Color::Reset,
-    );
-    scr.print_fbg(12, 1, "P A D", DULL, Color::Reset);
-    scr.print_fbg(
-        12,
-        2,
-        format!(
-            "{} {} {}",
-            flags.get(FlagType::PAD) >> 2 & 0b1,
-            flags.get(FlagType::PAD) >> 1 & 0b1,
-            flags.get(FlagType::PAD) & 0b1,
-        )
-        .as_str(),
-        DISABLED,
-        Color::Reset,
-    );
+    // PSW as pushed by PUSH PSW: S Z 0 AC 0 P 1 CY, the 8085 fills in K and V
+    let (names, fixed) = match cpu {
+        Cpu::I8080 => (['S', 'Z', '0', 'A', '0', 'P', '1', 'C'], Flag::FIXED | !Flag::MASK),
+        Cpu::I8085 => (['S', 'Z', 'K', 'A', '0', 'P', 'V', 'C'], 0b0000_1000),
+        // The Z80's undocumented copies of result bits 5 and 3 are not kept
+        Cpu::Z80 => (['S', 'Z', '5', 'H', '3', 'V', 'N', 'C'], 0b0010_1000),
+    };
+    for (i, name) in names.iter().enumerate() {
+        let bit = flags.reg >> (7 - i) & 0b1;
+        let is_fixed = fixed >> (7 - i) & 0b1 == 1;
+
+        scr.print_fbg(
+            2 + 2 * i as i32,
+            1,
+            &name.to_string(),
+            if is_fixed { DULL } else { NORMAL },
+            Color::Reset,
+        );
+        scr.print_fbg(
+            2 + 2 * i as i32,
+            2,
+            &format!("{}", bit),
+            if is_fixed { DISABLED } else { ENABLED },
+            Color::Reset,
+        );
+    }
 
     scr
 }
 
-fn display_ops(state: &State, height: u32, line: &i32) -> Screen {
+fn display_ops(state: &State, mem: &Memory, height: u32, line: &i32) -> Screen {
     let mut scr = Screen::new(52, height - 11);
-    let mut counter = 0 as i32;
+    let mut counter = 0_i32;
     let mut ip = state.pc as i32 + line;
 
     scr.rect_border(
@@ -343,6 +437,10 @@ fn display_ops(state: &State, height: u32, line: &i32) -> Screen {
         Color::Reset,
     );
 
+    if let Some(int) = state.interrupt {
+        scr.print_fbg(36, 0, &format!(" {} pend ", int), HIGHLIGHT, Color::Reset);
+    }
+
     if *line != 0 {
         scr.rect_border(
             2,
@@ -369,49 +467,39 @@ fn display_ops(state: &State, height: u32, line: &i32) -> Screen {
         scr.print_fbg(2, 2, ">", NORMAL, Color::Reset);
     }
 
-    while ip < (state.mem.len() as i32) && counter < (height / 2 - 7) as i32 {
+    while ip < (mem.data.len() as i32) && counter < (height / 2 - 7) as i32 {
         if ip < 0 {
             counter += 1;
             ip += 1;
             continue;
         }
 
-        let _ip = ip as usize;
-        let opcode = state.mem[_ip];
+        let _ip = ip as u16;
+        let instr = decode_as(state.cpu, &mem.data, _ip);
 
         let instr_addr = format!("{:#06x}:", _ip).to_string();
-        let instr = format!("{:02}", Opcode::convert(opcode)).to_string();
-        let opc = format!("{:02x}", opcode).to_string();
+        let opc = format!("{:02x}", instr.opcode).to_string();
+        let r_adr: String = instr.bytes[1..instr.length as usize]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
 
         scr.print_fbg(4, 2 * (counter + 1), &instr_addr, DARK, Color::Reset);
         scr.print_fbg(13, 2 * (counter + 1), &opc, ENABLED, Color::Reset);
-        scr.print_fbg(22, 2 * (counter + 1), &instr, HIGHLIGHT, Color::Reset);
-
-        match Opcode::cycles(opcode) {
-            3 => {
-                let adr = join_bytes(state.mem[_ip + 2], state.mem[_ip + 1]);
-                let adr = format!("{:04x}", adr).to_string();
-                scr.print_fbg(33, 2 * (counter + 1), &adr, LIGHT, Color::Reset);
-
-                let r_adr =
-                    format!("{:02x}{:02x}", state.mem[_ip + 1], state.mem[_ip + 2]).to_string();
-                scr.print_fbg(15, 2 * (counter + 1), &r_adr, DARKENAB, Color::Reset);
-                ip += 3;
-            }
-            2 => {
-                let adr = state.mem[_ip + 1];
-                let adr = format!("{:02x}", adr).to_string();
-                scr.print_fbg(33, 2 * (counter + 1), &adr, LIGHT, Color::Reset);
-
-                let r_adr = format!("{:02x}", state.mem[_ip + 1]).to_string();
-                scr.print_fbg(15, 2 * (counter + 1), &r_adr, DARKENAB, Color::Reset);
-                ip += 2;
-            }
-            _ => {
-                ip += 1;
-            }
+        scr.print_fbg(15, 2 * (counter + 1), &r_adr, DARKENAB, Color::Reset);
+        scr.print_fbg(22, 2 * (counter + 1), instr.mnemonic, HIGHLIGHT, Color::Reset);
+
+        let operands = instr.to_string();
+        if let Some((_, operands)) = operands.split_once(' ') {
+            scr.print_fbg(27, 2 * (counter + 1), operands, LIGHT, Color::Reset);
+        }
+
+        if instr.is_undocumented() {
+            scr.print_fbg(21, 2 * (counter + 1), "*", LIGHT, Color::Reset);
+            scr.print_fbg(40, 2 * (counter + 1), "undoc", DISABLED, Color::Reset);
         }
 
+        ip += instr.length as i32;
         counter += 1;
     }
     scr
@@ -473,6 +561,65 @@ pub fn display_ports(io: &IO) -> Screen {
     scr
 }
 
+/// SIM masks, pending inputs and the serial lines of the 8085
+fn display_pins(pins: &Pins) -> Screen {
+    let mut scr = Screen::new(27, 4);
+    scr.rect_border(
+        0,
+        0,
+        scr.get_width() as i32 - 1,
+        scr.get_height() as i32 - 1,
+        BorderStyle::new_heavy().with_colors(DARK, Color::Reset),
+    );
+    scr.print_fbg(2, 1, "TR 7.5 6.5 5.5 SID SOD", NORMAL, Color::Reset);
+
+    let pending = [pins.trap, pins.rst7_5, pins.rst6_5, pins.rst5_5];
+    let masked = [false, pins.mask & 0b100 != 0, pins.mask & 0b010 != 0, pins.mask & 0b001 != 0];
+    for (i, x) in [2, 6, 10, 14].into_iter().enumerate() {
+        let text = if masked[i] { "m" } else { "-" };
+        let text = if pending[i] { "*" } else { text };
+        scr.print_fbg(x, 2, text, if pending[i] { ENABLED } else { DISABLED }, Color::Reset);
+    }
+    for (x, bit) in [(19, pins.sid), (23, pins.sod)] {
+        scr.print_fbg(x, 2, &format!("{}", bit as u8), if bit { ENABLED } else { DISABLED }, Color::Reset);
+    }
+
+    scr
+}
+
+/// Alternate and index registers and the interrupt state of the Z80
+fn display_z80(regs: &z80::Registers) -> Screen {
+    let mut scr = Screen::new(27, 6);
+    scr.rect_border(
+        0,
+        0,
+        scr.get_width() as i32 - 1,
+        scr.get_height() as i32 - 1,
+        BorderStyle::new_heavy().with_colors(DARK, Color::Reset),
+    );
+    scr.print_fbg(2, 1, "AF'  BC'  DE'  HL'", NORMAL, Color::Reset);
+    scr.print_fbg(
+        2,
+        2,
+        &format!("{:04x} {:04x} {:04x} {:04x}", regs.af_, regs.bc_, regs.de_, regs.hl_),
+        ENABLED,
+        Color::Reset,
+    );
+    scr.print_fbg(2, 3, "IX   IY   I  R  IM F2", NORMAL, Color::Reset);
+    scr.print_fbg(
+        2,
+        4,
+        &format!(
+            "{:04x} {:04x} {:02x} {:02x} {}  {}",
+            regs.ix, regs.iy, regs.i, regs.r, regs.im, regs.iff2 as u8
+        ),
+        ENABLED,
+        Color::Reset,
+    );
+
+    scr
+}
+
 pub fn pulse_anim(pulse: &f32) -> Screen {
     let mut scr = Screen::new(13, 13);
 
@@ -566,3 +713,184 @@ pub fn pulse_anim(pulse: &f32) -> Screen {
 
     scr
 }
+
+/// Runs the TUI debugger, paused unless `start_in_game`
+pub async fn emulate(mut state: State, mut board: Board, mut session: Session, start_in_game: bool) {
+    let mut engine =
+        console_engine::ConsoleEngine::init(WIDTH, HEIGHT, TARGET_FPS).unwrap_or_else(|err| {
+            println!("Could not create screen: {err}");
+            process::exit(-1);
+        });
+
+    let mut show_keybinds = false;
+    let mut mv = 0;
+    let mut pulse = 0.0;
+    let mut debug = !start_in_game;
+    let mut live = false;
+    let mut fault: Option<CpuError> = None;
+
+    loop {
+        engine.wait_frame();
+        engine.check_resize();
+        engine.clear_screen();
+        
+        if process_input(
+            &engine,
+            &mut state,
+            &mut board,
+            &mut show_keybinds,
+            &mut mv,
+            &mut debug,
+            &mut live,
+            &mut fault,
+            &mut session,
+        )
+        .await
+        {
+            break;
+        };
+
+        if debug {
+            debug_state(&mut engine, &state, &board, &mut pulse, &show_keybinds, &mv);
+            session.pause();
+            if live {
+                engine.print_fbg(
+                    engine.get_width() as i32 - 33,
+                    engine.get_height() as i32 - 3,
+                    "LIVE",
+                    HIGHLIGHT,
+                    Color::Reset,
+                );
+            }
+            if let Some(err) = &fault {
+                engine.print_fbg(
+                    3,
+                    engine.get_height() as i32 - 3,
+                    &err.to_string(),
+                    HIGHLIGHT,
+                    Color::Reset,
+                );
+            }
+        } else {
+            // Drop into the debugger on the faulting instruction
+            if graphics::rewind_held() {
+                session.rewind_frame(&mut state, &mut board);
+            } else if let Err(err) = running_state(&mut engine, &mut state, &mut board, &mut session) {
+                fault = Some(err);
+                debug = true;
+                mv = 0;
+            }
+            engine.print(0, 0, &format!("{}", engine.frame_count));
+            if graphics::graphics(&mut board).await {
+                debug = true;
+            }
+            match graphics::slot_key() {
+                Some(SlotKey::Load) => {
+                    session.load(&mut state, &mut board);
+                }
+                Some(key) => {
+                    session.slots.handle(key, &mut state, &mut board);
+                }
+                None => (),
+            }
+        }
+
+        if let (None, Some(msg)) = (&fault, &session.slots.message) {
+            engine.print_fbg(3, engine.get_height() as i32 - 3, msg, DARKENAB, Color::Reset);
+        }
+        if let Some(playback) = &session.playback {
+            engine.print_fbg(10, 0, &playback.status(&state), HIGHLIGHT, Color::Reset);
+        }
+
+        engine.draw();
+    }
+
+    session.finish();
+}
+
+fn debug_state(
+    engine: &mut ConsoleEngine,
+    state: &State,
+    board: &Board,
+    pulse: &mut f32,
+    show_keybinds: &bool,
+    mv: &i32,
+) {
+    engine.rect_border(
+        1,
+        1,
+        engine.get_width() as i32 - 2,
+        engine.get_height() as i32 - 2,
+        BorderStyle::new_double().with_colors(DULL, Color::Reset),
+    );
+
+    
+    engine.print_screen(
+        3,
+        2,
+        &disass(state, board, engine.get_height(), engine.get_width(), mv),
+    );
+
+    engine.print_screen(79, engine.get_height() as i32 / 2 - 6, &pulse_anim(pulse));
+    *pulse += 0.1;
+
+
+    if *show_keybinds {
+        engine.print_screen(engine.get_width() as i32 - 26, 2, &keybinds());
+    }
+
+    engine.print_fbg(
+        engine.get_width() as i32 - 19,
+        engine.get_height() as i32 - 3,
+        "keybinds: (h)elp",
+        DARKENAB,
+        Color::Reset,
+    );
+}
+
+fn running_state(
+    engine: &mut ConsoleEngine,
+    state: &mut State,
+    board: &mut Board,
+    session: &mut Session,
+) -> Result<(), CpuError> {
+    engine.fill_rect(
+        1,
+        1,
+        engine.get_width() as i32 - 2,
+        engine.get_height() as i32 - 2,
+        pixel::pxl_bg(' ', DISABLED),
+    );
+    engine.rect_border(
+        1,
+        1,
+        engine.get_width() as i32 - 2,
+        engine.get_height() as i32 - 2,
+        BorderStyle::new_double().with_colors(NORMAL, Color::Reset),
+    );
+    engine.fill_rect(
+        engine.get_width() as i32 / 2 - 20,
+        engine.get_height() as i32 / 2 - 2,
+        engine.get_width() as i32 / 2 + 18,
+        engine.get_height() as i32 / 2 + 2,
+        pixel::pxl_bg(' ', Color::Reset),
+    );
+    engine.rect_border(
+        engine.get_width() as i32 / 2 - 20,
+        engine.get_height() as i32 / 2 - 2,
+        engine.get_width() as i32 / 2 + 18,
+        engine.get_height() as i32 / 2 + 2,
+        BorderStyle::new_double().with_colors(NORMAL, Color::Reset),
+    );
+    engine.print_fbg(
+        engine.get_width() as i32 / 2 - 18,
+        engine.get_height() as i32 / 2,
+        "Running - (d)ebug on game to return",
+        NORMAL,
+        Color::Reset,
+    );
+
+    engine.print_screen(3, 3, &display_ports(&board.io));
+
+    session.run_due(state, board)
+}
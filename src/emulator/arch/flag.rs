@@ -1,55 +1,75 @@
+/// Flags kept in the 8080 PSW layout `S Z 0 AC 0 P 1 CY`, so the register
+/// can be pushed and popped as is. The 8085 uses the spare bits for its
+/// undocumented flags: `S Z K AC 0 P V CY`, the Z80 for `S Z Y H X P/V N C`.
+#[derive(Clone, Copy)]
 pub struct Flag {
     pub reg: u8,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 pub enum FlagType {
     Z,
     S,
     P,
     CY,
     AC,
-    PAD,
+    /// 8085 signed overflow
+    V,
+    /// 8085 INX/DCX wrap and sign underflow indicator
+    K,
+    /// Z80 subtract flag, used by DAA
+    N,
 }
 
 impl Flag {
+    /// Bits that always read as 1 in the PSW
+    pub const FIXED: u8 = 0b0000_0010;
+    /// Bits backed by a flag
+    pub const MASK: u8 = 0b1101_0101;
+
     pub fn new() -> Self {
-        Flag { reg: 0 }
+        Flag { reg: Self::FIXED }
     }
 
-    pub fn get(&self, f: FlagType) -> u8 {
+    fn bit(f: FlagType) -> u8 {
         use FlagType::*;
         match f {
-            Z => (self.reg >> 7) & 0b1,
-            S => (self.reg >> 6) & 0b1,
-            P => (self.reg >> 5) & 0b1,
-            CY => (self.reg >> 4) & 0b1,
-            AC => (self.reg >> 3) & 0b1,
-            PAD => (self.reg) & 0b111,
+            S => 7,
+            Z => 6,
+            K => 5,
+            AC => 4,
+            P => 2,
+            V | N => 1,
+            CY => 0,
         }
     }
 
+    pub fn get(&self, f: FlagType) -> u8 {
+        (self.reg >> Self::bit(f)) & 0b1
+    }
+
     pub fn set(&mut self, f: FlagType) {
-        use FlagType::*;
-        match f {
-            Z => self.reg |= 1 << 7,
-            S => self.reg |= 1 << 6,
-            P => self.reg |= 1 << 5,
-            CY => self.reg |= 1 << 4,
-            AC => self.reg |= 1 << 3,
-            PAD => self.reg |= 0b111,
-        }
+        self.reg |= 1 << Self::bit(f);
     }
 
     pub fn unset(&mut self, f: FlagType) {
-        use FlagType::*;
-        match f {
-            Z => self.reg &= !(1 << 7),
-            S => self.reg &= !(1 << 6),
-            P => self.reg &= !(1 << 5),
-            CY => self.reg &= !(1 << 4),
-            AC => self.reg &= !(1 << 3),
-            PAD => self.reg &= 0b000,
-        }
+        self.reg &= !(1 << Self::bit(f));
+    }
+
+    /// Loads a PSW byte (e.g. from POP PSW), forcing the fixed bits
+    pub fn set_psw(&mut self, psw: u8) {
+        self.reg = (psw & Self::MASK) | Self::FIXED;
+    }
+
+    /// Loads a PSW byte on the 8085, where only bit 3 is unused
+    pub fn set_psw_8085(&mut self, psw: u8) {
+        self.reg = psw & !0b0000_1000;
+    }
+}
+
+impl Default for Flag {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -114,4 +134,22 @@ mod tests {
         assert_eq!(flag.get(CY), 0);
         assert_eq!(flag.get(AC), 0);
     }
+
+    #[test]
+    fn psw_layout() {
+        let mut flag = Flag::new();
+        assert_eq!(flag.reg, 0b0000_0010);
+
+        flag.set(S);
+        flag.set(Z);
+        flag.set(AC);
+        flag.set(P);
+        flag.set(CY);
+        assert_eq!(flag.reg, 0b1101_0111);
+
+        flag.set_psw(0xff);
+        assert_eq!(flag.reg, 0b1101_0111);
+        flag.set_psw(0x00);
+        assert_eq!(flag.reg, 0b0000_0010);
+    }
 }
@@ -0,0 +1,240 @@
+use super::instruction::{decode as decode_8080, Instruction, Operand, RegPair};
+use super::opcodes::Opcode;
+use super::timing;
+
+/// Restart address of the TRAP input
+pub const TRAP_VECTOR: u16 = 0x24;
+/// Restart address of RSTV, taken when V is set
+pub const RSTV_VECTOR: u16 = 0x40;
+
+/// Interrupt inputs the 8085 has on top of INTR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    /// Non-maskable, ignores the interrupt enable
+    Trap,
+    /// Edge triggered into a flip-flop that SIM can reset
+    Rst7_5,
+    Rst6_5,
+    Rst5_5,
+}
+
+impl Input {
+    pub fn vector(&self) -> u16 {
+        match self {
+            Input::Trap => TRAP_VECTOR,
+            Input::Rst7_5 => 0x3c,
+            Input::Rst6_5 => 0x34,
+            Input::Rst5_5 => 0x2c,
+        }
+    }
+
+    /// Bit of the SIM mask that blocks this input
+    pub fn mask(&self) -> u8 {
+        match self {
+            Input::Trap => 0,
+            Input::Rst7_5 => 0b100,
+            Input::Rst6_5 => 0b010,
+            Input::Rst5_5 => 0b001,
+        }
+    }
+}
+
+/// Interrupt and serial pins of the 8085
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pins {
+    /// TRAP latched until it is taken
+    pub trap: bool,
+    /// RST 7.5 flip-flop, set on a rising edge of the pin
+    pub rst7_5: bool,
+    pub rst7_5_line: bool,
+    /// RST 6.5 and 5.5 are level sensitive
+    pub rst6_5: bool,
+    pub rst5_5: bool,
+    /// SIM interrupt masks: bit 0 RST 5.5, bit 1 RST 6.5, bit 2 RST 7.5
+    pub mask: u8,
+    /// Serial input data, read by RIM
+    pub sid: bool,
+    /// Serial output data, written by SIM
+    pub sod: bool,
+    /// Interrupt enable before the last TRAP, read back by the next RIM
+    pub trap_ie: Option<bool>,
+}
+
+impl Pins {
+    pub fn set_input(&mut self, input: Input, level: bool) {
+        match input {
+            Input::Trap => self.trap |= level,
+            Input::Rst7_5 => {
+                self.rst7_5 |= level && !self.rst7_5_line;
+                self.rst7_5_line = level;
+            }
+            Input::Rst6_5 => self.rst6_5 = level,
+            Input::Rst5_5 => self.rst5_5 = level,
+        }
+    }
+
+    /// Highest priority input that is asking for service and not masked.
+    /// Only TRAP gets through while interrupts are disabled.
+    pub fn pending(&self, enabled: bool) -> Option<Input> {
+        let unmasked = |input: Input| self.mask & input.mask() == 0;
+
+        if self.trap {
+            Some(Input::Trap)
+        } else if !enabled {
+            None
+        } else if self.rst7_5 && unmasked(Input::Rst7_5) {
+            Some(Input::Rst7_5)
+        } else if self.rst6_5 && unmasked(Input::Rst6_5) {
+            Some(Input::Rst6_5)
+        } else if self.rst5_5 && unmasked(Input::Rst5_5) {
+            Some(Input::Rst5_5)
+        } else {
+            None
+        }
+    }
+
+    /// RIM: SID, pending 7.5 6.5 5.5, IE, masks 7.5 6.5 5.5
+    pub fn rim(&mut self, enable: u8) -> u8 {
+        let ie = self.trap_ie.take().map_or(enable, u8::from);
+        (self.sid as u8) << 7
+            | (self.rst7_5 as u8) << 6
+            | (self.rst6_5 as u8) << 5
+            | (self.rst5_5 as u8) << 4
+            | ie << 3
+            | self.mask
+    }
+
+    /// SIM: SOD, SOD enable, -, reset 7.5, mask set enable, masks 7.5 6.5 5.5
+    pub fn sim(&mut self, a: u8) {
+        if a & 0b0000_1000 != 0 {
+            self.mask = a & 0b111;
+        }
+        if a & 0b0001_0000 != 0 {
+            self.rst7_5 = false;
+        }
+        if a & 0b0100_0000 != 0 {
+            self.sod = a & 0b1000_0000 != 0;
+        }
+    }
+}
+
+/// Decodes an opcode as the 8085 sees it. Only the twelve opcodes the
+/// 8080 leaves undocumented differ.
+pub const fn convert(n: u8) -> Opcode {
+    match n {
+        0x08 => Opcode::DSUB,
+        0x10 => Opcode::ARHL,
+        0x18 => Opcode::RDEL,
+        0x20 => Opcode::RIM,
+        0x28 => Opcode::LDHI,
+        0x30 => Opcode::SIM,
+        0x38 => Opcode::LDSI,
+        0xcb => Opcode::RSTV,
+        0xd9 => Opcode::SHLX,
+        0xdd => Opcode::JNK,
+        0xed => Opcode::LHLX,
+        0xfd => Opcode::JK,
+        _ => Opcode::convert(n),
+    }
+}
+
+pub const fn length(opcode: u8) -> u8 {
+    match convert(opcode) {
+        Opcode::LDHI | Opcode::LDSI => 2,
+        Opcode::JNK | Opcode::JK => 3,
+        Opcode::RSTV | Opcode::SHLX | Opcode::LHLX => 1,
+        _ => Opcode::length(opcode),
+    }
+}
+
+/// 8085 T-states, the not-taken count for conditional instructions. Unlike
+/// the 8080, conditional jumps are also shorter when not taken.
+pub const fn cycles(opcode: u8) -> u8 {
+    match opcode {
+        0x76 => 5,
+        0x40..=0xbf if opcode & 0b111 == 6 || (opcode < 0x80 && opcode & 0b11_1000 == 0b11_0000) => 7,
+        0x40..=0xbf => 4,
+
+        0x08 | 0x18 | 0x28 | 0x38 => 10,
+        0x10 => 7,
+        0x34..=0x36 => 10,
+        0x00..=0x3f => match opcode & 0b1111 {
+            0x3 | 0xb => 6,
+            0x4 | 0x5 | 0xc | 0xd => 4,
+            0x1 | 0x9 => 10,
+            _ => Opcode::cycles(opcode),
+        },
+
+        0xcb => 6,
+        0xcd => 18,
+        0xd9 | 0xed => 10,
+        0xdd | 0xfd => 7,
+        0xe9 | 0xf9 => 6,
+        0xe3 => 16,
+        _ => match opcode & 0b111 {
+            0b000 => 6,
+            0b010 => 7,
+            0b100 => 9,
+            0b111 => 12,
+            _ if opcode & 0b1111 == 0x5 => 12,
+            _ => Opcode::cycles(opcode),
+        },
+    }
+}
+
+pub const fn cycles_taken(opcode: u8) -> u8 {
+    match opcode {
+        0xc0..=0xff => match opcode & 0b111 {
+            0b000 => 12,
+            0b010 => 10,
+            0b100 => 18,
+            _ => match opcode {
+                0xcb => 12,
+                0xdd | 0xfd => 10,
+                _ => cycles(opcode),
+            },
+        },
+        _ => cycles(opcode),
+    }
+}
+
+/// Opcodes Intel never documented for the 8085. RIM and SIM are official.
+pub const fn is_undocumented(opcode: u8) -> bool {
+    Opcode::is_undocumented(opcode) && opcode != 0x20 && opcode != 0x30
+}
+
+/// `instruction::decode` for the 8085
+pub fn decode(mem: &[u8], adr: u16) -> Instruction {
+    let mut instr = decode_8080(mem, adr);
+    let opcode = instr.opcode;
+    let d8 = instr.d8();
+    let d16 = instr.d16();
+
+    use Operand::*;
+    let name = match opcode {
+        0x08 => Some(("DSUB", [None, None])),
+        0x10 => Some(("ARHL", [None, None])),
+        0x18 => Some(("RDEL", [None, None])),
+        0x20 => Some(("RIM", [None, None])),
+        0x28 => Some(("LDHI", [Some(Imm8(d8)), None])),
+        0x30 => Some(("SIM", [None, None])),
+        0x38 => Some(("LDSI", [Some(Imm8(d8)), None])),
+        0xcb => Some(("RSTV", [None, None])),
+        0xd9 => Some(("SHLX", [Some(Pair(RegPair::D)), None])),
+        0xdd => Some(("JNK", [Some(Addr(d16)), None])),
+        0xed => Some(("LHLX", [Some(Pair(RegPair::D)), None])),
+        0xfd => Some(("JK", [Some(Addr(d16)), None])),
+        _ => None,
+    };
+    if let Some((mnemonic, operands)) = name {
+        instr.mnemonic = mnemonic;
+        instr.operands = operands;
+    }
+
+    instr.op = convert(opcode);
+    instr.length = timing::I8085.length[opcode as usize];
+    instr.cycles = timing::I8085.cycles[opcode as usize];
+    instr.cycles_taken = timing::I8085.cycles_taken[opcode as usize];
+    instr.undocumented = is_undocumented(opcode);
+    instr
+}
@@ -0,0 +1,351 @@
+use std::fmt;
+
+use super::i8085;
+use super::opcodes::Opcode;
+use super::state::Cpu;
+use super::timing;
+use super::z80;
+use crate::emulator::utils::join_bytes;
+
+/// 8-bit register as encoded in the 3-bit register field. `M` is the
+/// memory byte addressed by HL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    M,
+    A,
+}
+
+impl Register {
+    pub fn from_bits(bits: u8) -> Register {
+        match bits & 0b111 {
+            0 => Register::B,
+            1 => Register::C,
+            2 => Register::D,
+            3 => Register::E,
+            4 => Register::H,
+            5 => Register::L,
+            6 => Register::M,
+            _ => Register::A,
+        }
+    }
+}
+
+/// Register pair as encoded in the 2-bit pair field. Stack instructions
+/// use `PSW` where the others use `SP`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum RegPair {
+    B,
+    D,
+    H,
+    SP,
+    PSW,
+}
+
+impl RegPair {
+    pub fn from_bits(bits: u8, stack: bool) -> RegPair {
+        match bits & 0b11 {
+            0 => RegPair::B,
+            1 => RegPair::D,
+            2 => RegPair::H,
+            _ if stack => RegPair::PSW,
+            _ => RegPair::SP,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Reg(Register),
+    Pair(RegPair),
+    Imm8(u8),
+    Imm16(u16),
+    /// Memory address of a jump, call or direct load/store
+    Addr(u16),
+    Port(u8),
+    /// RST vector number
+    Rst(u8),
+    /// Fixed operand text, the Z80's register, flag and condition names
+    Name(&'static str),
+    /// Z80 (IX+d) or (IY+d)
+    Indexed(&'static str, i8),
+    /// Z80 memory operand (nn)
+    Mem(u16),
+    /// Z80 port operand (n)
+    Io(u8),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction {
+    pub opcode: u8,
+    pub op: Opcode,
+    pub mnemonic: &'static str,
+    pub operands: [Option<Operand>; 2],
+    /// Raw instruction bytes, only the first `length` are meaningful
+    pub bytes: [u8; 4],
+    pub length: u8,
+    /// T-states, the not-taken count for conditional calls and returns
+    pub cycles: u8,
+    pub cycles_taken: u8,
+    pub undocumented: bool,
+}
+
+const ALU: [&str; 8] = ["ADD", "ADC", "SUB", "SBB", "ANA", "XRA", "ORA", "CMP"];
+const ALU_IMM: [&str; 8] = ["ADI", "ACI", "SUI", "SBI", "ANI", "XRI", "ORI", "CPI"];
+const ROTATE: [&str; 8] = ["RLC", "RRC", "RAL", "RAR", "DAA", "CMA", "STC", "CMC"];
+const RET_CC: [&str; 8] = ["RNZ", "RZ", "RNC", "RC", "RPO", "RPE", "RP", "RM"];
+const JMP_CC: [&str; 8] = ["JNZ", "JZ", "JNC", "JC", "JPO", "JPE", "JP", "JM"];
+const CALL_CC: [&str; 8] = ["CNZ", "CZ", "CNC", "CC", "CPO", "CPE", "CP", "CM"];
+
+/// Decodes the instruction at `adr`. Operand bytes past the end of
+/// memory wrap around to the start of the address space.
+pub fn decode(mem: &[u8], adr: u16) -> Instruction {
+    let fetch = |n: u16| {
+        mem.get(adr.wrapping_add(n) as usize)
+            .copied()
+            .unwrap_or(0)
+    };
+    let bytes = [fetch(0), fetch(1), fetch(2), fetch(3)];
+    let opcode = bytes[0];
+
+    let d8 = bytes[1];
+    let d16 = join_bytes(bytes[2], bytes[1]);
+    let ddd = Register::from_bits(opcode >> 3);
+    let sss = Register::from_bits(opcode);
+    let rp = RegPair::from_bits(opcode >> 4, false);
+    let rp_stack = RegPair::from_bits(opcode >> 4, true);
+    let field = ((opcode >> 3) & 0b111) as usize;
+
+    use Operand::*;
+    let (mnemonic, operands) = match opcode {
+        0x76 => ("HLT", [None, None]),
+        0x40..=0x7f => ("MOV", [Some(Reg(ddd)), Some(Reg(sss))]),
+        0x80..=0xbf => (ALU[field], [Some(Reg(sss)), None]),
+
+        0x02 | 0x12 => ("STAX", [Some(Pair(rp)), None]),
+        0x0a | 0x1a => ("LDAX", [Some(Pair(rp)), None]),
+        0x22 => ("SHLD", [Some(Addr(d16)), None]),
+        0x2a => ("LHLD", [Some(Addr(d16)), None]),
+        0x32 => ("STA", [Some(Addr(d16)), None]),
+        0x3a => ("LDA", [Some(Addr(d16)), None]),
+        0x00..=0x3f => match opcode & 0b1111 {
+            0x1 => ("LXI", [Some(Pair(rp)), Some(Imm16(d16))]),
+            0x3 => ("INX", [Some(Pair(rp)), None]),
+            0x9 => ("DAD", [Some(Pair(rp)), None]),
+            0xb => ("DCX", [Some(Pair(rp)), None]),
+            _ => match opcode & 0b111 {
+                0b100 => ("INR", [Some(Reg(ddd)), None]),
+                0b101 => ("DCR", [Some(Reg(ddd)), None]),
+                0b110 => ("MVI", [Some(Reg(ddd)), Some(Imm8(d8))]),
+                0b111 => (ROTATE[field], [None, None]),
+                _ => ("NOP", [None, None]),
+            },
+        },
+
+        0xc3 | 0xcb => ("JMP", [Some(Addr(d16)), None]),
+        0xc9 | 0xd9 => ("RET", [None, None]),
+        0xcd | 0xdd | 0xed | 0xfd => ("CALL", [Some(Addr(d16)), None]),
+        0xd3 => ("OUT", [Some(Port(d8)), None]),
+        0xdb => ("IN", [Some(Port(d8)), None]),
+        0xe3 => ("XTHL", [None, None]),
+        0xe9 => ("PCHL", [None, None]),
+        0xeb => ("XCHG", [None, None]),
+        0xf3 => ("DI", [None, None]),
+        0xf9 => ("SPHL", [None, None]),
+        0xfb => ("EI", [None, None]),
+        _ => match opcode & 0b111 {
+            0b000 => (RET_CC[field], [None, None]),
+            0b010 => (JMP_CC[field], [Some(Addr(d16)), None]),
+            0b100 => (CALL_CC[field], [Some(Addr(d16)), None]),
+            0b110 => (ALU_IMM[field], [Some(Imm8(d8)), None]),
+            0b111 => ("RST", [Some(Rst(field as u8)), None]),
+            _ if opcode & 0b1111 == 0x1 => ("POP", [Some(Pair(rp_stack)), None]),
+            _ => ("PUSH", [Some(Pair(rp_stack)), None]),
+        },
+    };
+
+    Instruction {
+        opcode,
+        op: Opcode::convert(opcode),
+        mnemonic,
+        operands,
+        bytes,
+        length: timing::I8080.length[opcode as usize],
+        cycles: timing::I8080.cycles[opcode as usize],
+        cycles_taken: timing::I8080.cycles_taken[opcode as usize],
+        undocumented: Opcode::is_undocumented(opcode),
+    }
+}
+
+/// Decodes the instruction at `adr` as `cpu` would run it
+pub fn decode_as(cpu: Cpu, mem: &[u8], adr: u16) -> Instruction {
+    match cpu {
+        Cpu::I8080 => decode(mem, adr),
+        Cpu::I8085 => i8085::decode(mem, adr),
+        Cpu::Z80 => z80::decode(mem, adr),
+    }
+}
+
+impl Instruction {
+    /// Immediate byte following the opcode
+    pub fn d8(&self) -> u8 {
+        self.bytes[1]
+    }
+
+    /// Immediate word or address following the opcode
+    pub fn d16(&self) -> u16 {
+        join_bytes(self.bytes[2], self.bytes[1])
+    }
+
+    pub fn is_undocumented(&self) -> bool {
+        self.undocumented
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl fmt::Display for RegPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Reg(r) => write!(f, "{}", r),
+            Operand::Pair(rp) => write!(f, "{}", rp),
+            Operand::Imm8(n) | Operand::Port(n) => write!(f, "{:02x}", n),
+            Operand::Imm16(n) | Operand::Addr(n) => write!(f, "{:04x}", n),
+            Operand::Rst(n) => write!(f, "{}", n),
+            Operand::Name(name) => write!(f, "{}", name),
+            Operand::Indexed(reg, d) if d < 0 => write!(f, "({}-{:02x})", reg, d.unsigned_abs()),
+            Operand::Indexed(reg, d) => write!(f, "({}+{:02x})", reg, d),
+            Operand::Mem(n) => write!(f, "({:04x})", n),
+            Operand::Io(n) => write!(f, "({:02x})", n),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+        match self.operands {
+            [Some(a), Some(b)] => write!(f, " {},{}", a, b),
+            [Some(a), None] => write!(f, " {}", a),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operands() {
+        let mem = [0x41, 0x31, 0x00, 0x24, 0xf5, 0xdb, 0x01, 0xd7, 0xc4, 0x34, 0x12];
+        let mov = decode(&mem, 0);
+        assert_eq!(mov.mnemonic, "MOV");
+        assert_eq!(mov.operands, [Some(Operand::Reg(Register::B)), Some(Operand::Reg(Register::C))]);
+
+        let lxi = decode(&mem, 1);
+        assert_eq!(lxi.operands, [Some(Operand::Pair(RegPair::SP)), Some(Operand::Imm16(0x2400))]);
+        assert_eq!((lxi.length, lxi.cycles), (3, 10));
+        assert_eq!(lxi.to_string(), "LXI SP,2400");
+
+        assert_eq!(decode(&mem, 4).to_string(), "PUSH PSW");
+        assert_eq!(decode(&mem, 5).operands[0], Some(Operand::Port(0x01)));
+        assert_eq!(decode(&mem, 7).to_string(), "RST 2");
+
+        let cnz = decode(&mem, 8);
+        assert_eq!(cnz.operands[0], Some(Operand::Addr(0x1234)));
+        assert_eq!((cnz.cycles, cnz.cycles_taken), (11, 17));
+    }
+
+    #[test]
+    fn matches_opcode_table() {
+        #[rustfmt::skip]
+        const EXPECTED: [(&str, u8); 256] = [
+            ("NOP", 1), ("LXI B,0000", 3), ("STAX B", 1), ("INX B", 1),
+            ("INR B", 1), ("DCR B", 1), ("MVI B,00", 2), ("RLC", 1),
+            ("NOP", 1), ("DAD B", 1), ("LDAX B", 1), ("DCX B", 1),
+            ("INR C", 1), ("DCR C", 1), ("MVI C,00", 2), ("RRC", 1),
+            ("NOP", 1), ("LXI D,0000", 3), ("STAX D", 1), ("INX D", 1),
+            ("INR D", 1), ("DCR D", 1), ("MVI D,00", 2), ("RAL", 1),
+            ("NOP", 1), ("DAD D", 1), ("LDAX D", 1), ("DCX D", 1),
+            ("INR E", 1), ("DCR E", 1), ("MVI E,00", 2), ("RAR", 1),
+            ("NOP", 1), ("LXI H,0000", 3), ("SHLD 0000", 3), ("INX H", 1),
+            ("INR H", 1), ("DCR H", 1), ("MVI H,00", 2), ("DAA", 1),
+            ("NOP", 1), ("DAD H", 1), ("LHLD 0000", 3), ("DCX H", 1),
+            ("INR L", 1), ("DCR L", 1), ("MVI L,00", 2), ("CMA", 1),
+            ("NOP", 1), ("LXI SP,0000", 3), ("STA 0000", 3), ("INX SP", 1),
+            ("INR M", 1), ("DCR M", 1), ("MVI M,00", 2), ("STC", 1),
+            ("NOP", 1), ("DAD SP", 1), ("LDA 0000", 3), ("DCX SP", 1),
+            ("INR A", 1), ("DCR A", 1), ("MVI A,00", 2), ("CMC", 1),
+            ("MOV B,B", 1), ("MOV B,C", 1), ("MOV B,D", 1), ("MOV B,E", 1),
+            ("MOV B,H", 1), ("MOV B,L", 1), ("MOV B,M", 1), ("MOV B,A", 1),
+            ("MOV C,B", 1), ("MOV C,C", 1), ("MOV C,D", 1), ("MOV C,E", 1),
+            ("MOV C,H", 1), ("MOV C,L", 1), ("MOV C,M", 1), ("MOV C,A", 1),
+            ("MOV D,B", 1), ("MOV D,C", 1), ("MOV D,D", 1), ("MOV D,E", 1),
+            ("MOV D,H", 1), ("MOV D,L", 1), ("MOV D,M", 1), ("MOV D,A", 1),
+            ("MOV E,B", 1), ("MOV E,C", 1), ("MOV E,D", 1), ("MOV E,E", 1),
+            ("MOV E,H", 1), ("MOV E,L", 1), ("MOV E,M", 1), ("MOV E,A", 1),
+            ("MOV H,B", 1), ("MOV H,C", 1), ("MOV H,D", 1), ("MOV H,E", 1),
+            ("MOV H,H", 1), ("MOV H,L", 1), ("MOV H,M", 1), ("MOV H,A", 1),
+            ("MOV L,B", 1), ("MOV L,C", 1), ("MOV L,D", 1), ("MOV L,E", 1),
+            ("MOV L,H", 1), ("MOV L,L", 1), ("MOV L,M", 1), ("MOV L,A", 1),
+            ("MOV M,B", 1), ("MOV M,C", 1), ("MOV M,D", 1), ("MOV M,E", 1),
+            ("MOV M,H", 1), ("MOV M,L", 1), ("HLT", 1), ("MOV M,A", 1),
+            ("MOV A,B", 1), ("MOV A,C", 1), ("MOV A,D", 1), ("MOV A,E", 1),
+            ("MOV A,H", 1), ("MOV A,L", 1), ("MOV A,M", 1), ("MOV A,A", 1),
+            ("ADD B", 1), ("ADD C", 1), ("ADD D", 1), ("ADD E", 1),
+            ("ADD H", 1), ("ADD L", 1), ("ADD M", 1), ("ADD A", 1),
+            ("ADC B", 1), ("ADC C", 1), ("ADC D", 1), ("ADC E", 1),
+            ("ADC H", 1), ("ADC L", 1), ("ADC M", 1), ("ADC A", 1),
+            ("SUB B", 1), ("SUB C", 1), ("SUB D", 1), ("SUB E", 1),
+            ("SUB H", 1), ("SUB L", 1), ("SUB M", 1), ("SUB A", 1),
+            ("SBB B", 1), ("SBB C", 1), ("SBB D", 1), ("SBB E", 1),
+            ("SBB H", 1), ("SBB L", 1), ("SBB M", 1), ("SBB A", 1),
+            ("ANA B", 1), ("ANA C", 1), ("ANA D", 1), ("ANA E", 1),
+            ("ANA H", 1), ("ANA L", 1), ("ANA M", 1), ("ANA A", 1),
+            ("XRA B", 1), ("XRA C", 1), ("XRA D", 1), ("XRA E", 1),
+            ("XRA H", 1), ("XRA L", 1), ("XRA M", 1), ("XRA A", 1),
+            ("ORA B", 1), ("ORA C", 1), ("ORA D", 1), ("ORA E", 1),
+            ("ORA H", 1), ("ORA L", 1), ("ORA M", 1), ("ORA A", 1),
+            ("CMP B", 1), ("CMP C", 1), ("CMP D", 1), ("CMP E", 1),
+            ("CMP H", 1), ("CMP L", 1), ("CMP M", 1), ("CMP A", 1),
+            ("RNZ", 1), ("POP B", 1), ("JNZ 0000", 3), ("JMP 0000", 3),
+            ("CNZ 0000", 3), ("PUSH B", 1), ("ADI 00", 2), ("RST 0", 1),
+            ("RZ", 1), ("RET", 1), ("JZ 0000", 3), ("JMP 0000", 3),
+            ("CZ 0000", 3), ("CALL 0000", 3), ("ACI 00", 2), ("RST 1", 1),
+            ("RNC", 1), ("POP D", 1), ("JNC 0000", 3), ("OUT 00", 2),
+            ("CNC 0000", 3), ("PUSH D", 1), ("SUI 00", 2), ("RST 2", 1),
+            ("RC", 1), ("RET", 1), ("JC 0000", 3), ("IN 00", 2),
+            ("CC 0000", 3), ("CALL 0000", 3), ("SBI 00", 2), ("RST 3", 1),
+            ("RPO", 1), ("POP H", 1), ("JPO 0000", 3), ("XTHL", 1),
+            ("CPO 0000", 3), ("PUSH H", 1), ("ANI 00", 2), ("RST 4", 1),
+            ("RPE", 1), ("PCHL", 1), ("JPE 0000", 3), ("XCHG", 1),
+            ("CPE 0000", 3), ("CALL 0000", 3), ("XRI 00", 2), ("RST 5", 1),
+            ("RP", 1), ("POP PSW", 1), ("JP 0000", 3), ("DI", 1),
+            ("CP 0000", 3), ("PUSH PSW", 1), ("ORI 00", 2), ("RST 6", 1),
+            ("RM", 1), ("SPHL", 1), ("JM 0000", 3), ("EI", 1),
+            ("CM 0000", 3), ("CALL 0000", 3), ("CPI 00", 2), ("RST 7", 1),
+        ];
+
+        for (opcode, &(text, length)) in EXPECTED.iter().enumerate() {
+            let instr = decode(&[opcode as u8, 0, 0], 0);
+            assert_eq!((instr.to_string().as_str(), instr.length), (text, length), "{:02x}", opcode);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use std::fmt;
+
+/// Instruction a device places on the data bus when the CPU acknowledges
+/// an interrupt request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    /// RST n, a single byte call to n * 8
+    Rst(u8),
+    /// CALL to any address
+    Call(u16),
+}
+
+impl Interrupt {
+    pub fn vector(&self) -> u16 {
+        match *self {
+            Interrupt::Rst(n) => 0x08 * (n & 0b111) as u16,
+            Interrupt::Call(adr) => adr,
+        }
+    }
+
+    /// Opcode the device drives onto the data bus, which the Z80 takes as
+    /// the low byte of the vector table entry in IM 2
+    pub fn data(&self) -> u8 {
+        match *self {
+            Interrupt::Rst(n) => 0xc7 | (n & 0b111) << 3,
+            Interrupt::Call(_) => 0xcd,
+        }
+    }
+
+    /// T-states of the acknowledge cycle running the instruction
+    pub fn cycles(&self) -> u8 {
+        match self {
+            Interrupt::Rst(_) => 11,
+            Interrupt::Call(_) => 17,
+        }
+    }
+}
+
+impl fmt::Display for Interrupt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Interrupt::Rst(n) => write!(f, "RST {}", n & 0b111),
+            Interrupt::Call(adr) => write!(f, "CALL {:04x}", adr),
+        }
+    }
+}
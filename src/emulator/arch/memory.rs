@@ -0,0 +1,168 @@
+use super::state::MEM_SIZE;
+use crate::emulator::bus::Bus;
+
+/// Granularity of the memory map
+pub const PAGE_SIZE: usize = 0x400;
+const PAGES: usize = MEM_SIZE / PAGE_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Ram,
+    /// Reads return the backing memory, writes are rejected
+    Rom,
+    /// Reads return 0, writes are rejected
+    Unmapped,
+}
+
+/// What happens to a write the map rejects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WritePolicy {
+    /// Drop the write, as the hardware does
+    Ignore,
+    /// Drop the write and report it on stderr
+    Log,
+    /// Drop the write and stop the CPU with `CpuError::BusFault`
+    Trap,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Page {
+    /// Backing memory the page resolves to
+    base: u16,
+    access: Access,
+}
+
+/// Decodes CPU addresses into the backing 64K of memory in `PAGE_SIZE`
+/// blocks, so regions can be mirrored or made read-only.
+#[derive(Debug, Clone)]
+pub struct MemoryMap {
+    pages: [Page; PAGES],
+    pub policy: WritePolicy,
+}
+
+impl MemoryMap {
+    /// Every address is RAM backed by itself
+    pub fn flat() -> MemoryMap {
+        let mut map = MemoryMap {
+            pages: [Page { base: 0, access: Access::Ram }; PAGES],
+            policy: WritePolicy::Ignore,
+        };
+        map.map(0x0000, MEM_SIZE, 0x0000, Access::Ram);
+        map
+    }
+
+    /// Maps `len` bytes from `start` onto the backing memory at `base`.
+    /// Both addresses and the length must be multiples of `PAGE_SIZE`.
+    pub fn map(&mut self, start: u16, len: usize, base: u16, access: Access) -> &mut MemoryMap {
+        assert!(
+            (start as usize | base as usize | len) & (PAGE_SIZE - 1) == 0,
+            "memory map regions must be aligned to {:#x}",
+            PAGE_SIZE
+        );
+
+        let first = start as usize / PAGE_SIZE;
+        for i in 0..len / PAGE_SIZE {
+            self.pages[(first + i) % PAGES] = Page {
+                base: base.wrapping_add((i * PAGE_SIZE) as u16),
+                access,
+            };
+        }
+        self
+    }
+
+    pub fn ram(&mut self, start: u16, len: usize) -> &mut MemoryMap {
+        self.map(start, len, start, Access::Ram)
+    }
+
+    pub fn rom(&mut self, start: u16, len: usize) -> &mut MemoryMap {
+        self.map(start, len, start, Access::Rom)
+    }
+
+    /// Makes `len` bytes from `start` show whatever is mapped at `target`
+    pub fn mirror(&mut self, start: u16, len: usize, target: u16) -> &mut MemoryMap {
+        let first = target as usize / PAGE_SIZE;
+        for i in 0..len / PAGE_SIZE {
+            self.pages[(start as usize / PAGE_SIZE + i) % PAGES] = self.pages[(first + i) % PAGES];
+        }
+        self
+    }
+
+    pub fn access(&self, adr: u16) -> Access {
+        self.pages[adr as usize / PAGE_SIZE].access
+    }
+
+    /// Address in the backing memory that `adr` resolves to
+    pub fn translate(&self, adr: u16) -> u16 {
+        let page = &self.pages[adr as usize / PAGE_SIZE];
+        page.base | (adr & (PAGE_SIZE as u16 - 1))
+    }
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        Self::flat()
+    }
+}
+
+/// 64K of backing memory seen through a `MemoryMap`. On its own it is a
+/// bus with nothing on the IO ports.
+pub struct Memory {
+    pub data: Vec<u8>,
+    pub map: MemoryMap,
+    /// Address of a write rejected under `WritePolicy::Trap`
+    pub fault: Option<u16>,
+}
+
+impl Memory {
+    /// Flat RAM holding `data` from address 0
+    pub fn new(mut data: Vec<u8>) -> Memory {
+        // Every 16 bit address must land inside memory
+        data.resize(MEM_SIZE, 0);
+
+        Memory {
+            data,
+            map: MemoryMap::flat(),
+            fault: None,
+        }
+    }
+
+    pub fn read(&self, adr: u16) -> u8 {
+        match self.map.access(adr) {
+            Access::Unmapped => 0,
+            _ => self.data[self.map.translate(adr) as usize],
+        }
+    }
+
+    pub fn write(&mut self, adr: u16, val: u8) {
+        match self.map.access(adr) {
+            Access::Ram => self.data[self.map.translate(adr) as usize] = val,
+            access => match self.map.policy {
+                WritePolicy::Ignore => (),
+                WritePolicy::Log => {
+                    eprintln!("Ignored write of {:02x} to {:?} at {:#06x}", val, access, adr)
+                }
+                WritePolicy::Trap => self.fault = Some(adr),
+            },
+        }
+    }
+}
+
+impl Bus for Memory {
+    fn read(&mut self, adr: u16) -> u8 {
+        Memory::read(self, adr)
+    }
+
+    fn write(&mut self, adr: u16, val: u8) {
+        Memory::write(self, adr, val)
+    }
+
+    fn input(&mut self, _port: u8) -> u8 {
+        0
+    }
+
+    fn output(&mut self, _port: u8, _val: u8) {}
+
+    fn take_fault(&mut self) -> Option<u16> {
+        self.fault.take()
+    }
+}
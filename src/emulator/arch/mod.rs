@@ -1,3 +1,9 @@
 pub mod flag;
+pub mod i8085;
+pub mod instruction;
+pub mod interrupt;
+pub mod memory;
 pub mod opcodes;
 pub mod state;
+pub mod timing;
+pub mod z80;
@@ -6,10 +6,9 @@ impl fmt::Display for Opcode {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Opcode {
-    NIMP(u8),
-
     NOP,
     LXIB,
     STAXB,
@@ -285,10 +284,24 @@ pub enum Opcode {
     CM,
     CPI,
     RST7,
+
+    // 8085 only, see `i8085::convert`
+    RIM,
+    SIM,
+    DSUB,
+    ARHL,
+    RDEL,
+    LDHI,
+    LDSI,
+    RSTV,
+    SHLX,
+    LHLX,
+    JNK,
+    JK,
 }
 
 impl Opcode {
-    pub fn cycles(opcode: u8) -> u8 {
+    pub const fn length(opcode: u8) -> u8 {
         let op = Opcode::convert(opcode);
         match op {
             Self::LXIB => 3,
@@ -337,12 +350,93 @@ impl Opcode {
             Self::OUT => 2,
             Self::IN => 2,
 
-            // Self::NOP => 4,
             _ => 1,
         }
     }
 
-    pub fn convert(n: u8) -> Opcode {
+    /// T-states taken by an instruction. For conditional calls and returns
+    /// this is the count when the condition is not met, see `cycles_taken`.
+    pub const fn cycles(opcode: u8) -> u8 {
+        let op = Opcode::convert(opcode);
+        match op {
+            Self::XTHL => 18,
+            Self::CALL => 17,
+            Self::SHLD | Self::LHLD => 16,
+            Self::STA | Self::LDA => 13,
+
+            Self::CNZ | Self::CZ | Self::CNC | Self::CC
+            | Self::CPO | Self::CPE | Self::CP | Self::CM => 11,
+            Self::PUSHB | Self::PUSHD | Self::PUSHH | Self::PUSHPSW => 11,
+            Self::RST0 | Self::RST1 | Self::RST2 | Self::RST3
+            | Self::RST4 | Self::RST5 | Self::RST6 | Self::RST7 => 11,
+
+            Self::LXIB | Self::LXID | Self::LXIH | Self::LXISP => 10,
+            Self::DADB | Self::DADD | Self::DADH | Self::DADSP => 10,
+            Self::INRM | Self::DCRM | Self::MVIM => 10,
+            Self::JMP | Self::JNZ | Self::JZ | Self::JNC | Self::JC
+            | Self::JPO | Self::JPE | Self::JP | Self::JM => 10,
+            Self::POPB | Self::POPD | Self::POPH | Self::POPPSW => 10,
+            Self::RET | Self::IN | Self::OUT => 10,
+
+            Self::STAXB | Self::STAXD | Self::LDAXB | Self::LDAXD => 7,
+            Self::MVIB | Self::MVIC | Self::MVID | Self::MVIE
+            | Self::MVIH | Self::MVIL | Self::MVIA => 7,
+            Self::MOVBM | Self::MOVCM | Self::MOVDM | Self::MOVEM
+            | Self::MOVHM | Self::MOVLM | Self::MOVAM => 7,
+            Self::MOVMB | Self::MOVMC | Self::MOVMD | Self::MOVME
+            | Self::MOVMH | Self::MOVML | Self::MOVMA => 7,
+            Self::ADDM | Self::ADCM | Self::SUBM | Self::SBBM
+            | Self::ANAM | Self::XRAM | Self::ORAM | Self::CMPM => 7,
+            Self::ADI | Self::ACI | Self::SUI | Self::SBI
+            | Self::ANI | Self::XRI | Self::ORI | Self::CPI => 7,
+            Self::HLT => 7,
+
+            Self::INXB | Self::INXD | Self::INXH | Self::INXSP => 5,
+            Self::DCXB | Self::DCXD | Self::DCXH | Self::DCXSP => 5,
+            Self::INRB | Self::INRC | Self::INRD | Self::INRE
+            | Self::INRH | Self::INRL | Self::INRA => 5,
+            Self::DCRB | Self::DCRC | Self::DCRD | Self::DCRE
+            | Self::DCRH | Self::DCRL | Self::DCRA => 5,
+            Self::RNZ | Self::RZ | Self::RNC | Self::RC
+            | Self::RPO | Self::RPE | Self::RP | Self::RM => 5,
+            Self::PCHL | Self::SPHL => 5,
+
+            Self::MOVBB | Self::MOVBC | Self::MOVBD | Self::MOVBE | Self::MOVBH | Self::MOVBL | Self::MOVBA
+            | Self::MOVCB | Self::MOVCC | Self::MOVCD | Self::MOVCE | Self::MOVCH | Self::MOVCL | Self::MOVCA
+            | Self::MOVDB | Self::MOVDC | Self::MOVDD | Self::MOVDE | Self::MOVDH | Self::MOVDL | Self::MOVDA
+            | Self::MOVEB | Self::MOVEC | Self::MOVED | Self::MOVEE | Self::MOVEH | Self::MOVEL | Self::MOVEA
+            | Self::MOVHB | Self::MOVHC | Self::MOVHD | Self::MOVHE | Self::MOVHH | Self::MOVHL | Self::MOVHA
+            | Self::MOVLB | Self::MOVLC | Self::MOVLD | Self::MOVLE | Self::MOVLH | Self::MOVLL | Self::MOVLA
+            | Self::MOVAB | Self::MOVAC | Self::MOVAD | Self::MOVAE | Self::MOVAH | Self::MOVAL | Self::MOVAA => 5,
+
+            // NOP, register ALU ops, rotates, DAA, CMA, STC, CMC, XCHG, DI, EI
+            _ => 4,
+        }
+    }
+
+    /// T-states taken by a conditional call or return when its condition
+    /// is met. Equal to `cycles` for every other instruction.
+    pub const fn cycles_taken(opcode: u8) -> u8 {
+        let op = Opcode::convert(opcode);
+        match op {
+            Self::CNZ | Self::CZ | Self::CNC | Self::CC
+            | Self::CPO | Self::CPE | Self::CP | Self::CM => 17,
+            Self::RNZ | Self::RZ | Self::RNC | Self::RC
+            | Self::RPO | Self::RPE | Self::RP | Self::RM => 11,
+            _ => Opcode::cycles(opcode),
+        }
+    }
+
+    /// Opcodes outside the documented 8080 set. On silicon they alias
+    /// NOP, JMP, RET and CALL and `convert` decodes them as such.
+    pub const fn is_undocumented(opcode: u8) -> bool {
+        matches!(
+            opcode,
+            0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 | 0xcb | 0xd9 | 0xdd | 0xed | 0xfd
+        )
+    }
+
+    pub const fn convert(n: u8) -> Opcode {
         match n {
             0x00 => Opcode::NOP,
             0x01 => Opcode::LXIB,
@@ -380,7 +474,7 @@ impl Opcode {
             0x1e => Opcode::MVIE,
             0x1f => Opcode::RAR,
 
-            0x20 => Opcode::NIMP(n), // RIM - special
+            0x20 => Opcode::NOP, // RIM on the 8085
             0x21 => Opcode::LXIH,
             0x22 => Opcode::SHLD,
             0x23 => Opcode::INXH,
@@ -398,7 +492,7 @@ impl Opcode {
             0x2e => Opcode::MVIL,
             0x2f => Opcode::CMA,
 
-            0x30 => Opcode::NIMP(n), // SIM - special
+            0x30 => Opcode::NOP, // SIM on the 8085
             0x31 => Opcode::LXISP,
             0x32 => Opcode::STA,
             0x33 => Opcode::INXSP,
@@ -572,7 +666,7 @@ impl Opcode {
             0xc8 => Opcode::RZ,
             0xc9 => Opcode::RET,
             0xca => Opcode::JZ,
-            0xcb => Opcode::NOP,
+            0xcb => Opcode::JMP,
             0xcc => Opcode::CZ,
             0xcd => Opcode::CALL,
             0xce => Opcode::ACI,
@@ -588,11 +682,11 @@ impl Opcode {
             0xd7 => Opcode::RST2,
 
             0xd8 => Opcode::RC,
-            0xd9 => Opcode::NOP,
+            0xd9 => Opcode::RET,
             0xda => Opcode::JC,
             0xdb => Opcode::IN, // IN - special
             0xdc => Opcode::CC,
-            0xdd => Opcode::NOP,
+            0xdd => Opcode::CALL,
             0xde => Opcode::SBI,
             0xdf => Opcode::RST3,
             //
@@ -610,7 +704,7 @@ impl Opcode {
             0xea => Opcode::JPE,
             0xeb => Opcode::XCHG,
             0xec => Opcode::CPE,
-            0xed => Opcode::NOP,
+            0xed => Opcode::CALL,
             0xee => Opcode::XRI,
             0xef => Opcode::RST5,
             //
@@ -628,7 +722,7 @@ impl Opcode {
             0xfa => Opcode::JM,
             0xfb => Opcode::EI, // EI - special
             0xfc => Opcode::CM,
-            0xfd => Opcode::NOP,
+            0xfd => Opcode::CALL,
             0xfe => Opcode::CPI,
             0xff => Opcode::RST7,
         }
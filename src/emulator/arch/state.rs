@@ -1,6 +1,30 @@
 use super::flag::Flag;
+use super::i8085::Pins;
+use super::instruction::{RegPair, Register};
+use super::interrupt::Interrupt;
+use super::z80;
+use crate::emulator::bus::Bus;
+use crate::emulator::utils::{join_bytes, split_bytes};
 
+/// Size of the 8080 address space
+pub const MEM_SIZE: usize = 0x10000;
+
+/// CPU model the core emulates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Cpu {
+    #[default]
+    I8080,
+    /// 8085: RIM/SIM, extra interrupt inputs, serial pins, undocumented
+    /// instructions and V/K flags, and its own instruction timings
+    I8085,
+    /// Z80: alternate registers, IX/IY, the CB/ED/DD/FD prefixed groups,
+    /// interrupt modes and its own flags and timings
+    Z80,
+}
+
+#[derive(Clone)]
 pub struct State {
+    pub cpu: Cpu,
     pub a: u8,
     pub b: u8,
     pub c: u8,
@@ -10,14 +34,34 @@ pub struct State {
     pub l: u8,
     pub sp: u16,
     pub pc: u16,
-    pub mem: Vec<u8>,
     pub flags: Flag,
+    /// Interrupt enable flip-flop
     pub enable: u8,
+    /// Set by EI so interrupts are only accepted after the next instruction
+    pub ei_delay: bool,
+    /// Request latched on the INTR line until the CPU acknowledges it
+    pub interrupt: Option<Interrupt>,
+    pub cycles: u64,
+    /// Set by HLT, cleared when an interrupt is taken
+    pub halted: bool,
+    /// Report undocumented opcodes as errors instead of running them
+    pub trap_undocumented: bool,
+    /// 8085 interrupt inputs and serial pins, unused on the 8080
+    pub pins: Pins,
+    /// Z80 registers, unused on the 8080
+    pub z80: z80::Registers,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl State {
-    pub fn new<'a>(mem: Vec<u8>) -> State {
+    pub fn new() -> State {
         State {
+            cpu: Cpu::I8080,
             a: 0,
             b: 0,
             c: 0,
@@ -27,9 +71,95 @@ impl State {
             l: 0,
             sp: 0x2400,
             pc: 0,
-            mem,
             flags: Flag::new(),
             enable: 0,
+            ei_delay: false,
+            interrupt: None,
+            cycles: 0,
+            halted: false,
+            trap_undocumented: false,
+            pins: Pins::default(),
+            z80: z80::Registers::default(),
+        }
+    }
+
+    pub fn with_cpu(cpu: Cpu) -> State {
+        let mut state = State::new();
+        state.cpu = cpu;
+        if cpu != Cpu::I8080 {
+            // Bit 1 is the V or N flag rather than a fixed 1
+            state.flags.reg = 0;
+        }
+        state
+    }
+
+    pub fn push(&mut self, bus: &mut impl Bus, val: u16) {
+        let (hi, lo) = split_bytes(val);
+        self.sp = self.sp.wrapping_sub(1);
+        bus.write(self.sp, hi);
+        self.sp = self.sp.wrapping_sub(1);
+        bus.write(self.sp, lo);
+    }
+
+    pub fn pop(&mut self, bus: &mut impl Bus) -> u16 {
+        let lo = bus.read(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        let hi = bus.read(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        join_bytes(hi, lo)
+    }
+
+    pub fn reg(&self, bus: &mut impl Bus, r: Register) -> u8 {
+        match r {
+            Register::B => self.b,
+            Register::C => self.c,
+            Register::D => self.d,
+            Register::E => self.e,
+            Register::H => self.h,
+            Register::L => self.l,
+            Register::M => bus.read(join_bytes(self.h, self.l)),
+            Register::A => self.a,
+        }
+    }
+
+    pub fn set_reg(&mut self, bus: &mut impl Bus, r: Register, val: u8) {
+        match r {
+            Register::B => self.b = val,
+            Register::C => self.c = val,
+            Register::D => self.d = val,
+            Register::E => self.e = val,
+            Register::H => self.h = val,
+            Register::L => self.l = val,
+            Register::M => bus.write(join_bytes(self.h, self.l), val),
+            Register::A => self.a = val,
+        }
+    }
+
+    pub fn pair(&self, rp: RegPair) -> u16 {
+        match rp {
+            RegPair::B => join_bytes(self.b, self.c),
+            RegPair::D => join_bytes(self.d, self.e),
+            RegPair::H => join_bytes(self.h, self.l),
+            RegPair::SP => self.sp,
+            RegPair::PSW => join_bytes(self.a, self.flags.reg),
+        }
+    }
+
+    pub fn set_pair(&mut self, rp: RegPair, val: u16) {
+        match rp {
+            RegPair::B => (self.b, self.c) = split_bytes(val),
+            RegPair::D => (self.d, self.e) = split_bytes(val),
+            RegPair::H => (self.h, self.l) = split_bytes(val),
+            RegPair::SP => self.sp = val,
+            RegPair::PSW => {
+                let (a, psw) = split_bytes(val);
+                self.a = a;
+                match self.cpu {
+                    Cpu::I8080 => self.flags.set_psw(psw),
+                    Cpu::I8085 => self.flags.set_psw_8085(psw),
+                    Cpu::Z80 => self.flags.reg = psw,
+                }
+            }
         }
     }
 }
@@ -0,0 +1,45 @@
+use super::i8085;
+use super::opcodes::Opcode;
+use super::state::Cpu;
+use super::z80;
+
+/// Length and T-states of each unprefixed opcode on one CPU. The
+/// disassembler and the executor both read these, so a listing always
+/// shows what the core will count.
+pub struct Timing {
+    pub length: [u8; 256],
+    /// T-states, the not-taken count for conditional calls and returns
+    pub cycles: [u8; 256],
+    pub cycles_taken: [u8; 256],
+}
+
+pub static I8080: Timing = Timing::new(Cpu::I8080);
+pub static I8085: Timing = Timing::new(Cpu::I8085);
+pub static Z80: Timing = Timing::new(Cpu::Z80);
+
+impl Timing {
+    const fn new(cpu: Cpu) -> Timing {
+        let mut timing = Timing { length: [0; 256], cycles: [0; 256], cycles_taken: [0; 256] };
+        let mut i = 0;
+        while i < 256 {
+            let op = i as u8;
+            (timing.length[i], timing.cycles[i], timing.cycles_taken[i]) = match cpu {
+                Cpu::I8080 => (Opcode::length(op), Opcode::cycles(op), Opcode::cycles_taken(op)),
+                Cpu::I8085 => (i8085::length(op), i8085::cycles(op), i8085::cycles_taken(op)),
+                Cpu::Z80 => (z80::length(op), z80::cycles(op), z80::cycles_taken(op)),
+            };
+            i += 1;
+        }
+        timing
+    }
+}
+
+impl Cpu {
+    pub fn timing(self) -> &'static Timing {
+        match self {
+            Cpu::I8080 => &I8080,
+            Cpu::I8085 => &I8085,
+            Cpu::Z80 => &Z80,
+        }
+    }
+}
@@ -0,0 +1,498 @@
+use super::instruction::{Instruction, Operand};
+use super::opcodes::Opcode;
+use super::timing;
+use crate::emulator::utils::join_bytes;
+
+/// Restart address of the NMI input
+pub const NMI_VECTOR: u16 = 0x66;
+/// Restart address of an interrupt in IM 1
+pub const IM1_VECTOR: u16 = 0x38;
+
+/// Index register a DD or FD prefix puts in place of HL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Index {
+    IX,
+    IY,
+}
+
+impl Index {
+    pub fn from_prefix(prefix: u8) -> Index {
+        match prefix {
+            0xdd => Index::IX,
+            _ => Index::IY,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Index::IX => "IX",
+            Index::IY => "IY",
+        }
+    }
+
+    fn indirect(&self) -> &'static str {
+        match self {
+            Index::IX => "(IX)",
+            Index::IY => "(IY)",
+        }
+    }
+
+    /// Undocumented halves standing in for H and L
+    fn half(&self, r: usize) -> &'static str {
+        match (self, r) {
+            (Index::IX, 4) => "IXH",
+            (Index::IX, _) => "IXL",
+            (Index::IY, 4) => "IYH",
+            (Index::IY, _) => "IYL",
+        }
+    }
+}
+
+/// Registers the Z80 has on top of the 8080's
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Registers {
+    /// Alternate set, swapped in by EX AF,AF' and EXX
+    pub af_: u16,
+    pub bc_: u16,
+    pub de_: u16,
+    pub hl_: u16,
+    pub ix: u16,
+    pub iy: u16,
+    /// High byte of the IM 2 vector table
+    pub i: u8,
+    /// Memory refresh counter, the low 7 bits count opcode fetches
+    pub r: u8,
+    /// Interrupt mode set by IM 0/1/2
+    pub im: u8,
+    /// Second interrupt flip-flop, keeps the enable across an NMI
+    pub iff2: bool,
+    /// NMI latched on its falling edge until it is taken
+    pub nmi: bool,
+}
+
+impl Registers {
+    pub fn index(&self, index: Index) -> u16 {
+        match index {
+            Index::IX => self.ix,
+            Index::IY => self.iy,
+        }
+    }
+
+    pub fn set_index(&mut self, index: Index, val: u16) {
+        match index {
+            Index::IX => self.ix = val,
+            Index::IY => self.iy = val,
+        }
+    }
+
+    /// Counts an opcode fetch, bit 7 of R is left as LD R,A set it
+    pub fn refresh(&mut self) {
+        self.r = (self.r & 0x80) | (self.r.wrapping_add(1) & 0x7f);
+    }
+}
+
+/// Length of an unprefixed opcode, a prefix counts as one byte
+pub const fn length(opcode: u8) -> u8 {
+    match opcode {
+        0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 => 2,
+        0xcb | 0xdd | 0xed | 0xfd => 1,
+        _ => Opcode::length(opcode),
+    }
+}
+
+/// Z80 T-states of an unprefixed opcode, the not-taken count for
+/// conditional instructions. A prefix byte on its own takes 4.
+pub const fn cycles(opcode: u8) -> u8 {
+    match opcode {
+        0x76 => 4,
+        0x40..=0xbf if opcode & 0b111 == 6 || (opcode < 0x80 && opcode & 0b11_1000 == 0b11_0000) => 7,
+        0x40..=0xbf => 4,
+
+        0x10 => 8,
+        0x18 => 12,
+        0x20 | 0x28 | 0x30 | 0x38 => 7,
+        0x22 | 0x2a => 16,
+        0x32 | 0x3a => 13,
+        0x34 | 0x35 => 11,
+        0x36 => 10,
+        0x00..=0x3f => match opcode & 0b1111 {
+            0x1 => 10,
+            0x2 | 0xa | 0x6 | 0xe => 7,
+            0x3 | 0xb => 6,
+            0x9 => 11,
+            _ => 4,
+        },
+
+        0xc3 | 0xc9 => 10,
+        0xcd => 17,
+        0xd3 | 0xdb => 11,
+        0xe3 => 19,
+        0xf9 => 6,
+        0xcb | 0xd9 | 0xdd | 0xe9 | 0xeb | 0xed | 0xf3 | 0xfb | 0xfd => 4,
+        _ => match opcode & 0b111 {
+            0b000 => 5,
+            0b101 | 0b111 => 11,
+            0b110 => 7,
+            _ => 10,
+        },
+    }
+}
+
+pub const fn cycles_taken(opcode: u8) -> u8 {
+    match opcode {
+        0x10 => 13,
+        0x20 | 0x28 | 0x30 | 0x38 => 12,
+        0xc0..=0xff if opcode & 0b111 == 0b000 => 11,
+        0xc0..=0xff if opcode & 0b111 == 0b100 => 17,
+        _ => cycles(opcode),
+    }
+}
+
+/// CB prefixed rotates, shifts and bit operations
+pub const fn cb_cycles(opcode: u8) -> u8 {
+    match (opcode & 0b111 == 6, opcode >> 6) {
+        (false, _) => 8,
+        (true, 1) => 12,
+        (true, _) => 15,
+    }
+}
+
+/// DD CB d op and FD CB d op
+pub const fn indexed_cb_cycles(opcode: u8) -> u8 {
+    match opcode >> 6 {
+        1 => 20,
+        _ => 23,
+    }
+}
+
+/// ED prefixed opcodes, the not-taken count for block repeats
+pub const fn ed_cycles(opcode: u8) -> u8 {
+    match opcode {
+        0x47 | 0x4f | 0x57 | 0x5f => 9,
+        0x67 | 0x6f => 18,
+        0x40..=0x7f => match opcode & 0b111 {
+            0 | 1 => 12,
+            2 => 15,
+            3 => 20,
+            5 => 14,
+            _ => 8,
+        },
+        0xa0..=0xbf if opcode & 0b100 == 0 => 16,
+        _ => 8,
+    }
+}
+
+/// ED prefixed opcodes when a block instruction repeats
+pub const fn ed_cycles_taken(opcode: u8) -> u8 {
+    match opcode {
+        0xb0..=0xbf if opcode & 0b100 == 0 => 21,
+        _ => ed_cycles(opcode),
+    }
+}
+
+/// Unprefixed opcodes reading or writing (HL), which a DD or FD prefix
+/// turns into (IX+d) or (IY+d)
+pub const fn uses_hl_memory(opcode: u8) -> bool {
+    match opcode {
+        0x34..=0x36 => true,
+        0x76 => false,
+        0x40..=0x7f => opcode & 0b111 == 6 || opcode & 0b11_1000 == 0b11_0000,
+        0x80..=0xbf => opcode & 0b111 == 6,
+        _ => false,
+    }
+}
+
+/// Unprefixed opcodes a DD or FD prefix changes in a documented way
+const fn uses_hl_pair(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        0x09 | 0x19 | 0x21 | 0x22 | 0x23 | 0x29 | 0x2a | 0x2b | 0x39 | 0xe1 | 0xe3 | 0xe5 | 0xe9 | 0xf9
+    ) || uses_hl_memory(opcode)
+}
+
+/// Unprefixed opcodes a DD or FD prefix does anything to
+pub fn takes_index(opcode: u8) -> bool {
+    uses_hl_pair(opcode) || uses_hl_bytes(opcode)
+}
+
+/// T-states of an unprefixed opcode run behind a DD or FD prefix
+pub const fn indexed_cycles(opcode: u8, taken: bool) -> u8 {
+    let base = if taken { cycles_taken(opcode) } else { cycles(opcode) };
+    // Adding the displacement takes 8 T-states, 5 overlap LD (IX+d),n's fetch
+    let disp = match opcode {
+        0x36 => 5,
+        _ if uses_hl_memory(opcode) => 8,
+        _ => 0,
+    };
+    base + 4 + disp
+}
+
+const R: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+const RP: [&str; 4] = ["BC", "DE", "HL", "SP"];
+const CC: [&str; 8] = ["NZ", "Z", "NC", "C", "PO", "PE", "P", "M"];
+const ALU: [&str; 8] = ["ADD", "ADC", "SUB", "SBC", "AND", "XOR", "OR", "CP"];
+const ROT: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SLL", "SRL"];
+const ROT_A: [&str; 8] = ["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"];
+const BITS: [&str; 8] = ["0", "1", "2", "3", "4", "5", "6", "7"];
+const IM: [&str; 8] = ["0", "0/1", "1", "2", "0", "0/1", "1", "2"];
+const BLOCK: [[&str; 4]; 4] = [
+    ["LDI", "CPI", "INI", "OUTI"],
+    ["LDD", "CPD", "IND", "OUTD"],
+    ["LDIR", "CPIR", "INIR", "OTIR"],
+    ["LDDR", "CPDR", "INDR", "OTDR"],
+];
+
+type Operands = [Option<Operand>; 2];
+
+/// Decodes the instruction at `adr` in Zilog syntax
+pub fn decode(mem: &[u8], adr: u16) -> Instruction {
+    let fetch = |n: u16| {
+        mem.get(adr.wrapping_add(n) as usize)
+            .copied()
+            .unwrap_or(0)
+    };
+    let bytes = [fetch(0), fetch(1), fetch(2), fetch(3)];
+    let opcode = bytes[0];
+
+    let (mnemonic, operands, length, undocumented, cycles) = match (opcode, bytes[1]) {
+        // A prefix followed by another prefix does nothing
+        (0xdd | 0xfd, 0xdd | 0xed | 0xfd) => ("NOP", [None, None], 1, true, (4, 4)),
+        (0xdd | 0xfd, 0xcb) => {
+            let index = Index::from_prefix(opcode);
+            let op = bytes[3];
+            let target = Operand::Indexed(index.name(), bytes[2] as i8);
+            let (mnemonic, operands) = decode_cb(op, target);
+            // Other register fields also copy the result into that register
+            let undocumented = op & 0b111 != 6 || op >> 3 == 6;
+            let cycles = indexed_cb_cycles(op);
+            (mnemonic, operands, 4, undocumented, (cycles, cycles))
+        }
+        (0xdd | 0xfd, op) => {
+            let index = Index::from_prefix(opcode);
+            let (mnemonic, operands, length) = decode_main(&bytes[1..], adr, Some(index));
+            let undocumented = !uses_hl_pair(op) || uses_hl_bytes(op);
+            let cycles = (indexed_cycles(op, false), indexed_cycles(op, true));
+            (mnemonic, operands, length, undocumented, cycles)
+        }
+        (0xcb, op) => {
+            let (mnemonic, operands) = decode_cb(op, Operand::Name(R[(op & 0b111) as usize]));
+            (mnemonic, operands, 2, op >> 3 == 6, (cb_cycles(op), cb_cycles(op)))
+        }
+        (0xed, op) => {
+            let (mnemonic, operands, length, undocumented) = decode_ed(&bytes);
+            (mnemonic, operands, length, undocumented, (ed_cycles(op), ed_cycles_taken(op)))
+        }
+        _ => {
+            let (mnemonic, operands, length) = decode_main(&bytes, adr, None);
+            let op = opcode as usize;
+            (mnemonic, operands, length, false, (timing::Z80.cycles[op], timing::Z80.cycles_taken[op]))
+        }
+    };
+
+    Instruction {
+        opcode,
+        op: Opcode::convert(opcode),
+        mnemonic,
+        operands,
+        bytes,
+        length,
+        cycles: cycles.0,
+        cycles_taken: cycles.1,
+        undocumented,
+    }
+}
+
+/// Opcodes using H or L on their own, IXH and friends behind a prefix
+fn uses_hl_bytes(opcode: u8) -> bool {
+    let (y, z) = ((opcode >> 3) & 0b111, opcode & 0b111);
+    let hl = |r: u8| r == 4 || r == 5;
+    match opcode >> 6 {
+        0 => (z == 4 || z == 5 || z == 6) && hl(y),
+        1 => !uses_hl_memory(opcode) && (hl(y) || hl(z)),
+        2 => hl(z),
+        _ => false,
+    }
+}
+
+/// Unprefixed opcodes, `b` starting at the opcode. `adr` is where the
+/// instruction, including any prefix, starts.
+fn decode_main(b: &[u8], adr: u16, index: Option<Index>) -> (&'static str, Operands, u8) {
+    use Operand::*;
+
+    let op = b[0];
+    let (x, y, z) = (op >> 6, ((op >> 3) & 0b111) as usize, (op & 0b111) as usize);
+    let (p, q) = (y >> 1, y & 1);
+
+    // Behind a prefix, (HL) takes a displacement ahead of any immediate
+    let mem = index.is_some() && uses_hl_memory(op);
+    let length = index.is_some() as u8 + length(op) + mem as u8;
+    let at = |i: usize| b.get(i).copied().unwrap_or(0);
+    let imm = if mem { 2 } else { 1 };
+    let n = at(imm);
+    let nn = join_bytes(at(imm + 1), n);
+    let d = at(1) as i8;
+    let rel = adr.wrapping_add(length as u16).wrapping_add(d as u16);
+
+    let r = |i: usize| match (index, i) {
+        (Some(ix), 6) => Indexed(ix.name(), d),
+        (Some(ix), 4 | 5) if !mem => Name(ix.half(i)),
+        _ => Name(R[i]),
+    };
+    let rp = |i: usize| match (index, i) {
+        (Some(ix), 2) => Name(ix.name()),
+        _ => Name(RP[i]),
+    };
+    let rp2 = |i: usize| if i == 3 { Name("AF") } else { rp(i) };
+    let alu = |y: usize, operand: Operand| match y {
+        0 | 1 | 3 => (ALU[y], [Some(Name("A")), Some(operand)]),
+        _ => (ALU[y], [Some(operand), None]),
+    };
+
+    let (mnemonic, operands) = match (x, z) {
+        (0, 0) => match y {
+            0 => ("NOP", [None, None]),
+            1 => ("EX", [Some(Name("AF")), Some(Name("AF'"))]),
+            2 => ("DJNZ", [Some(Addr(rel)), None]),
+            3 => ("JR", [Some(Addr(rel)), None]),
+            _ => ("JR", [Some(Name(CC[y - 4])), Some(Addr(rel))]),
+        },
+        (0, 1) if q == 0 => ("LD", [Some(rp(p)), Some(Imm16(nn))]),
+        (0, 1) => ("ADD", [Some(rp(2)), Some(rp(p))]),
+        (0, 2) => match (q, p) {
+            (0, 0) => ("LD", [Some(Name("(BC)")), Some(Name("A"))]),
+            (0, 1) => ("LD", [Some(Name("(DE)")), Some(Name("A"))]),
+            (0, 2) => ("LD", [Some(Mem(nn)), Some(rp(2))]),
+            (0, _) => ("LD", [Some(Mem(nn)), Some(Name("A"))]),
+            (_, 0) => ("LD", [Some(Name("A")), Some(Name("(BC)"))]),
+            (_, 1) => ("LD", [Some(Name("A")), Some(Name("(DE)"))]),
+            (_, 2) => ("LD", [Some(rp(2)), Some(Mem(nn))]),
+            _ => ("LD", [Some(Name("A")), Some(Mem(nn))]),
+        },
+        (0, 3) if q == 0 => ("INC", [Some(rp(p)), None]),
+        (0, 3) => ("DEC", [Some(rp(p)), None]),
+        (0, 4) => ("INC", [Some(r(y)), None]),
+        (0, 5) => ("DEC", [Some(r(y)), None]),
+        (0, 6) => ("LD", [Some(r(y)), Some(Imm8(n))]),
+        (0, _) => (ROT_A[y], [None, None]),
+
+        (1, 6) if y == 6 => ("HALT", [None, None]),
+        (1, _) => ("LD", [Some(r(y)), Some(r(z))]),
+        (2, _) => alu(y, r(z)),
+
+        (3, 0) => ("RET", [Some(Name(CC[y])), None]),
+        (3, 1) => match (q, p) {
+            (0, _) => ("POP", [Some(rp2(p)), None]),
+            (_, 0) => ("RET", [None, None]),
+            (_, 1) => ("EXX", [None, None]),
+            (_, 2) => ("JP", [Some(Name(index.map_or("(HL)", |ix| ix.indirect()))), None]),
+            _ => ("LD", [Some(Name("SP")), Some(rp(2))]),
+        },
+        (3, 2) => ("JP", [Some(Name(CC[y])), Some(Addr(nn))]),
+        (3, 3) => match y {
+            2 => ("OUT", [Some(Io(n)), Some(Name("A"))]),
+            3 => ("IN", [Some(Name("A")), Some(Io(n))]),
+            4 => ("EX", [Some(Name("(SP)")), Some(rp(2))]),
+            5 => ("EX", [Some(Name("DE")), Some(Name("HL"))]),
+            6 => ("DI", [None, None]),
+            7 => ("EI", [None, None]),
+            // The CB prefix is decoded before getting here
+            _ => ("JP", [Some(Addr(nn)), None]),
+        },
+        (3, 4) => ("CALL", [Some(Name(CC[y])), Some(Addr(nn))]),
+        (3, 5) if q == 0 => ("PUSH", [Some(rp2(p)), None]),
+        // As are DD, ED and FD
+        (3, 5) => ("CALL", [Some(Addr(nn)), None]),
+        (3, 6) => alu(y, Imm8(n)),
+        _ => ("RST", [Some(Imm8((y * 8) as u8)), None]),
+    };
+
+    (mnemonic, operands, length)
+}
+
+/// CB prefixed opcodes acting on `target`
+fn decode_cb(op: u8, target: Operand) -> (&'static str, Operands) {
+    let y = ((op >> 3) & 0b111) as usize;
+    let bit = Some(Operand::Name(BITS[y]));
+    match op >> 6 {
+        0 => (ROT[y], [Some(target), None]),
+        1 => ("BIT", [bit, Some(target)]),
+        2 => ("RES", [bit, Some(target)]),
+        _ => ("SET", [bit, Some(target)]),
+    }
+}
+
+/// ED prefixed opcodes, `b` starting at the prefix
+fn decode_ed(b: &[u8]) -> (&'static str, Operands, u8, bool) {
+    use Operand::*;
+
+    let op = b[1];
+    let (x, y, z) = (op >> 6, ((op >> 3) & 0b111) as usize, (op & 0b111) as usize);
+    let (p, q) = (y >> 1, y & 1);
+    let nn = join_bytes(b[3], b[2]);
+    let none = [None, None];
+
+    let (mnemonic, operands, undocumented) = match (x, z) {
+        (1, 0) if y == 6 => ("IN", [Some(Name("F")), Some(Name("(C)"))], true),
+        (1, 0) => ("IN", [Some(Name(R[y])), Some(Name("(C)"))], false),
+        (1, 1) if y == 6 => ("OUT", [Some(Name("(C)")), Some(Imm8(0))], true),
+        (1, 1) => ("OUT", [Some(Name("(C)")), Some(Name(R[y]))], false),
+        (1, 2) if q == 0 => ("SBC", [Some(Name("HL")), Some(Name(RP[p]))], false),
+        (1, 2) => ("ADC", [Some(Name("HL")), Some(Name(RP[p]))], false),
+        (1, 3) if q == 0 => ("LD", [Some(Mem(nn)), Some(Name(RP[p]))], false),
+        (1, 3) => ("LD", [Some(Name(RP[p])), Some(Mem(nn))], false),
+        (1, 4) => ("NEG", none, y != 0),
+        (1, 5) if y == 1 => ("RETI", none, false),
+        (1, 5) => ("RETN", none, y != 0),
+        (1, 6) => ("IM", [Some(Name(IM[y])), None], !matches!(y, 0 | 2 | 3)),
+        (1, _) => match y {
+            0 => ("LD", [Some(Name("I")), Some(Name("A"))], false),
+            1 => ("LD", [Some(Name("R")), Some(Name("A"))], false),
+            2 => ("LD", [Some(Name("A")), Some(Name("I"))], false),
+            3 => ("LD", [Some(Name("A")), Some(Name("R"))], false),
+            4 => ("RRD", none, false),
+            5 => ("RLD", none, false),
+            _ => ("NOP", none, true),
+        },
+        (2, 0..=3) if y >= 4 => (BLOCK[y - 4][z], none, false),
+        _ => ("NOP", none, true),
+    };
+
+    let length = if x == 1 && z == 3 { 4 } else { 2 };
+    (mnemonic, operands, length, undocumented)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disassembly() {
+        let cases: [(&[u8], &str, u8); 12] = [
+            (&[0x78], "LD A,B", 4),
+            (&[0x18, 0xfe], "JR 0000", 12),
+            (&[0x10, 0x02], "DJNZ 0004", 8),
+            (&[0x08], "EX AF,AF'", 4),
+            (&[0xdd, 0x21, 0x34, 0x12], "LD IX,1234", 14),
+            (&[0xfd, 0x7e, 0xfb], "LD A,(IY-05)", 19),
+            (&[0xdd, 0x36, 0x02, 0x09], "LD (IX+02),09", 19),
+            (&[0xdd, 0x66, 0x01], "LD H,(IX+01)", 19),
+            (&[0xcb, 0x7e], "BIT 7,(HL)", 12),
+            (&[0xfd, 0xcb, 0x03, 0xc6], "SET 0,(IY+03)", 23),
+            (&[0xed, 0x4b, 0x00, 0x80], "LD BC,(8000)", 20),
+            (&[0xed, 0xb0], "LDIR", 16),
+        ];
+
+        for (bytes, text, cycles) in cases {
+            let instr = decode(bytes, 0);
+            assert_eq!(instr.to_string(), text);
+            assert_eq!(instr.length as usize, bytes.len(), "{}", text);
+            assert_eq!(instr.cycles, cycles, "{}", text);
+            assert!(!instr.is_undocumented(), "{}", text);
+        }
+
+        assert!(decode(&[0xdd, 0x44], 0).is_undocumented());
+        assert_eq!(decode(&[0xdd, 0x44], 0).to_string(), "LD B,IXH");
+        assert!(decode(&[0xed, 0x00], 0).is_undocumented());
+    }
+}
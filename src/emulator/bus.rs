@@ -0,0 +1,20 @@
+/// Everything the CPU talks to: the memory address space and the 256 IO
+/// ports. Each machine provides its own so the same core can run them all.
+pub trait Bus {
+    fn read(&mut self, adr: u16) -> u8;
+    fn write(&mut self, adr: u16, val: u8);
+
+    /// IN from `port`
+    fn input(&mut self, port: u8) -> u8;
+    /// OUT of `val` to `port`
+    fn output(&mut self, port: u8, val: u8);
+
+    /// Called after every step with the T-states it took
+    fn tick(&mut self, _cycles: u8) {}
+
+    /// Address of an access the bus refused during the last instruction.
+    /// `run_op` turns it into a `CpuError::BusFault`.
+    fn take_fault(&mut self) -> Option<u16> {
+        None
+    }
+}
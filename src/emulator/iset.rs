@@ -1,1389 +1,812 @@
-use std::process;
+use std::marker::PhantomData;
 
-use crate::IO;
-use super::arch::{flag::FlagType, opcodes::Opcode, state::State};
+use super::arch::{
+    flag::FlagType,
+    i8085,
+    instruction::{RegPair, Register},
+    interrupt::Interrupt,
+    opcodes::Opcode,
+    state::{Cpu, State},
+    z80,
+};
+use super::bus::Bus;
+use super::iset8085;
+use super::isetz80;
+use super::step::{CpuError, StepOutcome};
 use super::utils::*;
 
+/// T-states accounted for each step spent halted
+pub const HALT_CYCLES: u8 = 4;
+
+/// Runs one instruction with pc already past the opcode byte and returns
+/// the T-states it took
+pub(super) type Handler<B> = fn(&mut State, &mut B, u8) -> u8;
+
+/// Handler table, built once for each kind of bus
+pub(super) struct Dispatch<B>(PhantomData<B>);
+
+impl<B: Bus> Dispatch<B> {
+    pub(super) const TABLE: [Handler<B>; 256] = dispatch_table();
+    const TABLE_8085: [Handler<B>; 256] = iset8085::dispatch_table();
+    pub(super) const TABLE_Z80: [Handler<B>; 256] = isetz80::dispatch_table();
+}
 
 impl State {
-    pub fn generate_interrupt(&mut self, n: u16) {
-        let (pchi, pclo) = split_bytes(self.pc );
-        self.mem[self.sp as usize - 1] = pchi;
-        self.mem[self.sp as usize - 2] = pclo;
-        self.sp -= 2;
+    /// Raises INTR. The request stays latched until the CPU accepts it at
+    /// an instruction boundary with interrupts enabled.
+    pub fn request_interrupt(&mut self, int: Interrupt) {
+        self.interrupt = Some(int);
+    }
+
+    /// Pushes the PC and runs `int` as an accepted interrupt does. The
+    /// 8085's and Z80's own vectors are taken as a CALL.
+    pub(super) fn acknowledge<B: Bus>(&mut self, bus: &mut B, int: Interrupt, cycles: u8) -> Result<u8, CpuError> {
         self.enable = 0;
+        self.halted = false;
+
+        self.push(bus, self.pc);
+        if let Some(adr) = bus.take_fault() {
+            return Err(CpuError::BusFault { pc: self.pc, opcode: int.data(), adr });
+        }
+        self.pc = int.vector();
 
-        // RST n
-        self.pc = 0x08*n;
+        self.cycles += cycles as u64;
+        bus.tick(cycles);
+        Ok(cycles)
     }
 
-    pub fn run_op(&mut self, io: &mut IO) {
-        let _pc = self.pc as usize;
-        let opcode = self.mem[_pc];
-    
-        match Opcode::convert(opcode) {
-            Opcode::NOP => (),
-            Opcode::LXIB => {
-                self.b = self.mem[_pc + 2];
-                self.c = self.mem[_pc + 1];
-                self.pc += 2;
-            }
-            Opcode::STAXB => {
-                let bc = join_bytes(self.b, self.c);
-                self.mem[bc as usize] = self.a;
-            }
-            Opcode::INXB => (self.b, self.c) = split_bytes(join_bytes(self.b, self.c) + 1),
-            Opcode::INRB => {
-                check_flag_ac(self.b, self.b + 1, self);
-                self.b += 1;
-                check_flag_z(self.b, self);
-                check_flag_s(self.b, self);
-                check_flag_p(self.b, self);
-            }
-            Opcode::DCRB => {
-                check_flag_ac(self.b, self.b - 1, self);
-                self.b -= 1;
-                check_flag_z(self.b, self);
-                check_flag_s(self.b, self);
-                check_flag_p(self.b, self);
-            }
-            Opcode::MVIB => {
-                self.b = self.mem[_pc + 1];
-                self.pc += 1;
-            }
-            Opcode::RLC => {
-                check_flag_cy8((self.a as u16) << 1, self);
-                self.a = self.a.rotate_left(1);
-            }
-            Opcode::DADB => {
-                let hl = join_bytes(self.h, self.l);
-                let bc = join_bytes(self.b, self.c);
-                check_flag_cy16(hl as u32 + bc as u32, self);
-                (self.h, self.l) = split_bytes(hl + bc);
-            }
-            Opcode::LDAXB => {
-                let bc = join_bytes(self.b, self.c);
-                self.a = self.mem[bc as usize];
-            }
-            Opcode::DCXB => (self.b, self.c) = split_bytes(join_bytes(self.b, self.c) - 1),
-            Opcode::INRC => {
-                check_flag_ac(self.c, self.c + 1, self);
-                self.c += 1;
-                check_flag_z(self.c, self);
-                check_flag_s(self.c, self);
-                check_flag_p(self.c, self);
-            }
-            Opcode::DCRC => {
-                check_flag_ac(self.c, self.c - 1, self);
-                self.c -= 1;
-                check_flag_z(self.c, self);
-                check_flag_s(self.c, self);
-                check_flag_p(self.c, self);
-            }
-            Opcode::MVIC => {
-                self.c = self.mem[_pc + 1];
-                self.pc += 1;
-            }
-            Opcode::RRC => {
-                check_flag_cy8((self.a as u16).rotate_right(1), self);
-                self.a = self.a.rotate_right(1);
-            }
-            Opcode::LXID => {
-                self.d = self.mem[_pc + 2];
-                self.e = self.mem[_pc + 1];
-                self.pc += 2;
-            }
-            Opcode::STAXD => {
-                let de = join_bytes(self.d, self.e);
-                self.mem[de as usize] = self.a;
-            }
-            Opcode::INXD => (self.d, self.e) = split_bytes(join_bytes(self.d, self.e) + 1),
-            Opcode::INRD => {
-                check_flag_ac(self.d, self.d + 1, self);
-                self.d += 1;
-                check_flag_z(self.d, self);
-                check_flag_s(self.d, self);
-                check_flag_p(self.d, self);
-            }
-            Opcode::DCRD => {
-                check_flag_ac(self.d, self.d - 1, self);
-                self.d -= 1;
-                check_flag_z(self.d, self);
-                check_flag_s(self.d, self);
-                check_flag_p(self.d, self);
-            }
-            Opcode::MVID => {
-                self.d = self.mem[_pc + 1];
-                self.pc += 1;
-            }
-            Opcode::RAL => {
-                let prev = self.flags.get(FlagType::CY);
-                check_flag_cy8((self.a as u16) << 1, self);
-                self.a = self.a << 1;
-                self.a |= prev;
-            }
-            Opcode::DADD => {
-                let hl = join_bytes(self.h, self.l);
-                let de = join_bytes(self.d, self.e);
-                check_flag_cy16(hl as u32 + de as u32, self);
-                (self.h, self.l) = split_bytes(hl + de);
-            }
-            Opcode::LDAXD => {
-                let de = join_bytes(self.d, self.e);
-                self.a = self.mem[de as usize];
-            }
-            Opcode::DCXD => (self.d, self.e) = split_bytes(join_bytes(self.d, self.e) - 1),
-            Opcode::INRE => {
-                check_flag_ac(self.e, self.e + 1, self);
-                self.e += 1;
-                check_flag_z(self.e, self);
-                check_flag_s(self.e, self);
-                check_flag_p(self.e, self);
-            }
-            Opcode::DCRE => {
-                check_flag_ac(self.e, self.e - 1, self);
-                self.e -= 1;
-                check_flag_z(self.e, self);
-                check_flag_s(self.e, self);
-                check_flag_p(self.e, self);
-            }
-            Opcode::MVIE => {
-                self.e = self.mem[_pc + 1];
-                self.pc += 1;
-            }
-            Opcode::RAR => {
-                let bit7 = self.flags.get(FlagType::CY);
-                check_flag_cy8((self.a as u16).rotate_right(1), self);
-                self.a = self.a >> 1;
-                self.a |= bit7 << 7;
-            }
-            Opcode::LXIH => {
-                self.h = self.mem[_pc + 2];
-                self.l = self.mem[_pc + 1];
-                self.pc += 2;
-            }
-            Opcode::SHLD => {
-                let adr = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]);
-                self.mem[adr as usize] = self.l;
-                self.mem[(adr + 1) as usize] = self.h;
-                self.pc += 2;
-            }
-            Opcode::INXH => (self.h, self.l) = split_bytes(join_bytes(self.h, self.l) + 1),
-            Opcode::INRH => {
-                check_flag_ac(self.h, self.h + 1, self);
-                self.h += 1;
-                check_flag_z(self.h, self);
-                check_flag_s(self.h, self);
-                check_flag_p(self.h, self);
-            }
-            Opcode::DCRH => {
-                check_flag_ac(self.h, self.h - 1, self);
-                self.h -= 1;
-                check_flag_z(self.h, self);
-                check_flag_s(self.h, self);
-                check_flag_p(self.h, self);
-            }
-            Opcode::MVIH => {
-                self.h = self.mem[_pc + 1];
-                self.pc += 1;
-            }
-            Opcode::DAA => {
-                if (self.a & 0xf) > 9 {
-                    self.a += 0x06;
-                }
-            }
-            Opcode::DADH => {
-                let hl = join_bytes(self.h, self.l);
-                check_flag_cy16((hl as u32) * 2, self);
-                (self.h, self.l) = split_bytes(2 * hl);
-            }
-            Opcode::LHLD => {
-                let adr = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]);
-                self.l = self.mem[adr as usize];
-                self.h = self.mem[(adr + 1) as usize];
-                self.pc += 2;
-            }
-            Opcode::DCXH => (self.h, self.l) = split_bytes(join_bytes(self.h, self.l) - 1),
-            Opcode::INRL => {
-                check_flag_ac(self.l, self.l + 1, self);
-                self.l += 1;
-                check_flag_z(self.l, self);
-                check_flag_s(self.l, self);
-                check_flag_p(self.l, self);
-            }
-            Opcode::DCRL => {
-                check_flag_ac(self.l, self.l - 1, self);
-                self.l -= 1;
-                check_flag_z(self.l, self);
-                check_flag_s(self.l, self);
-                check_flag_p(self.l, self);
-            }
-            Opcode::MVIL => {
-                self.l = self.mem[_pc + 1];
-                self.pc += 1;
-            }
-            Opcode::CMA => {
-                self.a = !self.a;
-            }
-            Opcode::LXISP => {
-                self.sp = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]);
-                self.pc += 2;
-            }
-            Opcode::STA => {
-                let adr = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]);
-                self.mem[adr as usize] = self.a;
-                self.pc += 2;
-            }
-            Opcode::INXSP => self.sp += 1,
-            Opcode::INRM => {
-                let adr = join_bytes(self.h, self.l) as usize;
-                check_flag_ac(self.mem[adr], self.mem[adr] + 1, self);
-                self.mem[adr] += 1;
-                check_flag_z(self.mem[adr], self);
-                check_flag_s(self.mem[adr], self);
-                check_flag_p(self.mem[adr], self);
-            }
-            Opcode::DCRM => {
-                let adr = join_bytes(self.h, self.l) as usize;
-                check_flag_ac(self.mem[adr], self.mem[adr] - 1, self);
-                self.mem[adr] -= 1;
-                check_flag_z(self.mem[adr], self);
-                check_flag_s(self.mem[adr], self);
-                check_flag_p(self.mem[adr], self);
-            }
-            Opcode::MVIM => {
-                let adr = join_bytes(self.h, self.l) as usize;
-                self.mem[adr] = self.mem[_pc + 1];
-                self.pc += 1;
-            }
-            Opcode::STC => self.flags.set(FlagType::CY),
-            Opcode::DADSP => {
-                let hl = join_bytes(self.h, self.l);
-                check_flag_cy16((hl + self.sp) as u32, self);
-                (self.h, self.l) = split_bytes(hl + self.sp);
-            }
-            Opcode::LDA => {
-                let adr = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]);
-                self.a = self.mem[adr as usize];
-                self.pc += 2;
-            }
-            Opcode::DCXSP => self.sp -= 1,
-            Opcode::INRA => {
-                check_flag_ac(self.a, self.a + 1, self);
-                self.a += 1;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::DCRA => {
-                check_flag_ac(self.a, self.a - 1, self);
-                self.a -= 1;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::MVIA => {
-                self.a = self.mem[_pc + 1];
-                self.pc += 1;
-            }
-            Opcode::CMC => match self.flags.get(FlagType::CY) == 0 {
-                true => self.flags.set(FlagType::CY),
-                false => self.flags.unset(FlagType::CY),
-            },
-            Opcode::MOVBB => self.b = self.b,
-            Opcode::MOVBC => self.b = self.c,
-            Opcode::MOVBD => self.b = self.d,
-            Opcode::MOVBE => self.b = self.e,
-            Opcode::MOVBH => self.b = self.h,
-            Opcode::MOVBL => self.b = self.l,
-            Opcode::MOVBM => self.b = self.mem[join_bytes(self.h, self.l) as usize],
-            Opcode::MOVBA => self.b = self.a,
-    
-            Opcode::MOVCB => self.c = self.b,
-            Opcode::MOVCC => self.c = self.c,
-            Opcode::MOVCD => self.c = self.d,
-            Opcode::MOVCE => self.c = self.e,
-            Opcode::MOVCH => self.c = self.h,
-            Opcode::MOVCL => self.c = self.l,
-            Opcode::MOVCM => self.c = self.mem[join_bytes(self.h, self.l) as usize],
-            Opcode::MOVCA => self.c = self.a,
-    
-            Opcode::MOVDB => self.d = self.b,
-            Opcode::MOVDC => self.d = self.c,
-            Opcode::MOVDD => self.d = self.d,
-            Opcode::MOVDE => self.d = self.e,
-            Opcode::MOVDH => self.d = self.h,
-            Opcode::MOVDL => self.d = self.l,
-            Opcode::MOVDM => self.d = self.mem[join_bytes(self.h, self.l) as usize],
-            Opcode::MOVDA => self.d = self.a,
-    
-            Opcode::MOVEC => self.e = self.c,
-            Opcode::MOVEB => self.e = self.b,
-            Opcode::MOVED => self.e = self.d,
-            Opcode::MOVEE => self.e = self.e,
-            Opcode::MOVEH => self.e = self.h,
-            Opcode::MOVEL => self.e = self.l,
-            Opcode::MOVEM => self.e = self.mem[join_bytes(self.h, self.l) as usize],
-            Opcode::MOVEA => self.e = self.a,
-    
-            Opcode::MOVHB => self.h = self.b,
-            Opcode::MOVHC => self.h = self.c,
-            Opcode::MOVHD => self.h = self.d,
-            Opcode::MOVHE => self.h = self.e,
-            Opcode::MOVHH => self.h = self.h,
-            Opcode::MOVHL => self.h = self.l,
-            Opcode::MOVHM => self.h = self.mem[join_bytes(self.h, self.l) as usize],
-            Opcode::MOVHA => self.h = self.a,
-    
-            Opcode::MOVLB => self.l = self.b,
-            Opcode::MOVLC => self.l = self.c,
-            Opcode::MOVLD => self.l = self.d,
-            Opcode::MOVLE => self.l = self.e,
-            Opcode::MOVLH => self.l = self.h,
-            Opcode::MOVLL => self.l = self.l,
-            Opcode::MOVLM => self.l = self.mem[join_bytes(self.h, self.l) as usize],
-            Opcode::MOVLA => self.l = self.a,
-    
-            Opcode::MOVMB => self.mem[join_bytes(self.h, self.l) as usize] = self.b,
-            Opcode::MOVMC => self.mem[join_bytes(self.h, self.l) as usize] = self.c,
-            Opcode::MOVMD => self.mem[join_bytes(self.h, self.l) as usize] = self.d,
-            Opcode::MOVME => self.mem[join_bytes(self.h, self.l) as usize] = self.e,
-            Opcode::MOVMH => self.mem[join_bytes(self.h, self.l) as usize] = self.h,
-            Opcode::MOVML => self.mem[join_bytes(self.h, self.l) as usize] = self.l,
-            Opcode::HLT => {
-                println!("Halted");
-                process::exit(-2)
-            }
-            Opcode::MOVMA => self.mem[join_bytes(self.h, self.l) as usize] = self.a,
-    
-            Opcode::MOVAB => self.a = self.b,
-            Opcode::MOVAC => self.a = self.c,
-            Opcode::MOVAD => self.a = self.d,
-            Opcode::MOVAE => self.a = self.e,
-            Opcode::MOVAH => self.a = self.h,
-            Opcode::MOVAL => self.a = self.l,
-            Opcode::MOVAM => self.a = self.mem[join_bytes(self.h, self.l) as usize],
-            Opcode::MOVAA => self.a = self.a,
-    
-            Opcode::ADDB => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) + (self.b as u16), self);
-                self.a += self.b;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ADDC => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) + (self.c as u16), self);
-                self.a += self.c;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ADDD => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) + (self.d as u16), self);
-                self.a += self.d;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ADDE => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) + (self.e as u16), self);
-                self.a += self.e;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ADDH => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) + (self.h as u16), self);
-                self.a += self.h;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ADDL => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) + (self.l as u16), self);
-                self.a += self.l;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ADDM => {
-                let adr = join_bytes(self.h, self.l) as usize;
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) + (self.mem[adr] as u16), self);
-                self.a += self.mem[adr];
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ADDA => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) + (self.a as u16), self);
-                self.a += self.a;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-    
-            Opcode::ADCB => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8(
-                    (self.a as u16) + (self.b as u16) + (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a += self.b + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ADCC => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8(
-                    (self.a as u16) + (self.c as u16) + (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a += self.c + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ADCD => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8(
-                    (self.a as u16) + (self.d as u16) + (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a += self.d + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ADCE => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8(
-                    (self.a as u16) + (self.e as u16) + (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a += self.e + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ADCH => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8(
-                    (self.a as u16) + (self.h as u16) + (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a += self.h + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ADCL => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8(
-                    (self.a as u16) + (self.l as u16) + (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a += self.l + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ADCM => {
-                let adr = join_bytes(self.h, self.l) as usize;
-                // check_flag_ac(reg, self)
-                check_flag_cy8(
-                    (self.a as u16) + (self.mem[adr] as u16) + (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a += self.mem[adr] + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ADCA => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8(
-                    (self.a as u16) + (self.a as u16) + (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a += self.a + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-    
-            Opcode::SUBB => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) - (self.b as u16), self);
-                self.a -= self.b;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::SUBC => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) - (self.c as u16), self);
-                self.a -= self.c;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::SUBD => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) - (self.d as u16), self);
-                self.a -= self.d;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::SUBE => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) - (self.e as u16), self);
-                self.a -= self.e;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::SUBH => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) - (self.h as u16), self);
-                self.a -= self.h;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::SUBL => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) - (self.l as u16), self);
-                self.a -= self.l;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::SUBM => {
-                let adr = join_bytes(self.h, self.l) as usize;
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) - (self.mem[adr] as u16), self);
-                self.a -= self.mem[adr];
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::SUBA => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) - (self.a as u16), self);
-                self.a -= self.a;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-    
-            Opcode::SBBB => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8(
-                    (self.a as u16) - (self.b as u16) - (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a -= self.b + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::SBBC => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8(
-                    (self.a as u16) - (self.c as u16) - (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a -= self.c + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::SBBD => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8(
-                    (self.a as u16) - (self.d as u16) - (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a -= self.d + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::SBBE => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8(
-                    (self.a as u16) - (self.e as u16) - (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a -= self.e + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::SBBH => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8(
-                    (self.a as u16) - (self.h as u16) - (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a -= self.h + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::SBBL => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8(
-                    (self.a as u16) - (self.l as u16) - (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a -= self.l + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::SBBM => {
-                let adr = join_bytes(self.h, self.l) as usize;
-                // check_flag_ac(reg, self)
-                check_flag_cy8(
-                    (self.a as u16) - (self.mem[adr] as u16) - (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a -= self.mem[adr] + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::SBBA => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8(
-                    (self.a as u16) - (self.a as u16) - (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a -= self.a + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-    
-            Opcode::ANAB => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) & (self.b as u16), self);
-                self.a &= self.b;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ANAC => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) & (self.c as u16), self);
-                self.a &= self.c;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ANAD => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) & (self.d as u16), self);
-                self.a &= self.d;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ANAE => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) & (self.e as u16), self);
-                self.a &= self.e;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ANAH => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) & (self.h as u16), self);
-                self.a &= self.h;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ANAL => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) & (self.l as u16), self);
-                self.a &= self.l;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ANAM => {
-                let adr = join_bytes(self.h, self.l) as usize;
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) & (self.mem[adr] as u16), self);
-                self.a &= self.mem[adr];
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ANAA => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) & (self.a as u16), self);
-                self.a &= self.a;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-    
-            Opcode::XRAB => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) ^ (self.b as u16), self);
-                self.a ^= self.b;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::XRAC => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) ^ (self.c as u16), self);
-                self.a ^= self.c;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::XRAD => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) ^ (self.d as u16), self);
-                self.a ^= self.d;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::XRAE => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) ^ (self.e as u16), self);
-                self.a ^= self.e;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::XRAH => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) ^ (self.h as u16), self);
-                self.a ^= self.h;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::XRAL => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) ^ (self.l as u16), self);
-                self.a ^= self.l;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::XRAM => {
-                let adr = join_bytes(self.h, self.l) as usize;
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) ^ (self.mem[adr] as u16), self);
-                self.a ^= self.mem[adr];
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::XRAA => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) ^ (self.a as u16), self);
-                self.a ^= self.a;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-    
-            Opcode::ORAB => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) | (self.b as u16), self);
-                self.a |= self.b;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ORAC => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) | (self.c as u16), self);
-                self.a |= self.c;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ORAD => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) | (self.d as u16), self);
-                self.a |= self.d;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ORAE => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) | (self.e as u16), self);
-                self.a |= self.e;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ORAH => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) | (self.h as u16), self);
-                self.a |= self.h;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ORAL => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) | (self.l as u16), self);
-                self.a |= self.l;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ORAM => {
-                let adr = join_bytes(self.h, self.l) as usize;
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) | (self.mem[adr] as u16), self);
-                self.a |= self.mem[adr];
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-            Opcode::ORAA => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) | (self.a as u16), self);
-                self.a |= self.a;
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-            }
-    
-            Opcode::CMPB => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) - (self.b as u16), self);
-                check_flag_z(self.a - self.b, self);
-                check_flag_s(self.a - self.b, self);
-                check_flag_p(self.a - self.b, self);
-            }
-            Opcode::CMPC => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) - (self.c as u16), self);
-                check_flag_z(self.a - self.c, self);
-                check_flag_s(self.a - self.c, self);
-                check_flag_p(self.a - self.c, self);
-            }
-            Opcode::CMPD => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) - (self.d as u16), self);
-                check_flag_z(self.a - self.d, self);
-                check_flag_s(self.a - self.d, self);
-                check_flag_p(self.a - self.d, self);
-            }
-            Opcode::CMPE => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) - (self.e as u16), self);
-                check_flag_z(self.a - self.e, self);
-                check_flag_s(self.a - self.e, self);
-                check_flag_p(self.a - self.e, self);
-            }
-            Opcode::CMPH => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) - (self.h as u16), self);
-                check_flag_z(self.a - self.h, self);
-                check_flag_s(self.a - self.h, self);
-                check_flag_p(self.a - self.h, self);
-            }
-            Opcode::CMPL => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) - (self.l as u16), self);
-                check_flag_z(self.a - self.l, self);
-                check_flag_s(self.a - self.l, self);
-                check_flag_p(self.a - self.l, self);
-            }
-            Opcode::CMPM => {
-                let adr = join_bytes(self.h, self.l) as usize;
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) - (self.mem[adr] as u16), self);
-                check_flag_z(self.a - self.mem[adr], self);
-                check_flag_s(self.a - self.mem[adr], self);
-                check_flag_p(self.a - self.mem[adr], self);
-            }
-            Opcode::CMPA => {
-                // check_flag_ac(reg, self)
-                check_flag_cy8((self.a as u16) - (self.a as u16), self);
-                check_flag_z(self.a - self.a, self);
-                check_flag_s(self.a - self.a, self);
-                check_flag_p(self.a - self.a, self);
-            }
-    
-            Opcode::RNZ => {
-                if self.flags.get(FlagType::Z) != 0b1 {
-                    self.pc = join_bytes(
-                        self.mem[self.sp as usize + 1],
-                        self.mem[self.sp as usize],
-                    ) - 1;
-                    self.sp += 2;
-                }
-            }
-            Opcode::POPB => {
-                self.c = self.mem[self.sp as usize];
-                self.b = self.mem[self.sp as usize + 1];
-                self.sp += 2;
-            }
-            Opcode::JNZ => {
-                if self.flags.get(FlagType::Z) != 0b1 {
-                    self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
-                } else {
-                    self.pc += 2;
-                }
-            }
-            Opcode::JMP => {
-                self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
-            }
-            Opcode::CNZ => {
-                if self.flags.get(FlagType::Z) != 0b1 {
-                    let (pchi, pclo) = split_bytes(self.pc + 3);
-                    self.mem[self.sp as usize - 1] = pchi;
-                    self.mem[self.sp as usize - 2] = pclo;
-                    self.sp -= 2;
-                    self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
-                } else {
-                    self.pc += 2;
-                }
-            }
-            Opcode::PUSHB => {
-                self.mem[self.sp as usize - 1] = self.b;
-                self.mem[self.sp as usize - 2] = self.c;
-                self.sp -= 2;
-            }
-            Opcode::ADI => {
-                check_flag_cy8((self.a as u16) + (self.mem[_pc + 1] as u16), self);
-                self.a += self.mem[_pc + 1];
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-                self.pc += 1;
-            }
-            Opcode::RST0 => {
-                let (pchi, pclo) = split_bytes(self.pc + 1);
-                self.mem[self.sp as usize - 1] = pchi;
-                self.mem[self.sp as usize - 2] = pclo;
-                self.sp -= 2;
-                self.pc = 0xff; // 0x00 - 1 due to end pc increment
-            }
-    
-            Opcode::RZ => {
-                if self.flags.get(FlagType::Z) == 0b1 {
-                    self.pc = join_bytes(
-                        self.mem[self.sp as usize + 1],
-                        self.mem[self.sp as usize],
-                    ) - 1;
-                    self.sp += 2;
-                }
-            }
-            Opcode::RET => {
-                self.pc = join_bytes(
-                    self.mem[self.sp as usize + 1],
-                    self.mem[self.sp as usize],
-                ) - 1;
-                self.sp += 2;
-            }
-            Opcode::JZ => {
-                if self.flags.get(FlagType::Z) == 0b1 {
-                    self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
-                } else {
-                    self.pc += 2;
-                }
-            }
-            Opcode::CZ => {
-                if self.flags.get(FlagType::Z) == 0b1 {
-                    let (pchi, pclo) = split_bytes(self.pc + 3);
-                    self.mem[self.sp as usize - 1] = pchi;
-                    self.mem[self.sp as usize - 2] = pclo;
-                    self.sp -= 2;
-                    self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
-                } else {
-                    self.pc += 2;
+    /// Runs one instruction, or takes an interrupt. On an error the state
+    /// is put back as it was, so the faulting instruction is left
+    /// unexecuted and can be looked at or run again.
+    pub fn run_op<B: Bus>(&mut self, bus: &mut B) -> Result<StepOutcome, CpuError> {
+        let before = self.clone();
+        let step = self.step(bus);
+        if step.is_err() {
+            *self = before;
+        }
+        step
+    }
+
+    fn step<B: Bus>(&mut self, bus: &mut B) -> Result<StepOutcome, CpuError> {
+        // EI only takes effect after the instruction following it
+        let delayed = std::mem::take(&mut self.ei_delay);
+        let enabled = self.enable == 1 && !delayed;
+
+        match self.cpu {
+            Cpu::I8080 => (),
+            Cpu::I8085 => {
+                if let Some(input) = self.pins.pending(enabled) {
+                    return Ok(StepOutcome::Interrupted(self.acknowledge_8085(bus, input)?));
                 }
             }
-            Opcode::CALL => {
-                let (pchi, pclo) = split_bytes(self.pc + 3);
-                self.mem[self.sp as usize - 1] = pchi;
-                self.mem[self.sp as usize - 2] = pclo;
-                self.sp -= 2;
-                self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
-            }
-            Opcode::ACI => {
-                check_flag_cy8(
-                    (self.a as u16)
-                        + (self.mem[_pc + 1] as u16)
-                        + (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a += self.mem[_pc + 1] + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-                self.pc += 1;
-            }
-            Opcode::RST1 => {
-                let (pchi, pclo) = split_bytes(self.pc + 1);
-                self.mem[self.sp as usize - 1] = pchi;
-                self.mem[self.sp as usize - 2] = pclo;
-                self.sp -= 2;
-                self.pc = 0x08 - 1;
-            }
-    
-            Opcode::RNC => {
-                if self.flags.get(FlagType::CY) != 0b1 {
-                    self.pc = join_bytes(
-                        self.mem[self.sp as usize + 1],
-                        self.mem[self.sp as usize],
-                    ) - 1;
-                    self.sp += 2;
+            Cpu::Z80 => {
+                if std::mem::take(&mut self.z80.nmi) {
+                    return Ok(StepOutcome::Interrupted(self.acknowledge_nmi(bus)?));
                 }
             }
-            Opcode::POPD => {
-                self.e = self.mem[self.sp as usize];
-                self.d = self.mem[self.sp as usize + 1];
-                self.sp += 2;
-            }
-            Opcode::JNC => {
-                if self.flags.get(FlagType::CY) != 0b1 {
-                    self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
-                } else {
-                    self.pc += 2;
-                }
+        }
+        if enabled {
+            if let Some(int) = self.interrupt.take() {
+                let cycles = match self.cpu {
+                    Cpu::I8080 => self.acknowledge(bus, int, int.cycles())?,
+                    // The 8085 acknowledge cycle takes one more T-state
+                    Cpu::I8085 => self.acknowledge(bus, int, int.cycles() + 1)?,
+                    Cpu::Z80 => self.acknowledge_z80(bus, int)?,
+                };
+                return Ok(StepOutcome::Interrupted(cycles));
+            }
+        }
+
+        // A halted CPU idles without fetching until an interrupt arrives
+        if self.halted {
+            self.cycles += HALT_CYCLES as u64;
+            bus.tick(HALT_CYCLES);
+            return Ok(StepOutcome::Halted(HALT_CYCLES));
+        }
+
+        let _pc = self.pc;
+        let opcode = bus.read(_pc);
+
+        if self.trap_undocumented && self.is_undocumented(bus, _pc, opcode) {
+            return Err(CpuError::Illegal { pc: _pc, opcode });
+        }
+
+        self.pc = _pc.wrapping_add(1);
+        let cycles = match self.cpu {
+            Cpu::I8080 => Dispatch::<B>::TABLE[opcode as usize](self, bus, opcode),
+            Cpu::I8085 => Dispatch::<B>::TABLE_8085[opcode as usize](self, bus, opcode),
+            Cpu::Z80 => {
+                self.z80.refresh();
+                Dispatch::<B>::TABLE_Z80[opcode as usize](self, bus, opcode)
             }
-            Opcode::OUT => {
-                let port = self.mem[_pc + 1];
-                io.machine_out(port, self.a); 
-                self.pc += 1;
+        };
+
+        // Rejected writes are dropped, `run_op` puts the registers back
+        if let Some(adr) = bus.take_fault() {
+            return Err(CpuError::BusFault { pc: _pc, opcode, adr });
+        }
+
+        self.cycles += cycles as u64;
+        bus.tick(cycles);
+        if self.halted {
+            Ok(StepOutcome::Halted(cycles))
+        } else {
+            Ok(StepOutcome::Executed(cycles))
+        }
+    }
+
+    /// Whether the instruction at `adr` is outside the documented set
+    fn is_undocumented<B: Bus>(&self, bus: &mut B, adr: u16, opcode: u8) -> bool {
+        match self.cpu {
+            Cpu::I8080 => Opcode::is_undocumented(opcode),
+            Cpu::I8085 => i8085::is_undocumented(opcode),
+            Cpu::Z80 => {
+                // Only prefixed opcodes can be, which takes the whole instruction
+                let bytes = [0, 1, 2, 3].map(|n| bus.read(adr.wrapping_add(n)));
+                z80::decode(&bytes, 0).undocumented
+            }
+        }
+    }
+
+    /// T-states of `op` on this CPU, from the table `decode` reports
+    pub(super) fn timing(&self, op: u8) -> u8 {
+        self.cpu.timing().cycles[op as usize]
+    }
+
+    /// T-states of `op` when its condition is met
+    pub(super) fn timing_taken(&self, op: u8) -> u8 {
+        self.cpu.timing().cycles_taken[op as usize]
+    }
+
+    pub(super) fn fetch8(&mut self, bus: &mut impl Bus) -> u8 {
+        let val = bus.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        val
+    }
+
+    pub(super) fn fetch16(&mut self, bus: &mut impl Bus) -> u16 {
+        let lo = self.fetch8(bus);
+        join_bytes(self.fetch8(bus), lo)
+    }
+
+    /// Condition from the 3-bit field of Jcc, Ccc and Rcc: NZ Z NC C PO PE P M
+    pub(super) fn condition(&self, cc: u8) -> bool {
+        let flag = match (cc >> 1) & 0b11 {
+            0 => FlagType::Z,
+            1 => FlagType::CY,
+            2 => FlagType::P,
+            _ => FlagType::S,
+        };
+        self.flags.get(flag) == cc & 0b1
+    }
+}
+
+pub(super) const fn dispatch_table<B: Bus>() -> [Handler<B>; 256] {
+    let mut table: [Handler<B>; 256] = [nop; 256];
+    let mut i = 0;
+    while i < 256 {
+        let op = i as u8;
+        table[i] = match op {
+            0x76 => hlt,
+            0x40..=0x7f => mov,
+            0x80..=0xbf => alu,
+
+            0x02 | 0x12 => stax,
+            0x0a | 0x1a => ldax,
+            0x22 => shld,
+            0x2a => lhld,
+            0x32 => sta,
+            0x3a => lda,
+            0x07 => rlc,
+            0x0f => rrc,
+            0x17 => ral,
+            0x1f => rar,
+            0x27 => daa_,
+            0x2f => cma,
+            0x37 => stc,
+            0x3f => cmc,
+            0x00..=0x3f => match op & 0b1111 {
+                0x1 => lxi,
+                0x3 => inx,
+                0x9 => dad,
+                0xb => dcx,
+                _ => match op & 0b111 {
+                    0b100 => inr,
+                    0b101 => dcr,
+                    0b110 => mvi,
+                    _ => nop,
+                },
             },
-            Opcode::CNC => {
-                if self.flags.get(FlagType::CY) != 0b1 {
-                    let (pchi, pclo) = split_bytes(self.pc + 3);
-                    self.mem[self.sp as usize - 1] = pchi;
-                    self.mem[self.sp as usize - 2] = pclo;
-                    self.sp -= 2;
-                    self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
-                } else {
-                    self.pc += 2;
-                }
-            }
-            Opcode::PUSHD => {
-                self.mem[self.sp as usize - 1] = self.d;
-                self.mem[self.sp as usize - 2] = self.e;
-                self.sp -= 2;
-            }
-            Opcode::SUI => {
-                check_flag_cy8((self.a as u16) - (self.mem[_pc + 1] as u16), self);
-                self.a -= self.mem[_pc + 1];
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-                self.pc += 1;
-            }
-            Opcode::RST2 => {
-                let (pchi, pclo) = split_bytes(self.pc + 1);
-                self.mem[self.sp as usize - 1] = pchi;
-                self.mem[self.sp as usize - 2] = pclo;
-                self.sp -= 2;
-                self.pc = 0x10 - 1;
-            }
-    
-            Opcode::RC => {
-                if self.flags.get(FlagType::CY) == 0b1 {
-                    self.pc = join_bytes(
-                        self.mem[self.sp as usize + 1],
-                        self.mem[self.sp as usize],
-                    ) - 1;
-                    self.sp += 2;
-                }
-            }
-            Opcode::JC => {
-                if self.flags.get(FlagType::CY) == 0b1 {
-                    self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
-                } else {
-                    self.pc += 2;
-                }
-            }
-            Opcode::IN => {
-                let port = self.mem[_pc + 1];
-                self.a = io.machine_in(port);
-                self.pc += 1;
-            }
-            Opcode::CC => {
-                if self.flags.get(FlagType::CY) == 0b1 {
-                    let (pchi, pclo) = split_bytes(self.pc + 3);
-                    self.mem[self.sp as usize - 1] = pchi;
-                    self.mem[self.sp as usize - 2] = pclo;
-                    self.sp -= 2;
-                    self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
-                } else {
-                    self.pc += 2;
-                }
-            }
-            Opcode::SBI => {
-                check_flag_cy8(
-                    (self.a as u16)
-                        - (self.mem[_pc + 1] as u16)
-                        - (self.flags.get(FlagType::CY) as u16),
-                    self,
-                );
-                self.a -= self.mem[_pc + 1] + self.flags.get(FlagType::CY);
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-                self.pc += 1;
-            }
-            Opcode::RST3 => {
-                let (pchi, pclo) = split_bytes(self.pc + 1);
-                self.mem[self.sp as usize - 1] = pchi;
-                self.mem[self.sp as usize - 2] = pclo;
-                self.sp -= 2;
-                self.pc = 0x18 - 1;
-            }
-    
-            Opcode::RPO => {
-                if self.flags.get(FlagType::P) != 0b1 {
-                    self.pc = join_bytes(
-                        self.mem[self.sp as usize + 1],
-                        self.mem[self.sp as usize],
-                    ) - 1;
-                    self.sp += 2;
-                }
-            }
-            Opcode::POPH => {
-                self.l = self.mem[self.sp as usize];
-                self.h = self.mem[self.sp as usize + 1];
-                self.sp += 2;
-            }
-            Opcode::JPO => {
-                if self.flags.get(FlagType::P) != 0b1 {
-                    self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
-                } else {
-                    self.pc += 2;
-                }
-            }
-            Opcode::XTHL => {
-                let tempsp = self.mem[self.sp as usize];
-                let tempsp1 = self.mem[self.sp as usize + 1];
-                self.mem[self.sp as usize] = self.l;
-                self.mem[self.sp as usize + 1] = self.h;
-                self.l = tempsp;
-                self.h = tempsp1;
-            }
-            Opcode::CPO => {
-                if self.flags.get(FlagType::P) != 0b1 {
-                    let (pchi, pclo) = split_bytes(self.pc + 3);
-                    self.mem[self.sp as usize - 1] = pchi;
-                    self.mem[self.sp as usize - 2] = pclo;
-                    self.sp -= 2;
-                    self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
-                } else {
-                    self.pc += 2;
-                }
-            }
-            Opcode::PUSHH => {
-                self.mem[self.sp as usize - 1] = self.h;
-                self.mem[self.sp as usize - 2] = self.l;
-                self.sp -= 2;
-            }
-            Opcode::ANI => {
-                check_flag_cy8((self.a as u16) & (self.mem[_pc + 1] as u16), self);
-                self.a &= self.mem[_pc + 1];
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-                self.pc += 1;
-            }
-            Opcode::RST4 => {
-                let (pchi, pclo) = split_bytes(self.pc + 1);
-                self.mem[self.sp as usize - 1] = pchi;
-                self.mem[self.sp as usize - 2] = pclo;
-                self.sp -= 2;
-                self.pc = 0x20 - 1;
-            }
-    
-            Opcode::RPE => {
-                if self.flags.get(FlagType::P) == 0b1 {
-                    self.pc = join_bytes(
-                        self.mem[self.sp as usize + 1],
-                        self.mem[self.sp as usize],
-                    ) - 1;
-                    self.sp += 2;
-                }
-            }
-            Opcode::PCHL => {
-                self.pc = join_bytes(self.h, self.l) - 1;
-            }
-            Opcode::JPE => {
-                if self.flags.get(FlagType::P) == 0b1 {
-                    self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
-                } else {
-                    self.pc += 2;
-                }
-            }
-            Opcode::XCHG => {
-                let temph = self.h;
-                let templ = self.l;
-                self.h = self.d;
-                self.l = self.e;
-                self.d = temph;
-                self.e = templ;
-            }
-            Opcode::CPE => {
-                if self.flags.get(FlagType::P) == 0b1 {
-                    let (pchi, pclo) = split_bytes(self.pc + 3);
-                    self.mem[self.sp as usize - 1] = pchi;
-                    self.mem[self.sp as usize - 2] = pclo;
-                    self.sp -= 2;
-                    self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
-                } else {
-                    self.pc += 2;
-                }
-            }
-            Opcode::XRI => {
-                check_flag_cy8((self.a as u16) ^ (self.mem[_pc + 1] as u16), self);
-                self.a ^= self.mem[_pc + 1];
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-                self.pc += 1;
-            }
-            Opcode::RST5 => {
-                let (pchi, pclo) = split_bytes(self.pc + 1);
-                self.mem[self.sp as usize - 1] = pchi;
-                self.mem[self.sp as usize - 2] = pclo;
-                self.sp -= 2;
-                self.pc = 0x28 - 1;
-            }
-    
-            Opcode::RP => {
-                if self.flags.get(FlagType::S) != 0b1 {
-                    self.pc = join_bytes(
-                        self.mem[self.sp as usize + 1],
-                        self.mem[self.sp as usize],
-                    ) - 1;
-                    self.sp += 2;
-                }
-            }
-            Opcode::POPPSW => {
-                self.flags.reg = self.mem[self.sp as usize];
-                self.a = self.mem[self.sp as usize + 1];
-                self.sp += 2;
-            }
-            Opcode::JP => {
-                if self.flags.get(FlagType::S) != 0b1 {
-                    self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
-                } else {
-                    self.pc += 2;
-                }
-            }
-            Opcode::DI => {
-                self.enable = 0;
-            }
-            Opcode::CP => {
-                if self.flags.get(FlagType::S) != 0b1 {
-                    self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
-                } else {
-                    self.pc += 2;
-                }
-            }
-            Opcode::PUSHPSW => {
-                self.mem[self.sp as usize - 2] = self.flags.reg;
-                self.mem[self.sp as usize - 1] = self.a;
-                self.sp -= 2;
-            }
-            Opcode::ORI => {
-                check_flag_cy8((self.a as u16) | (self.mem[_pc + 1] as u16), self);
-                self.a |= self.mem[_pc + 1];
-                check_flag_z(self.a, self);
-                check_flag_s(self.a, self);
-                check_flag_p(self.a, self);
-                self.pc += 1;
-            }
-            Opcode::RST6 => {
-                let (pchi, pclo) = split_bytes(self.pc + 1);
-                self.mem[self.sp as usize - 1] = pchi;
-                self.mem[self.sp as usize - 2] = pclo;
-                self.sp -= 2;
-                self.pc = 0x30 - 1;
-            }
-    
-            Opcode::RM => {
-                if self.flags.get(FlagType::S) == 0b1 {
-                    self.pc = join_bytes(
-                        self.mem[self.sp as usize + 1],
-                        self.mem[self.sp as usize],
-                    ) - 1;
-                    self.sp += 2;
-                }
-            }
-            Opcode::SPHL => {
-                self.sp = join_bytes(self.h, self.l);
-            }
-            Opcode::JM => {
-                if self.flags.get(FlagType::S) == 0b1 {
-                    self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
-                } else {
-                    self.pc += 2;
-                }
-            }
-            Opcode::EI => {
-                self.enable = 1;
+
+            0xc3 | 0xcb => jmp,
+            0xc9 | 0xd9 => ret,
+            0xcd | 0xdd | 0xed | 0xfd => call,
+            0xd3 => out,
+            0xdb => in_,
+            0xe3 => xthl,
+            0xe9 => pchl,
+            0xeb => xchg,
+            0xf3 => di,
+            0xf9 => sphl,
+            0xfb => ei,
+            _ => match op & 0b111 {
+                0b000 => rcc,
+                0b010 => jcc,
+                0b100 => ccc,
+                0b110 => alu_imm,
+                0b111 => rst,
+                _ if op & 0b1111 == 0x1 => pop,
+                _ => push,
+            },
+        };
+        i += 1;
+    }
+    table
+}
+
+// Register and register pair fields: DDD = bits 5-3, SSS = bits 2-0, RP = bits 5-4
+
+fn ddd(op: u8) -> Register {
+    Register::from_bits(op >> 3)
+}
+
+fn sss(op: u8) -> Register {
+    Register::from_bits(op)
+}
+
+fn rp(op: u8) -> RegPair {
+    RegPair::from_bits(op >> 4, false)
+}
+
+fn nop<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    state.timing(op)
+}
+
+fn hlt<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    state.halted = true;
+    state.timing(op)
+}
+
+fn mov<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let val = state.reg(bus, sss(op));
+    state.set_reg(bus, ddd(op), val);
+    state.timing(op)
+}
+
+fn mvi<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let val = state.fetch8(bus);
+    state.set_reg(bus, ddd(op), val);
+    state.timing(op)
+}
+
+fn inr<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let val = inr8(state.reg(bus, ddd(op)), state);
+    state.set_reg(bus, ddd(op), val);
+    state.timing(op)
+}
+
+fn dcr<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let val = dcr8(state.reg(bus, ddd(op)), state);
+    state.set_reg(bus, ddd(op), val);
+    state.timing(op)
+}
+
+/// ADD ADC SUB SBB ANA XRA ORA CMP, selected by bits 5-3
+fn alu_op(state: &mut State, op: u8, val: u8) {
+    let carry = state.flags.get(FlagType::CY);
+    let a = state.a;
+    state.a = match (op >> 3) & 0b111 {
+        0 => add8(a, val, 0, state),
+        1 => add8(a, val, carry, state),
+        2 => sub8(a, val, 0, state),
+        3 => sub8(a, val, carry, state),
+        4 => ana8(a, val, state),
+        5 => xra8(a, val, state),
+        6 => ora8(a, val, state),
+        _ => {
+            sub8(a, val, 0, state);
+            a
+        }
+    };
+}
+
+fn alu<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let val = state.reg(bus, sss(op));
+    alu_op(state, op, val);
+    state.timing(op)
+}
+
+fn alu_imm<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let val = state.fetch8(bus);
+    alu_op(state, op, val);
+    state.timing(op)
+}
+
+fn lxi<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let val = state.fetch16(bus);
+    state.set_pair(rp(op), val);
+    state.timing(op)
+}
+
+fn inx<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    let val = state.pair(rp(op)).wrapping_add(1);
+    state.set_pair(rp(op), val);
+    if state.cpu == Cpu::I8085 {
+        check_flag(FlagType::K, val == 0x0000, state);
+    }
+    state.timing(op)
+}
+
+fn dcx<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    let val = state.pair(rp(op)).wrapping_sub(1);
+    state.set_pair(rp(op), val);
+    if state.cpu == Cpu::I8085 {
+        check_flag(FlagType::K, val == 0xffff, state);
+    }
+    state.timing(op)
+}
+
+fn dad<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    let hl = state.pair(RegPair::H) as u32 + state.pair(rp(op)) as u32;
+    check_flag_cy16(hl, state);
+    state.set_pair(RegPair::H, hl as u16);
+    state.timing(op)
+}
+
+fn stax<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    bus.write(state.pair(rp(op)), state.a);
+    state.timing(op)
+}
+
+fn ldax<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    state.a = bus.read(state.pair(rp(op)));
+    state.timing(op)
+}
+
+fn shld<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let adr = state.fetch16(bus);
+    bus.write(adr, state.l);
+    bus.write(adr.wrapping_add(1), state.h);
+    state.timing(op)
+}
+
+fn lhld<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let adr = state.fetch16(bus);
+    state.l = bus.read(adr);
+    state.h = bus.read(adr.wrapping_add(1));
+    state.timing(op)
+}
+
+fn sta<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let adr = state.fetch16(bus);
+    bus.write(adr, state.a);
+    state.timing(op)
+}
+
+fn lda<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let adr = state.fetch16(bus);
+    state.a = bus.read(adr);
+    state.timing(op)
+}
+
+fn rlc<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    check_flag_cy8((state.a as u16) << 1, state);
+    state.a = state.a.rotate_left(1);
+    state.timing(op)
+}
+
+fn rrc<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    check_flag_cy8((state.a as u16).rotate_right(1), state);
+    state.a = state.a.rotate_right(1);
+    state.timing(op)
+}
+
+fn ral<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    let prev = state.flags.get(FlagType::CY);
+    check_flag_cy8((state.a as u16) << 1, state);
+    state.a = (state.a << 1) | prev;
+    state.timing(op)
+}
+
+fn rar<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    let bit7 = state.flags.get(FlagType::CY);
+    check_flag_cy8((state.a as u16).rotate_right(1), state);
+    state.a = (state.a >> 1) | (bit7 << 7);
+    state.timing(op)
+}
+
+fn daa_<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    daa(state);
+    state.timing(op)
+}
+
+fn cma<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    state.a = !state.a;
+    state.timing(op)
+}
+
+fn stc<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    state.flags.set(FlagType::CY);
+    state.timing(op)
+}
+
+fn cmc<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    match state.flags.get(FlagType::CY) == 0 {
+        true => state.flags.set(FlagType::CY),
+        false => state.flags.unset(FlagType::CY),
+    }
+    state.timing(op)
+}
+
+fn jmp<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    state.pc = state.fetch16(bus);
+    state.timing(op)
+}
+
+fn jcc<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let adr = state.fetch16(bus);
+    if state.condition(op >> 3) {
+        state.pc = adr;
+        state.timing_taken(op)
+    } else {
+        state.timing(op)
+    }
+}
+
+fn call<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let adr = state.fetch16(bus);
+    state.push(bus, state.pc);
+    state.pc = adr;
+    state.timing(op)
+}
+
+fn ccc<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    if state.condition(op >> 3) {
+        call(state, bus, op);
+        state.timing_taken(op)
+    } else {
+        state.pc = state.pc.wrapping_add(2);
+        state.timing(op)
+    }
+}
+
+fn ret<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    state.pc = state.pop(bus);
+    state.timing(op)
+}
+
+fn rcc<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    if state.condition(op >> 3) {
+        ret(state, bus, op);
+        state.timing_taken(op)
+    } else {
+        state.timing(op)
+    }
+}
+
+fn rst<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    state.push(bus, state.pc);
+    state.pc = (op & 0b0011_1000) as u16;
+    state.timing(op)
+}
+
+fn push<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    state.push(bus, state.pair(RegPair::from_bits(op >> 4, true)));
+    state.timing(op)
+}
+
+fn pop<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let val = state.pop(bus);
+    state.set_pair(RegPair::from_bits(op >> 4, true), val);
+    state.timing(op)
+}
+
+fn xthl<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let hl = state.pair(RegPair::H);
+    let top = state.pop(bus);
+    state.set_pair(RegPair::H, top);
+    state.push(bus, hl);
+    state.timing(op)
+}
+
+fn pchl<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    state.pc = state.pair(RegPair::H);
+    state.timing(op)
+}
+
+fn sphl<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    state.sp = state.pair(RegPair::H);
+    state.timing(op)
+}
+
+fn xchg<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    (state.h, state.d) = (state.d, state.h);
+    (state.l, state.e) = (state.e, state.l);
+    state.timing(op)
+}
+
+fn out<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let port = state.fetch8(bus);
+    bus.output(port, state.a);
+    state.timing(op)
+}
+
+fn in_<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let port = state.fetch8(bus);
+    state.a = bus.input(port);
+    state.timing(op)
+}
+
+fn di<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    state.enable = 0;
+    state.timing(op)
+}
+
+fn ei<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    state.enable = 1;
+    state.ei_delay = true;
+    state.timing(op)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::arch::instruction::decode_as;
+    use crate::emulator::arch::memory::{Memory, WritePolicy};
+
+    fn state_with(program: &[u8]) -> (State, Memory) {
+        (State::new(), Memory::new(program.to_vec()))
+    }
+
+    #[test]
+    fn cycles() {
+        // MVI A,0 ; CALL 0010 ; ... 0010: RNZ ; RZ
+        let (mut state, mut mem) = state_with(&[0x3e, 0x00, 0xcd, 0x10, 0x00]);
+        mem.data[0x10] = 0xc0;
+        mem.data[0x11] = 0xc8;
+
+        assert_eq!(state.run_op(&mut mem).unwrap().cycles(), 7);
+        assert_eq!(state.run_op(&mut mem).unwrap().cycles(), 17);
+        assert_eq!(state.pc, 0x10);
+        assert_eq!(state.cycles, 24);
+    }
+
+    #[test]
+    fn conditional_cycles() {
+        // XRA A ; CNZ 0010 ; CZ 0010 ; ... 0010: RNZ ; RZ
+        let (mut state, mut mem) = state_with(&[0xaf, 0xc4, 0x10, 0x00, 0xcc, 0x10, 0x00]);
+        mem.data[0x10] = 0xc0;
+        mem.data[0x11] = 0xc8;
+
+        assert_eq!(state.run_op(&mut mem).unwrap().cycles(), 4);
+        assert_eq!(state.run_op(&mut mem).unwrap().cycles(), 11);
+        assert_eq!(state.run_op(&mut mem).unwrap().cycles(), 17);
+        assert_eq!(state.run_op(&mut mem).unwrap().cycles(), 5);
+        assert_eq!(state.run_op(&mut mem).unwrap().cycles(), 11);
+        assert_eq!(state.pc, 0x07);
+        assert_eq!(state.cycles, 48);
+    }
+
+    #[test]
+    fn condition_codes() {
+        // JNZ JZ JNC JC JPO JPE JP JM 0010 with Z and P set
+        let taken = [false, true, true, false, false, true, true, false];
+
+        for (cc, taken) in taken.into_iter().enumerate() {
+            let (mut state, mut mem) = state_with(&[0xc2 | (cc as u8) << 3, 0x10, 0x00]);
+            state.flags.set_psw(0b0100_0100);
+            state.run_op(&mut mem).unwrap();
+            assert_eq!(state.pc == 0x10, taken, "cc {}", cc);
+        }
+    }
+
+    #[test]
+    fn daa() {
+        // MVI A,19 ; ADI 28 ; DAA ; ADI 53 ; DAA
+        let (mut state, mut mem) = state_with(&[0x3e, 0x19, 0xc6, 0x28, 0x27, 0xc6, 0x53, 0x27]);
+
+        state.run_op(&mut mem).unwrap();
+        state.run_op(&mut mem).unwrap();
+        assert_eq!(state.a, 0x41);
+        assert_eq!(state.flags.get(FlagType::AC), 1);
+        state.run_op(&mut mem).unwrap();
+        assert_eq!(state.a, 0x47);
+        assert_eq!(state.flags.get(FlagType::CY), 0);
+
+        state.run_op(&mut mem).unwrap();
+        state.run_op(&mut mem).unwrap();
+        assert_eq!(state.a, 0x00);
+        assert_eq!(state.flags.get(FlagType::CY), 1);
+        assert_eq!(state.flags.get(FlagType::Z), 1);
+    }
+
+    #[test]
+    fn auxiliary_carry() {
+        // MVI A,10 ; SUI 01 ; INR A ; DCR A ; ANI 08
+        let (mut state, mut mem) = state_with(&[0x3e, 0x10, 0xd6, 0x01, 0x3c, 0x3d, 0xe6, 0x08]);
+
+        state.run_op(&mut mem).unwrap();
+        state.run_op(&mut mem).unwrap();
+        assert_eq!(state.a, 0x0f);
+        assert_eq!(state.flags.get(FlagType::AC), 0);
+        assert_eq!(state.flags.get(FlagType::CY), 0);
+        assert_eq!(state.flags.get(FlagType::P), 1);
+
+        state.run_op(&mut mem).unwrap();
+        assert_eq!(state.a, 0x10);
+        assert_eq!(state.flags.get(FlagType::AC), 1);
+
+        state.run_op(&mut mem).unwrap();
+        assert_eq!(state.a, 0x0f);
+        assert_eq!(state.flags.get(FlagType::AC), 0);
+
+        state.run_op(&mut mem).unwrap();
+        assert_eq!(state.a, 0x08);
+        assert_eq!(state.flags.get(FlagType::AC), 1);
+        assert_eq!(state.flags.get(FlagType::P), 0);
+    }
+
+    #[test]
+    fn undocumented_aliases() {
+        // 0000: 0x08 (NOP) ; 0xcb JMP 0010 ... 0010: 0xdd CALL 0020 ; 0x38 (NOP) ... 0020: 0xd9 RET
+        let (mut state, mut mem) = state_with(&[0x08, 0xcb, 0x10, 0x00]);
+        mem.data[0x10..0x14].copy_from_slice(&[0xdd, 0x20, 0x00, 0x38]);
+        mem.data[0x20] = 0xd9;
+
+        assert_eq!(state.run_op(&mut mem).unwrap().cycles(), 4);
+        assert_eq!(state.pc, 0x01);
+        assert_eq!(state.run_op(&mut mem).unwrap().cycles(), 10);
+        assert_eq!(state.pc, 0x10);
+        assert_eq!(state.run_op(&mut mem).unwrap().cycles(), 17);
+        assert_eq!(state.pc, 0x20);
+        assert_eq!(state.run_op(&mut mem).unwrap().cycles(), 10);
+        assert_eq!(state.pc, 0x13);
+        assert_eq!(state.run_op(&mut mem).unwrap().cycles(), 4);
+        assert_eq!(state.pc, 0x14);
+    }
+
+    #[test]
+    fn wraparound() {
+        for opcode in 0..=0xff {
+            for sp in [0x0000, 0x0001, 0xffff] {
+                let (mut state, mut mem) = state_with(&[0xff, 0xff]);
+                mem.data[0xffff] = opcode;
+                state.pc = 0xffff;
+                state.sp = sp;
+                (state.a, state.b, state.c, state.d) = (0xff, 0xff, 0xff, 0xff);
+                (state.e, state.h, state.l) = (0xff, 0xff, 0xff);
+                state.flags.set_psw(0xff);
+
+                state.run_op(&mut mem).unwrap();
             }
-            Opcode::CM => {
-                if self.flags.get(FlagType::S) == 0b1 {
-                    let (pchi, pclo) = split_bytes(self.pc + 3);
-                    self.mem[self.sp as usize - 1] = pchi;
-                    self.mem[self.sp as usize - 2] = pclo;
-                    self.sp -= 2;
-                    self.pc = join_bytes(self.mem[_pc + 2], self.mem[_pc + 1]) - 1;
+        }
+
+        // RST 0 lands on 0x0000 and pushes the wrapped return address
+        let (mut state, mut mem) = state_with(&[]);
+        mem.data[0xffff] = 0xc7;
+        state.pc = 0xffff;
+        state.sp = 0x0001;
+        state.run_op(&mut mem).unwrap();
+        assert_eq!(state.pc, 0x0000);
+        assert_eq!(state.sp, 0xffff);
+        assert_eq!(state.pop(&mut mem), 0x0000);
+    }
+
+    #[test]
+    fn halt() {
+        // EI ; HLT ; INR A
+        let (mut state, mut mem) = state_with(&[0xfb, 0x76, 0x3c]);
+
+        state.run_op(&mut mem).unwrap();
+        assert_eq!(state.run_op(&mut mem), Ok(StepOutcome::Halted(7)));
+        assert!(state.halted);
+
+        for _ in 0..10 {
+            assert_eq!(state.run_op(&mut mem).unwrap().cycles(), HALT_CYCLES);
+        }
+        assert_eq!(state.pc, 0x02);
+        assert_eq!(state.cycles, 4 + 7 + 10 * HALT_CYCLES as u64);
+
+        state.request_interrupt(Interrupt::Rst(1));
+        assert_eq!(state.run_op(&mut mem), Ok(StepOutcome::Interrupted(11)));
+        assert!(!state.halted);
+        assert_eq!(state.pc, 0x08);
+        assert_eq!(state.pop(&mut mem), 0x02);
+    }
+
+    #[test]
+    fn interrupt_latch() {
+        // NOP ; EI ; NOP ; NOP
+        let (mut state, mut mem) = state_with(&[0x00, 0xfb, 0x00, 0x00]);
+
+        // Latched while interrupts are disabled
+        state.request_interrupt(Interrupt::Call(0x1234));
+        assert_eq!(state.run_op(&mut mem), Ok(StepOutcome::Executed(4)));
+        assert_eq!(state.interrupt, Some(Interrupt::Call(0x1234)));
+
+        // EI, then one more instruction before it is accepted
+        state.run_op(&mut mem).unwrap();
+        state.run_op(&mut mem).unwrap();
+        assert_eq!(state.pc, 0x03);
+        assert_eq!(state.run_op(&mut mem), Ok(StepOutcome::Interrupted(17)));
+        assert_eq!(state.pc, 0x1234);
+        assert_eq!(state.enable, 0);
+        assert_eq!(state.interrupt, None);
+        assert_eq!(state.pop(&mut mem), 0x03);
+    }
+
+    #[test]
+    fn trap_undocumented() {
+        let (mut state, mut mem) = state_with(&[0x00, 0xdd, 0x00, 0x00]);
+        state.trap_undocumented = true;
+
+        assert_eq!(state.run_op(&mut mem), Ok(StepOutcome::Executed(4)));
+        assert_eq!(
+            state.run_op(&mut mem),
+            Err(CpuError::Illegal { pc: 0x01, opcode: 0xdd })
+        );
+        assert_eq!(state.pc, 0x01);
+        assert_eq!(state.cycles, 4);
+    }
+
+    #[test]
+    fn bus_fault_leaves_instruction_unexecuted() {
+        // EI ; INR M with HL on the rom it runs from
+        let (mut state, mut mem) = state_with(&[0xfb, 0x34]);
+        mem.map.rom(0x0000, 0x400);
+        mem.map.policy = WritePolicy::Trap;
+        state.run_op(&mut mem).unwrap();
+        let flags = state.flags.reg;
+
+        for _ in 0..2 {
+            assert_eq!(
+                state.run_op(&mut mem),
+                Err(CpuError::BusFault { pc: 0x01, opcode: 0x34, adr: 0x0000 })
+            );
+            assert_eq!((state.pc, state.flags.reg, state.cycles), (0x01, flags, 4));
+            assert!(state.ei_delay);
+        }
+        assert_eq!(mem.data[0x0000], 0xfb);
+    }
+
+    #[test]
+    fn bus_fault_in_acknowledge() {
+        // EI ; NOP ; NOP with the stack running down into rom
+        let (mut state, mut mem) = state_with(&[0xfb, 0x00, 0x00]);
+        mem.map.rom(0x0000, 0x400);
+        mem.map.policy = WritePolicy::Trap;
+        state.sp = 0x0401;
+        state.run_op(&mut mem).unwrap();
+        state.run_op(&mut mem).unwrap();
+
+        state.request_interrupt(Interrupt::Rst(1));
+        assert_eq!(
+            state.run_op(&mut mem),
+            Err(CpuError::BusFault { pc: 0x02, opcode: 0xcf, adr: 0x03ff })
+        );
+        assert_eq!((state.pc, state.sp, state.enable), (0x02, 0x0401, 1));
+        assert_eq!(state.interrupt, Some(Interrupt::Rst(1)));
+    }
+
+    #[test]
+    fn runs_as_decoded() {
+        // Every opcode with 0110 after it: anything not landing on the next
+        // instruction must have jumped and taken its condition
+        for cpu in [Cpu::I8080, Cpu::I8085, Cpu::Z80] {
+            for opcode in 0..=0xff_u8 {
+                let mut mem = Memory::new(vec![]);
+                mem.data[0x100..0x104].copy_from_slice(&[opcode, 0x10, 0x01, 0x00]);
+                let mut state = State::with_cpu(cpu);
+                (state.pc, state.sp) = (0x100, 0x2000);
+
+                let instr = decode_as(cpu, &mem.data, 0x100);
+                let cycles = state.run_op(&mut mem).unwrap().cycles();
+                if state.pc == 0x100 + instr.length as u16 {
+                    assert_eq!(cycles, instr.cycles, "{:?} {}", cpu, instr);
                 } else {
-                    self.pc += 2;
+                    assert_eq!(cycles, instr.cycles_taken, "{:?} {}", cpu, instr);
                 }
             }
-            Opcode::CPI => {
-                check_flag_cy8((self.a as u16) - (self.mem[_pc + 1] as u16), self);
-                check_flag_z(self.a - self.mem[_pc + 1], self);
-                check_flag_s(self.a - self.mem[_pc + 1], self);
-                check_flag_p(self.a - self.mem[_pc + 1], self);
-                self.pc += 1;
-            }
-            Opcode::RST7 => {
-                let (pchi, pclo) = split_bytes(self.pc + 1);
-                self.mem[self.sp as usize - 1] = pchi;
-                self.mem[self.sp as usize - 2] = pclo;
-                self.sp -= 2;
-                self.pc = 0x38 - 1;
-            }
-    
-            // Not implemented Instructions
-            Opcode::NIMP(x) => {
-                println!("Instruction {:#04x} not implemented", x);
-                process::exit(-1);
-            }
-        };
-    
-        self.pc += 1;
+        }
     }
 }
@@ -0,0 +1,234 @@
+use super::arch::{
+    flag::FlagType,
+    i8085::{self, Input},
+    instruction::RegPair,
+    interrupt::Interrupt,
+    state::State,
+};
+use super::bus::Bus;
+use super::iset::{dispatch_table as dispatch_table_8080, Handler};
+use super::step::CpuError;
+use super::utils::*;
+
+/// T-states of accepting TRAP or one of the RST n.5 inputs
+const ACKNOWLEDGE_CYCLES: u8 = 12;
+
+impl State {
+    /// Accepts one of the 8085 interrupt inputs
+    pub(super) fn acknowledge_8085<B: Bus>(&mut self, bus: &mut B, input: Input) -> Result<u8, CpuError> {
+        match input {
+            Input::Trap => {
+                self.pins.trap = false;
+                self.pins.trap_ie = Some(self.enable == 1);
+            }
+            Input::Rst7_5 => self.pins.rst7_5 = false,
+            Input::Rst6_5 | Input::Rst5_5 => (),
+        }
+        self.acknowledge(bus, Interrupt::Call(input.vector()), ACKNOWLEDGE_CYCLES)
+    }
+}
+
+/// The 8080 table with the opcodes the 8085 gives a meaning to replaced
+pub(super) const fn dispatch_table<B: Bus>() -> [Handler<B>; 256] {
+    let mut table = dispatch_table_8080::<B>();
+    table[0x08] = dsub;
+    table[0x10] = arhl;
+    table[0x18] = rdel;
+    table[0x20] = rim;
+    table[0x28] = ldhi;
+    table[0x30] = sim;
+    table[0x38] = ldsi;
+    table[0xcb] = rstv;
+    table[0xd9] = shlx;
+    table[0xdd] = jnk;
+    table[0xed] = lhlx;
+    table[0xfd] = jk;
+    table
+}
+
+/// DSUB: HL = HL - BC, Z reflects the whole 16 bit result
+fn dsub<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    let l = sub8(state.l, state.c, 0, state);
+    let borrow = state.flags.get(FlagType::CY);
+    let h = sub8(state.h, state.b, borrow, state);
+    (state.h, state.l) = (h, l);
+    check_flag(FlagType::Z, h | l == 0, state);
+    state.timing(op)
+}
+
+/// ARHL: arithmetic shift right of HL, bit 0 goes to CY
+fn arhl<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    let hl = state.pair(RegPair::H);
+    check_flag(FlagType::CY, hl & 0b1 != 0, state);
+    state.set_pair(RegPair::H, ((hl as i16) >> 1) as u16);
+    state.timing(op)
+}
+
+/// RDEL: rotates DE left through CY, V is set when the sign changes
+fn rdel<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    let de = state.pair(RegPair::D);
+    let res = (de << 1) | state.flags.get(FlagType::CY) as u16;
+    check_flag(FlagType::CY, de & 0x8000 != 0, state);
+    check_flag(FlagType::V, (de ^ res) & 0x8000 != 0, state);
+    state.set_pair(RegPair::D, res);
+    state.timing(op)
+}
+
+fn rim<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    state.a = state.pins.rim(state.enable);
+    state.timing(op)
+}
+
+fn sim<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    state.pins.sim(state.a);
+    state.timing(op)
+}
+
+/// LDHI: DE = HL + d8
+fn ldhi<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let val = state.fetch8(bus) as u16;
+    state.set_pair(RegPair::D, state.pair(RegPair::H).wrapping_add(val));
+    state.timing(op)
+}
+
+/// LDSI: DE = SP + d8
+fn ldsi<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let val = state.fetch8(bus) as u16;
+    state.set_pair(RegPair::D, state.sp.wrapping_add(val));
+    state.timing(op)
+}
+
+/// RSTV: restart at 0x40 when V is set
+fn rstv<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    if state.flags.get(FlagType::V) == 1 {
+        state.push(bus, state.pc);
+        state.pc = i8085::RSTV_VECTOR;
+        state.timing_taken(op)
+    } else {
+        state.timing(op)
+    }
+}
+
+/// SHLX: stores HL at the address in DE
+fn shlx<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let adr = state.pair(RegPair::D);
+    bus.write(adr, state.l);
+    bus.write(adr.wrapping_add(1), state.h);
+    state.timing(op)
+}
+
+/// LHLX: loads HL from the address in DE
+fn lhlx<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let adr = state.pair(RegPair::D);
+    state.l = bus.read(adr);
+    state.h = bus.read(adr.wrapping_add(1));
+    state.timing(op)
+}
+
+fn jump_if<B: Bus>(state: &mut State, bus: &mut B, op: u8, cond: bool) -> u8 {
+    let adr = state.fetch16(bus);
+    if cond {
+        state.pc = adr;
+        state.timing_taken(op)
+    } else {
+        state.timing(op)
+    }
+}
+
+fn jnk<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let cond = state.flags.get(FlagType::K) == 0;
+    jump_if(state, bus, op, cond)
+}
+
+fn jk<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let cond = state.flags.get(FlagType::K) == 1;
+    jump_if(state, bus, op, cond)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::arch::{memory::Memory, state::Cpu};
+
+    fn state_with(program: &[u8]) -> (State, Memory) {
+        let mut state = State::with_cpu(Cpu::I8085);
+        state.sp = 0x100;
+        (state, Memory::new(program.to_vec()))
+    }
+
+    #[test]
+    fn rim_sim() {
+        // MVI A,1B ; SIM ; EI ; RIM
+        let (mut state, mut mem) = state_with(&[0x3e, 0x1b, 0x30, 0xfb, 0x20]);
+        state.pins.set_input(Input::Rst7_5, true);
+        state.pins.sid = true;
+
+        for _ in 0..4 {
+            state.run_op(&mut mem).unwrap();
+        }
+        // 7.5 reset by SIM, masks 7.5 and 5.5 set, interrupts enabled
+        assert_eq!(state.a, 0b1000_1011);
+        assert_eq!(state.cycles, 7 + 4 + 4 + 4);
+    }
+
+    #[test]
+    fn dsub_jnk() {
+        // LXI H,8000 ; LXI B,0001 ; DSUB ; JNK 0000 ; JK 0020
+        let (mut state, mut mem) =
+            state_with(&[0x21, 0x00, 0x80, 0x01, 0x01, 0x00, 0x08, 0xdd, 0x00, 0x00, 0xfd, 0x20, 0x00]);
+
+        for _ in 0..3 {
+            state.run_op(&mut mem).unwrap();
+        }
+        assert_eq!(state.pair(RegPair::H), 0x7fff);
+        assert_eq!(state.flags.get(FlagType::CY), 0);
+        assert_eq!(state.flags.get(FlagType::V), 1);
+        assert_eq!(state.flags.get(FlagType::K), 1);
+
+        assert_eq!(state.run_op(&mut mem).unwrap().cycles(), 7);
+        assert_eq!(state.run_op(&mut mem).unwrap().cycles(), 10);
+        assert_eq!(state.pc, 0x20);
+    }
+
+    #[test]
+    fn ana_sets_ac() {
+        // MVI A,F0 ; ANI 03 ; ANA B
+        let (mut state, mut mem) = state_with(&[0x3e, 0xf0, 0xe6, 0x03, 0xa0]);
+
+        for _ in 0..2 {
+            state.run_op(&mut mem).unwrap();
+        }
+        assert_eq!(state.a, 0);
+        assert_eq!(state.flags.get(FlagType::AC), 1);
+
+        state.flags.unset(FlagType::AC);
+        state.run_op(&mut mem).unwrap();
+        assert_eq!(state.flags.get(FlagType::AC), 1);
+    }
+
+    #[test]
+    fn trap_priority() {
+        // EI ; NOP ; NOP
+        let (mut state, mut mem) = state_with(&[0xfb, 0x00, 0x00]);
+        state.run_op(&mut mem).unwrap();
+        state.pins.set_input(Input::Rst5_5, true);
+        state.pins.set_input(Input::Trap, true);
+
+        assert_eq!(state.run_op(&mut mem).unwrap().cycles(), 12);
+        assert_eq!(state.pc, i8085::TRAP_VECTOR);
+        assert_eq!(state.enable, 0);
+
+        // RST 5.5 waits for interrupts to be enabled again, TRAP does not
+        state.pc = 0x01;
+        state.run_op(&mut mem).unwrap();
+        assert_eq!(state.pc, 0x02);
+        state.enable = 1;
+        state.run_op(&mut mem).unwrap();
+        assert_eq!(state.pc, 0x2c);
+
+        // RIM after TRAP reports the enable from before it
+        mem.data[0x2c] = 0x20;
+        state.run_op(&mut mem).unwrap();
+        assert_eq!(state.a & 0b1000, 0b1000);
+    }
+}
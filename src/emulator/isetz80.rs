@@ -0,0 +1,615 @@
+use super::arch::{
+    flag::FlagType,
+    instruction::{RegPair, Register},
+    interrupt::Interrupt,
+    state::State,
+    z80::{self, Index},
+};
+use super::bus::Bus;
+use super::iset::{dispatch_table as dispatch_table_8080, Dispatch, Handler};
+use super::step::CpuError;
+use super::utils::*;
+
+/// T-states of accepting an NMI
+const NMI_CYCLES: u8 = 11;
+
+impl State {
+    /// Accepts an NMI. IFF2 keeps the enable so RETN can restore it.
+    pub(super) fn acknowledge_nmi<B: Bus>(&mut self, bus: &mut B) -> Result<u8, CpuError> {
+        self.z80.refresh();
+        self.acknowledge(bus, Interrupt::Call(z80::NMI_VECTOR), NMI_CYCLES)
+    }
+
+    /// Accepts INTR in the current interrupt mode
+    pub(super) fn acknowledge_z80<B: Bus>(&mut self, bus: &mut B, int: Interrupt) -> Result<u8, CpuError> {
+        self.z80.iff2 = false;
+        self.z80.refresh();
+        match self.z80.im {
+            // The device's instruction runs as on the 8080, with two wait states
+            0 => self.acknowledge(bus, int, int.cycles() + 2),
+            1 => self.acknowledge(bus, Interrupt::Call(z80::IM1_VECTOR), 13),
+            _ => {
+                let adr = join_bytes(self.z80.i, int.data());
+                let vector = join_bytes(bus.read(adr.wrapping_add(1)), bus.read(adr));
+                self.acknowledge(bus, Interrupt::Call(vector), 19)
+            }
+        }
+    }
+}
+
+/// The 8080 table with the Z80's new opcodes, prefixes and flag behaviour
+pub(super) const fn dispatch_table<B: Bus>() -> [Handler<B>; 256] {
+    let mut table = dispatch_table_8080::<B>();
+    table[0x07] = rotate_a;
+    table[0x0f] = rotate_a;
+    table[0x17] = rotate_a;
+    table[0x1f] = rotate_a;
+    table[0x27] = daa_;
+    table[0x2f] = cpl;
+    table[0x37] = scf;
+    table[0x3f] = ccf;
+    table[0x09] = add_hl;
+    table[0x19] = add_hl;
+    table[0x29] = add_hl;
+    table[0x39] = add_hl;
+
+    table[0x08] = ex_af;
+    table[0x10] = djnz;
+    table[0x18] = jr;
+    table[0x20] = jr_cc;
+    table[0x28] = jr_cc;
+    table[0x30] = jr_cc;
+    table[0x38] = jr_cc;
+    table[0xd9] = exx;
+    table[0xf3] = di;
+    table[0xfb] = ei;
+
+    table[0xcb] = cb;
+    table[0xdd] = indexed;
+    table[0xed] = ed;
+    table[0xfd] = indexed;
+    table
+}
+
+/// RLCA RRCA RLA RRA: as on the 8080, also clearing H and N
+fn rotate_a<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let cycles = Dispatch::<B>::TABLE[op as usize](state, bus, op);
+    state.flags.unset(FlagType::AC);
+    state.flags.unset(FlagType::N);
+    cycles
+}
+
+/// DAA: adjusts A after an addition or, with N set, a subtraction
+fn daa_<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    let a = state.a;
+    let sub = state.flags.get(FlagType::N) == 1;
+    let half = state.flags.get(FlagType::AC) == 1;
+    let mut carry = state.flags.get(FlagType::CY) == 1;
+
+    let mut correction = 0;
+    if half || a & 0x0f > 9 {
+        correction |= 0x06;
+    }
+    if carry || a > 0x99 {
+        correction |= 0x60;
+        carry = true;
+    }
+
+    state.a = match sub {
+        true => a.wrapping_sub(correction),
+        false => a.wrapping_add(correction),
+    };
+    check_flags_zsp(state.a, state);
+    check_flag(FlagType::CY, carry, state);
+    check_flag(FlagType::AC, if sub { half && a & 0x0f < 6 } else { a & 0x0f > 9 }, state);
+    state.timing(op)
+}
+
+fn cpl<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    state.a = !state.a;
+    state.flags.set(FlagType::AC);
+    state.flags.set(FlagType::N);
+    state.timing(op)
+}
+
+fn scf<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    state.flags.set(FlagType::CY);
+    state.flags.unset(FlagType::AC);
+    state.flags.unset(FlagType::N);
+    state.timing(op)
+}
+
+/// CCF: H takes the old carry
+fn ccf<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    let carry = state.flags.get(FlagType::CY) == 1;
+    check_flag(FlagType::AC, carry, state);
+    check_flag(FlagType::CY, !carry, state);
+    state.flags.unset(FlagType::N);
+    state.timing(op)
+}
+
+/// ADD HL,rr: like DAD, with H from bit 11
+fn add_hl<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    let hl = state.pair(RegPair::H);
+    let val = state.pair(RegPair::from_bits(op >> 4, false));
+    let res = hl as u32 + val as u32;
+    check_flag_cy16(res, state);
+    check_flag(FlagType::AC, (hl & 0x0fff) + (val & 0x0fff) > 0x0fff, state);
+    state.flags.unset(FlagType::N);
+    state.set_pair(RegPair::H, res as u16);
+    state.timing(op)
+}
+
+fn ex_af<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    let af = state.pair(RegPair::PSW);
+    state.set_pair(RegPair::PSW, state.z80.af_);
+    state.z80.af_ = af;
+    state.timing(op)
+}
+
+fn exx<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    let (bc, de, hl) = (state.pair(RegPair::B), state.pair(RegPair::D), state.pair(RegPair::H));
+    state.set_pair(RegPair::B, state.z80.bc_);
+    state.set_pair(RegPair::D, state.z80.de_);
+    state.set_pair(RegPair::H, state.z80.hl_);
+    (state.z80.bc_, state.z80.de_, state.z80.hl_) = (bc, de, hl);
+    state.timing(op)
+}
+
+/// Relative jump by the displacement following the opcode
+fn jump_relative<B: Bus>(state: &mut State, bus: &mut B, op: u8, cond: bool) -> u8 {
+    let d = state.fetch8(bus) as i8;
+    if cond {
+        state.pc = state.pc.wrapping_add(d as u16);
+        state.timing_taken(op)
+    } else {
+        state.timing(op)
+    }
+}
+
+fn jr<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    jump_relative(state, bus, op, true)
+}
+
+/// JR NZ, Z, NC and C use the first four condition codes
+fn jr_cc<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    let cond = state.condition((op >> 3) - 4);
+    jump_relative(state, bus, op, cond)
+}
+
+fn djnz<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> u8 {
+    state.b = state.b.wrapping_sub(1);
+    let cond = state.b != 0;
+    jump_relative(state, bus, op, cond)
+}
+
+fn di<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    state.enable = 0;
+    state.z80.iff2 = false;
+    state.timing(op)
+}
+
+fn ei<B: Bus>(state: &mut State, _: &mut B, op: u8) -> u8 {
+    state.enable = 1;
+    state.z80.iff2 = true;
+    state.ei_delay = true;
+    state.timing(op)
+}
+
+/// CB prefix: rotates, shifts and bit operations on a register or (HL)
+fn cb<B: Bus>(state: &mut State, bus: &mut B, _: u8) -> u8 {
+    let op = state.fetch8(bus);
+    state.z80.refresh();
+
+    let r = Register::from_bits(op);
+    let val = state.reg(bus, r);
+    if let Some(res) = bit_op(state, op, val) {
+        state.set_reg(bus, r, res);
+    }
+    z80::cb_cycles(op)
+}
+
+/// Runs CB prefixed `op` on `val`, returning the result unless it is a BIT
+fn bit_op(state: &mut State, op: u8, val: u8) -> Option<u8> {
+    let y = (op >> 3) & 0b111;
+    match op >> 6 {
+        0 => Some(shift(state, y, val)),
+        1 => {
+            let set = val & (1 << y) != 0;
+            check_flag(FlagType::Z, !set, state);
+            check_flag(FlagType::P, !set, state);
+            check_flag(FlagType::S, y == 7 && set, state);
+            state.flags.set(FlagType::AC);
+            state.flags.unset(FlagType::N);
+            None
+        }
+        2 => Some(val & !(1 << y)),
+        _ => Some(val | (1 << y)),
+    }
+}
+
+/// RLC RRC RL RR SLA SRA SLL SRL, selected by `y`
+fn shift(state: &mut State, y: u8, val: u8) -> u8 {
+    let carry = state.flags.get(FlagType::CY);
+    let (res, out) = match y {
+        0 => (val.rotate_left(1), val >> 7),
+        1 => (val.rotate_right(1), val & 1),
+        2 => (val << 1 | carry, val >> 7),
+        3 => (val >> 1 | carry << 7, val & 1),
+        4 => (val << 1, val >> 7),
+        5 => (((val as i8) >> 1) as u8, val & 1),
+        6 => (val << 1 | 1, val >> 7),
+        _ => (val >> 1, val & 1),
+    };
+    check_flags_zsp(res, state);
+    check_flag(FlagType::CY, out == 1, state);
+    state.flags.unset(FlagType::AC);
+    state.flags.unset(FlagType::N);
+    res
+}
+
+/// DD and FD prefixes: runs the next opcode with IX or IY standing in for
+/// HL, and (IX+d) or (IY+d) for (HL)
+fn indexed<B: Bus>(state: &mut State, bus: &mut B, prefix: u8) -> u8 {
+    let index = Index::from_prefix(prefix);
+    let op = bus.read(state.pc);
+    match op {
+        // Another prefix takes over, this one was a NOP
+        0xdd | 0xed | 0xfd => return state.timing(prefix),
+        0xcb => return indexed_cb(state, bus, index),
+        _ => (),
+    }
+    state.pc = state.pc.wrapping_add(1);
+    state.z80.refresh();
+
+    let handler = Dispatch::<B>::TABLE_Z80[op as usize];
+    let hl = state.pair(RegPair::H);
+    let cycles = if z80::uses_hl_memory(op) {
+        let d = state.fetch8(bus) as i8;
+        let adr = state.z80.index(index).wrapping_add(d as u16);
+        if let 0x74 | 0x75 = op {
+            // LD (IX+d),H and LD (IX+d),L store the real H and L
+            bus.write(adr, if op == 0x74 { state.h } else { state.l });
+            return state.timing(op) + (z80::indexed_cycles(op, false) - z80::cycles(op));
+        }
+        state.set_pair(RegPair::H, adr);
+        let cycles = handler(state, bus, op);
+
+        // LD H,(IX+d) and LD L,(IX+d) load the real H and L
+        let (h, l) = split_bytes(hl);
+        state.h = if op == 0x66 { state.h } else { h };
+        state.l = if op == 0x6e { state.l } else { l };
+        cycles
+    } else if z80::takes_index(op) {
+        state.set_pair(RegPair::H, state.z80.index(index));
+        let cycles = handler(state, bus, op);
+        state.z80.set_index(index, state.pair(RegPair::H));
+        state.set_pair(RegPair::H, hl);
+        cycles
+    } else {
+        handler(state, bus, op)
+    };
+    cycles + (z80::indexed_cycles(op, false) - z80::cycles(op))
+}
+
+/// DD CB d op: a CB operation on (IX+d)
+fn indexed_cb<B: Bus>(state: &mut State, bus: &mut B, index: Index) -> u8 {
+    // Only the CB is an opcode fetch, the real opcode follows the displacement
+    state.pc = state.pc.wrapping_add(1);
+    state.z80.refresh();
+    let d = state.fetch8(bus) as i8;
+    let op = state.fetch8(bus);
+
+    let adr = state.z80.index(index).wrapping_add(d as u16);
+    let val = bus.read(adr);
+    if let Some(res) = bit_op(state, op, val) {
+        bus.write(adr, res);
+        // Undocumented: any other register field also gets a copy
+        let r = Register::from_bits(op);
+        if r != Register::M {
+            state.set_reg(bus, r, res);
+        }
+    }
+    z80::indexed_cb_cycles(op)
+}
+
+/// ED prefix: 16 bit arithmetic, IO through C, interrupt control and the
+/// block instructions
+fn ed<B: Bus>(state: &mut State, bus: &mut B, _: u8) -> u8 {
+    let op = state.fetch8(bus);
+    state.z80.refresh();
+
+    let y = (op >> 3) & 0b111;
+    let taken = match (op >> 6, op & 0b111) {
+        (1, 0) => {
+            // IN r,(C), y = 6 only sets the flags
+            let val = bus.input(state.c);
+            check_flags_zsp(val, state);
+            state.flags.unset(FlagType::AC);
+            state.flags.unset(FlagType::N);
+            if y != 6 {
+                state.set_reg(bus, Register::from_bits(y), val);
+            }
+            false
+        }
+        (1, 1) => {
+            let val = if y == 6 { 0 } else { state.reg(bus, Register::from_bits(y)) };
+            bus.output(state.c, val);
+            false
+        }
+        (1, 2) => {
+            adc16(state, op);
+            false
+        }
+        (1, 3) => {
+            let adr = state.fetch16(bus);
+            let rp = RegPair::from_bits(y >> 1, false);
+            if y & 1 == 0 {
+                let (hi, lo) = split_bytes(state.pair(rp));
+                bus.write(adr, lo);
+                bus.write(adr.wrapping_add(1), hi);
+            } else {
+                let val = join_bytes(bus.read(adr.wrapping_add(1)), bus.read(adr));
+                state.set_pair(rp, val);
+            }
+            false
+        }
+        (1, 4) => {
+            // NEG
+            state.a = sub8(0, state.a, 0, state);
+            false
+        }
+        (1, 5) => {
+            // RETN and RETI put back the enable saved by an NMI
+            state.enable = state.z80.iff2 as u8;
+            state.pc = state.pop(bus);
+            false
+        }
+        (1, 6) => {
+            state.z80.im = [0, 0, 1, 2][(y & 0b11) as usize];
+            false
+        }
+        (1, 7) => {
+            match y {
+                0 => state.z80.i = state.a,
+                1 => state.z80.r = state.a,
+                2 | 3 => {
+                    state.a = if y == 2 { state.z80.i } else { state.z80.r };
+                    check_flag_z(state.a, state);
+                    check_flag_s(state.a, state);
+                    check_flag(FlagType::P, state.z80.iff2, state);
+                    state.flags.unset(FlagType::AC);
+                    state.flags.unset(FlagType::N);
+                }
+                4 | 5 => rotate_digit(state, bus, y == 5),
+                _ => (),
+            }
+            false
+        }
+        (2, 0..=3) if y >= 4 => block(state, bus, op),
+        _ => false,
+    };
+
+    if taken {
+        z80::ed_cycles_taken(op)
+    } else {
+        z80::ed_cycles(op)
+    }
+}
+
+/// ADC HL,rr and SBC HL,rr
+fn adc16(state: &mut State, op: u8) {
+    let hl = state.pair(RegPair::H);
+    let val = state.pair(RegPair::from_bits(op >> 4, false));
+    let carry = state.flags.get(FlagType::CY) as u16;
+    let sub = op & 0b1000 == 0;
+
+    let (res, half, overflow, borrow) = if sub {
+        let (res, b1) = hl.overflowing_sub(val);
+        let (res, b2) = res.overflowing_sub(carry);
+        let half = (hl & 0x0fff) < (val & 0x0fff) + carry;
+        (res, half, (hl ^ val) & (hl ^ res) & 0x8000 != 0, b1 || b2)
+    } else {
+        let (res, c1) = hl.overflowing_add(val);
+        let (res, c2) = res.overflowing_add(carry);
+        let half = (hl & 0x0fff) + (val & 0x0fff) + carry > 0x0fff;
+        (res, half, !(hl ^ val) & (hl ^ res) & 0x8000 != 0, c1 || c2)
+    };
+
+    state.set_pair(RegPair::H, res);
+    check_flag(FlagType::S, res & 0x8000 != 0, state);
+    check_flag(FlagType::Z, res == 0, state);
+    check_flag(FlagType::AC, half, state);
+    check_flag(FlagType::P, overflow, state);
+    check_flag(FlagType::N, sub, state);
+    check_flag(FlagType::CY, borrow, state);
+}
+
+/// RRD and RLD: rotate a BCD digit between A and (HL)
+fn rotate_digit<B: Bus>(state: &mut State, bus: &mut B, left: bool) {
+    let adr = state.pair(RegPair::H);
+    let m = bus.read(adr);
+    let (m, digit) = match left {
+        true => (m << 4 | (state.a & 0x0f), m >> 4),
+        false => ((state.a << 4) | (m >> 4), m & 0x0f),
+    };
+    bus.write(adr, m);
+    state.a = (state.a & 0xf0) | digit;
+
+    check_flags_zsp(state.a, state);
+    state.flags.unset(FlagType::AC);
+    state.flags.unset(FlagType::N);
+}
+
+/// LDI CPI INI OUTI and their decrementing and repeating forms. Returns
+/// whether the instruction repeats, which it does by running again.
+fn block<B: Bus>(state: &mut State, bus: &mut B, op: u8) -> bool {
+    let dec = op & 0b1000 != 0;
+    let repeat = op & 0b1_0000 != 0;
+    let step = |adr: u16| if dec { adr.wrapping_sub(1) } else { adr.wrapping_add(1) };
+
+    let hl = state.pair(RegPair::H);
+    state.set_pair(RegPair::H, step(hl));
+    let more = match op & 0b11 {
+        0 => {
+            let de = state.pair(RegPair::D);
+            let val = bus.read(hl);
+            bus.write(de, val);
+            state.set_pair(RegPair::D, step(de));
+
+            let bc = state.pair(RegPair::B).wrapping_sub(1);
+            state.set_pair(RegPair::B, bc);
+            state.flags.unset(FlagType::AC);
+            state.flags.unset(FlagType::N);
+            check_flag(FlagType::P, bc != 0, state);
+            bc != 0
+        }
+        1 => {
+            let carry = state.flags.get(FlagType::CY) == 1;
+            let val = bus.read(hl);
+            let res = sub8(state.a, val, 0, state);
+
+            let bc = state.pair(RegPair::B).wrapping_sub(1);
+            state.set_pair(RegPair::B, bc);
+            check_flag(FlagType::CY, carry, state);
+            check_flag(FlagType::P, bc != 0, state);
+            bc != 0 && res != 0
+        }
+        2 => {
+            let val = bus.input(state.c);
+            bus.write(hl, val);
+            state.b = state.b.wrapping_sub(1);
+            check_flag_z(state.b, state);
+            state.flags.set(FlagType::N);
+            state.b != 0
+        }
+        _ => {
+            let val = bus.read(hl);
+            state.b = state.b.wrapping_sub(1);
+            bus.output(state.c, val);
+            check_flag_z(state.b, state);
+            state.flags.set(FlagType::N);
+            state.b != 0
+        }
+    };
+
+    if repeat && more {
+        state.pc = state.pc.wrapping_sub(2);
+    }
+    repeat && more
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::arch::{memory::Memory, state::Cpu};
+
+    fn state_with(program: &[u8]) -> (State, Memory) {
+        let mut state = State::with_cpu(Cpu::Z80);
+        state.sp = 0x100;
+        (state, Memory::new(program.to_vec()))
+    }
+
+    fn run(state: &mut State, mem: &mut Memory, steps: usize) -> u64 {
+        let start = state.cycles;
+        for _ in 0..steps {
+            state.run_op(mem).unwrap();
+        }
+        state.cycles - start
+    }
+
+    #[test]
+    fn index_registers() {
+        // LD IX,0080 ; LD (IX+02),55 ; LD H,(IX+02) ; INC IXL ; LD A,(IX+01)
+        let (mut state, mut mem) = state_with(&[
+            0xdd, 0x21, 0x80, 0x00, 0xdd, 0x36, 0x02, 0x55, 0xdd, 0x66, 0x02, 0xdd, 0x2c, 0xdd, 0x7e,
+            0x01,
+        ]);
+        state.l = 0x11;
+
+        assert_eq!(run(&mut state, &mut mem, 3), 14 + 19 + 19);
+        assert_eq!(mem.data[0x82], 0x55);
+        assert_eq!((state.h, state.l), (0x55, 0x11));
+
+        run(&mut state, &mut mem, 2);
+        assert_eq!(state.z80.ix, 0x81);
+        assert_eq!(state.a, 0x55);
+        assert_eq!(state.z80.r, 10);
+    }
+
+    #[test]
+    fn index_stores_h_and_l() {
+        // LD IX,0080 ; LD IY,0090 ; LD H,12 ; LD L,34 ; LD (IX+02),H ; LD (IX+03),L ;
+        // LD (IY-01),H ; LD (IY+00),L
+        let (mut state, mut mem) = state_with(&[
+            0xdd, 0x21, 0x80, 0x00, 0xfd, 0x21, 0x90, 0x00, 0x26, 0x12, 0x2e, 0x34, 0xdd, 0x74, 0x02, 0xdd,
+            0x75, 0x03, 0xfd, 0x74, 0xff, 0xfd, 0x75, 0x00,
+        ]);
+
+        assert_eq!(run(&mut state, &mut mem, 8), 14 + 14 + 7 + 7 + 19 * 4);
+        assert_eq!(mem.data[0x82..0x84], [0x12, 0x34]);
+        assert_eq!(mem.data[0x8f..0x91], [0x12, 0x34]);
+        assert_eq!((state.h, state.l), (0x12, 0x34));
+    }
+
+    #[test]
+    fn exchanges_and_loops() {
+        // LD B,3 ; EXX ; EX AF,AF' ; DJNZ -4 ; LDIR
+        let (mut state, mut mem) = state_with(&[0x06, 0x03, 0xd9, 0x08, 0x10, 0xfc, 0xed, 0xb0]);
+        state.z80.bc_ = 0x0002;
+
+        run(&mut state, &mut mem, 3);
+        assert_eq!((state.b, state.z80.bc_), (0x00, 0x0300));
+        // B is now 0, so DJNZ wraps around 256 times
+        assert_eq!(run(&mut state, &mut mem, 1), 13);
+        assert_eq!(state.pc, 0x02);
+
+        let (mut state, mut mem) = state_with(&[0xed, 0xb0]);
+        mem.data[0x40..0x43].copy_from_slice(&[1, 2, 3]);
+        state.set_pair(RegPair::H, 0x40);
+        state.set_pair(RegPair::D, 0x50);
+        state.set_pair(RegPair::B, 3);
+        assert_eq!(run(&mut state, &mut mem, 3), 21 + 21 + 16);
+        assert_eq!(&mem.data[0x50..0x53], &[1, 2, 3]);
+        assert_eq!(state.pc, 0x02);
+        assert_eq!(state.flags.get(FlagType::P), 0);
+    }
+
+    #[test]
+    fn flags() {
+        // LD A,80 ; NEG ; LD A,15 ; SUB 06 ; DAA ; BIT 7,A
+        let (mut state, mut mem) =
+            state_with(&[0x3e, 0x80, 0xed, 0x44, 0x3e, 0x15, 0xd6, 0x06, 0x27, 0xcb, 0x7f]);
+
+        run(&mut state, &mut mem, 2);
+        assert_eq!(state.a, 0x80);
+        assert_eq!(state.flags.get(FlagType::P), 1);
+        assert_eq!(state.flags.get(FlagType::N), 1);
+
+        run(&mut state, &mut mem, 3);
+        assert_eq!(state.a, 0x09);
+        run(&mut state, &mut mem, 1);
+        assert_eq!(state.flags.get(FlagType::Z), 1);
+        assert_eq!(state.flags.get(FlagType::AC), 1);
+    }
+
+    #[test]
+    fn interrupt_modes() {
+        // IM 2 ; LD A,02 ; LD I,A ; EI ; NOP
+        let (mut state, mut mem) = state_with(&[0xed, 0x5e, 0x3e, 0x02, 0xed, 0x47, 0xfb, 0x00]);
+        // RST 1 puts CF on the bus
+        mem.data[0x02cf] = 0x34;
+        mem.data[0x02d0] = 0x12;
+        run(&mut state, &mut mem, 5);
+
+        state.request_interrupt(Interrupt::Rst(1));
+        assert_eq!(state.run_op(&mut mem).unwrap().cycles(), 19);
+        assert_eq!(state.pc, 0x1234);
+        assert!(!state.z80.iff2);
+
+        // An NMI gets through with interrupts disabled
+        state.z80.nmi = true;
+        assert_eq!(state.run_op(&mut mem).unwrap().cycles(), 11);
+        assert_eq!(state.pc, z80::NMI_VECTOR);
+    }
+}
@@ -1,3 +1,7 @@
 pub mod arch;
+pub mod bus;
 pub mod iset;
+pub mod iset8085;
+pub mod isetz80;
+pub mod step;
 pub mod utils;
@@ -0,0 +1,56 @@
+use std::{error::Error, fmt};
+
+/// What a single call to `State::run_op` did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// An instruction ran, taking this many T-states
+    Executed(u8),
+    /// The CPU is halted and idled for this many T-states
+    Halted(u8),
+    /// An interrupt was acknowledged, its instruction taking this many T-states
+    Interrupted(u8),
+}
+
+impl StepOutcome {
+    pub fn cycles(&self) -> u8 {
+        match *self {
+            StepOutcome::Executed(n) | StepOutcome::Halted(n) | StepOutcome::Interrupted(n) => n,
+        }
+    }
+}
+
+/// Reasons the CPU refused to execute an instruction. The PC is left on
+/// the faulting instruction so a debugger can inspect it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuError {
+    /// Opcode outside the documented instruction set, reported when
+    /// `State::trap_undocumented` is set
+    Illegal { pc: u16, opcode: u8 },
+    /// Memory access rejected by the bus
+    BusFault { pc: u16, opcode: u8, adr: u16 },
+}
+
+impl CpuError {
+    pub fn pc(&self) -> u16 {
+        match *self {
+            CpuError::Illegal { pc, .. } | CpuError::BusFault { pc, .. } => pc,
+        }
+    }
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CpuError::Illegal { pc, opcode } => {
+                write!(f, "illegal opcode {:#04x} at {:#06x}", opcode, pc)
+            }
+            CpuError::BusFault { pc, opcode, adr } => write!(
+                f,
+                "bus fault at {:#06x} by opcode {:#04x} at {:#06x}",
+                adr, opcode, pc
+            ),
+        }
+    }
+}
+
+impl Error for CpuError {}
@@ -1,4 +1,5 @@
-use super::arch::state::State;
+use super::arch::flag::FlagType;
+use super::arch::state::{Cpu, State};
 
 pub fn join_bytes(reg1: u8, reg2: u8) -> u16 {
     ((reg1 as u16) << 8) | (reg2 as u16)
@@ -10,49 +11,195 @@ pub fn split_bytes(word: u16) -> (u8, u8) {
 
 pub fn check_flag_z(reg: u8, state: &mut State) {
     if reg == 0 {
-        state.flags.set(super::arch::flag::FlagType::Z);
+        state.flags.set(FlagType::Z);
     } else {
-        state.flags.unset(super::arch::flag::FlagType::Z);
+        state.flags.unset(FlagType::Z);
     }
 }
 
 pub fn check_flag_s(reg: u8, state: &mut State) {
     if reg >> 7 == 0b1 {
-        state.flags.set(super::arch::flag::FlagType::S);
+        state.flags.set(FlagType::S);
     } else {
-        state.flags.unset(super::arch::flag::FlagType::S);
+        state.flags.unset(FlagType::S);
     }
 }
 
 pub fn check_flag_p(reg: u8, state: &mut State) {
-    if reg & 0b1 == 0b0 {
-        state.flags.set(super::arch::flag::FlagType::P);
+    if reg.count_ones() & 0b1 == 0 {
+        state.flags.set(FlagType::P);
     } else {
-        state.flags.unset(super::arch::flag::FlagType::P);
+        state.flags.unset(FlagType::P);
     }
 }
 
 pub fn check_flag_cy8(reg: u16, state: &mut State) {
     if reg > 0xff {
-        state.flags.set(super::arch::flag::FlagType::CY);
+        state.flags.set(FlagType::CY);
     } else {
-        state.flags.unset(super::arch::flag::FlagType::CY);
+        state.flags.unset(FlagType::CY);
     }
 }
 
 pub fn check_flag_cy16(reg: u32, state: &mut State) {
     if reg > 0xffff {
-        state.flags.set(super::arch::flag::FlagType::CY);
+        state.flags.set(FlagType::CY);
     } else {
-        state.flags.unset(super::arch::flag::FlagType::CY);
+        state.flags.unset(FlagType::CY);
     }
 }
 
-#[allow(dead_code)]
-pub fn check_flag_ac(prev: u8, reg: u8, state: &mut State) {
-    if (prev & 0x0f) > (reg & 0x0f) {
-        state.flags.set(super::arch::flag::FlagType::AC);
+/// Half carry out of bit 3 when adding `val` and `carry` to `reg`
+pub fn check_flag_ac(reg: u8, val: u8, carry: u8, state: &mut State) {
+    if (reg & 0x0f) + (val & 0x0f) + carry > 0x0f {
+        state.flags.set(FlagType::AC);
     } else {
-        state.flags.unset(super::arch::flag::FlagType::AC);
+        state.flags.unset(FlagType::AC);
+    }
+}
+
+/// Sets or clears `f`
+pub fn check_flag(f: FlagType, on: bool, state: &mut State) {
+    if on {
+        state.flags.set(f);
+    } else {
+        state.flags.unset(f);
+    }
+}
+
+/// 8085 V and K after adding `val` to `a`
+pub fn check_flags_vk(a: u8, val: u8, res: u8, state: &mut State) {
+    let (s1, s2, r) = (a & 0x80 != 0, val & 0x80 != 0, res & 0x80 != 0);
+    check_flag(FlagType::V, s1 == s2 && s1 != r, state);
+    check_flag(FlagType::K, (s1 && s2) || ((s1 || s2) && !r), state);
+}
+
+/// Flags the 8085 and Z80 keep on top of the 8080's after adding `val` to
+/// `a`, with `val` complemented for a subtraction
+fn check_flags_ext(a: u8, val: u8, res: u8, sub: bool, state: &mut State) {
+    match state.cpu {
+        Cpu::I8080 => (),
+        Cpu::I8085 => check_flags_vk(a, val, res, state),
+        Cpu::Z80 => {
+            // P/V is overflow rather than parity, H a borrow when subtracting
+            let overflow = (a ^ val) & 0x80 == 0 && (a ^ res) & 0x80 != 0;
+            check_flag(FlagType::P, overflow, state);
+            check_flag(FlagType::N, sub, state);
+            if sub {
+                check_flag(FlagType::AC, state.flags.get(FlagType::AC) == 0, state);
+            }
+        }
+    }
+}
+
+pub fn check_flags_zsp(reg: u8, state: &mut State) {
+    check_flag_z(reg, state);
+    check_flag_s(reg, state);
+    check_flag_p(reg, state);
+}
+
+/// ADD / ADC: `a + val + carry`, setting every flag
+pub fn add8(a: u8, val: u8, carry: u8, state: &mut State) -> u8 {
+    let res = a as u16 + val as u16 + carry as u16;
+    check_flag_cy8(res, state);
+    check_flag_ac(a, val, carry, state);
+    check_flags_zsp(res as u8, state);
+    check_flags_ext(a, val, res as u8, false, state);
+    res as u8
+}
+
+/// SUB / SBB / CMP: `a - val - borrow`, setting every flag. The 8080
+/// subtracts by adding the complement, which is where AC comes from.
+pub fn sub8(a: u8, val: u8, borrow: u8, state: &mut State) -> u8 {
+    let res = (a as u16).wrapping_sub(val as u16).wrapping_sub(borrow as u16);
+    check_flag_cy8(res, state);
+    check_flag_ac(a, !val, 1 - borrow, state);
+    check_flags_zsp(res as u8, state);
+    check_flags_ext(a, !val, res as u8, true, state);
+    res as u8
+}
+
+/// INR: like ADD 1 but leaves CY alone
+pub fn inr8(reg: u8, state: &mut State) -> u8 {
+    let res = reg.wrapping_add(1);
+    check_flag_ac(reg, 1, 0, state);
+    check_flags_zsp(res, state);
+    if state.cpu == Cpu::Z80 {
+        check_flag(FlagType::P, res == 0x80, state);
+        state.flags.unset(FlagType::N);
+    }
+    res
+}
+
+/// DCR: like adding 0xff, leaves CY alone
+pub fn dcr8(reg: u8, state: &mut State) -> u8 {
+    let res = reg.wrapping_sub(1);
+    check_flag_ac(reg, 0x0f, 0, state);
+    check_flags_zsp(res, state);
+    if state.cpu == Cpu::Z80 {
+        check_flag(FlagType::AC, reg & 0x0f == 0, state);
+        check_flag(FlagType::P, res == 0x7f, state);
+        state.flags.set(FlagType::N);
+    }
+    res
+}
+
+/// ANA: clears CY, AC is the OR of bit 3 of both operands on the 8080
+pub fn ana8(a: u8, val: u8, state: &mut State) -> u8 {
+    let res = a & val;
+    check_flag_cy8(0, state);
+    // The 8085 and the Z80 always set it
+    let ac = match state.cpu {
+        Cpu::I8080 => (a | val) & 0x08 != 0,
+        Cpu::I8085 | Cpu::Z80 => true,
+    };
+    check_flag(FlagType::AC, ac, state);
+    check_flags_zsp(res, state);
+    if state.cpu == Cpu::Z80 {
+        state.flags.unset(FlagType::N);
+    }
+    res
+}
+
+/// XRA: clears CY and AC
+pub fn xra8(a: u8, val: u8, state: &mut State) -> u8 {
+    logic8(a ^ val, state)
+}
+
+/// ORA: clears CY and AC
+pub fn ora8(a: u8, val: u8, state: &mut State) -> u8 {
+    logic8(a | val, state)
+}
+
+fn logic8(res: u8, state: &mut State) -> u8 {
+    check_flag_cy8(0, state);
+    state.flags.unset(FlagType::AC);
+    check_flags_zsp(res, state);
+    if state.cpu == Cpu::Z80 {
+        state.flags.unset(FlagType::N);
+    }
+    res
+}
+
+/// DAA: adjusts A to two BCD digits after an addition
+pub fn daa(state: &mut State) {
+    let lsb = state.a & 0x0f;
+    let msb = state.a >> 4;
+    let mut correction = 0;
+    let mut carry = state.flags.get(FlagType::CY);
+
+    if state.flags.get(FlagType::AC) == 1 || lsb > 9 {
+        correction += 0x06;
+    }
+    if carry == 1 || msb > 9 || (msb >= 9 && lsb > 9) {
+        correction += 0x60;
+        carry = 1;
+    }
+
+    state.a = add8(state.a, correction, 0, state);
+    if carry == 1 {
+        state.flags.set(FlagType::CY);
+    } else {
+        state.flags.unset(FlagType::CY);
     }
 }
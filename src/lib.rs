@@ -1,180 +1,170 @@
-use console_engine::rect_style::BorderStyle;
-use console_engine::Color;
-use console_engine::{self, pixel, ConsoleEngine};
-pub use emulator::arch::state::State;
-use machine::video::graphics;
+pub use emulator::arch::i8085::{Input, Pins};
+pub use emulator::arch::instruction::{decode, decode_as, Instruction, Operand, RegPair, Register};
+pub use emulator::arch::interrupt::Interrupt;
+pub use emulator::arch::memory::{Access, Memory, MemoryMap, WritePolicy};
+pub use emulator::arch::opcodes::Opcode;
+pub use emulator::arch::state::{Cpu, State};
+pub use emulator::bus::Bus;
+pub use emulator::step::{CpuError, StepOutcome};
+pub use machine::board::Board;
+pub use machine::drivers::{machine, Generic, Machine, MACHINES};
 pub use machine::io::IO;
-use std::env::Args;
-use std::process;
+pub use machine::memory::midway_map;
+pub use machine::bdos::TPA_START;
+use machine::bdos::{Bdos, BDOS_ENTRY, WARM_BOOT};
+pub use machine::scheduler::Scheduler;
+pub use machine::savestate::{fingerprint, SaveError, Slots};
+pub use machine::rewind::Rewind;
+pub use machine::movie::{Movie, MovieError, Playback};
+pub use machine::runner::Runner;
+pub use machine::romset::{Chip, Format, RomError, RomSet};
+pub use machine::romdb::{crc32, identify, sha1, KnownRom, System, ROMS};
+use machine::scheduler::CPU_HZ;
+#[cfg(feature = "tui")]
+pub use console::emulate;
+#[cfg(any(feature = "tui", feature = "window"))]
+pub use session::Session;
+#[cfg(feature = "window")]
+pub use session::play;
+use std::io::Write;
+use std::time::Instant;
 use std::{error::Error, fs};
-use console::*;
 
 mod emulator;
+#[cfg(feature = "tui")]
 mod console;
 mod machine;
-
-pub fn get_file(cmd_line: Args) -> Option<String> {
-    let mut cmds = cmd_line.into_iter();
-    cmds.next();
-    match cmds.next() {
-        Some(x) => Some(x),
-        None => None
-    }
-}
+#[cfg(any(feature = "tui", feature = "window"))]
+mod session;
 
 pub fn load_rom(file_path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
-    let mut memory = vec![0; 0x10000];
-    let file_data = fs::read(file_path)?;
+    load_rom_at(file_path, 0)
+}
 
-    let mut i = 0;
-    for byte in file_data {
-        memory[i] = byte;
-        i += 1;
-    }
-    Ok(memory)
+/// Reads a raw binary into a 64K memory image starting at `adr`
+pub fn load_rom_at(file_path: &str, adr: u16) -> Result<Vec<u8>, Box<dyn Error>> {
+    Ok(RomSet::from_file(file_path, adr)?.memory)
 }
 
-pub async fn emulate(mut state: State, mut io: IO) {
-    let mut engine =
-        console_engine::ConsoleEngine::init(WIDTH, HEIGHT, TARGET_FPS).unwrap_or_else(|err| {
-            println!("Could not create screen: {err}");
-            process::exit(-1);
-        });
+/// Runs a CP/M CPU exerciser (CPUDIAG, 8080PRE, TST8080, 8080EXM) until it
+/// returns to CP/M, echoing its console output. Returns whether it passed.
+pub fn selftest(file_path: &str) -> Result<bool, Box<dyn Error>> {
+    let program = fs::read(file_path)?;
+    let mut state = State::new();
+    state.pc = TPA_START;
+    run_cpm(state, Bdos::load_com(&program))
+}
 
-    let mut show_keybinds = false;
-    let mut mv = 0;
-    let mut pulse = 0.0;
-    let mut debug = true;
-    let mut live = false;
-    let mut int = true;
+/// Runs a CP/M program already laid out in `memory` from `state.pc`, as
+/// `selftest` does
+pub fn run_cpm(mut state: State, mut memory: Vec<u8>) -> Result<bool, Box<dyn Error>> {
+    Bdos::install(&mut memory);
+    let mut mem = Memory::new(memory);
+    let mut bdos = Bdos::new();
 
     loop {
-        engine.wait_frame();
-        engine.check_resize();
-        engine.clear_screen();
-        
-        if process_input(&engine, &mut state, &mut io, &mut show_keybinds, &mut mv, &mut debug, &mut live).await {
-            break;
-        };
-
-        if debug {
-            debug_state(&mut engine, &state, &io, &mut pulse, &show_keybinds, &mv);
-            if live {
-                engine.print_fbg(
-                    engine.get_width() as i32 - 33,
-                    engine.get_height() as i32 - 3,
-                    "LIVE",
-                    HIGHLIGHT,
-                    Color::Reset,
-                );
+        // Nothing raises interrupts here, so a halt is final
+        if state.halted {
+            println!();
+            println!("Halted at {:#06x}", state.pc.wrapping_sub(1));
+            return Ok(false);
+        }
+
+        match state.pc {
+            WARM_BOOT => {
+                bdos.returned = true;
+                break;
             }
-        } else {
-            running_state(&mut engine, &mut state, &mut io, &mut debug);
-            engine.print(0, 0, &format!("{}", engine.frame_count));
-            if state.enable == 1 && !debug{
-                if int {
-                    state.generate_interrupt(1);
-                } else {
-                    state.generate_interrupt(2);
+            BDOS_ENTRY => bdos.call(&mut state, &mut mem),
+            _ => {
+                if let Err(err) = state.run_op(&mut mem) {
+                    println!();
+                    println!("{}", err);
+                    return Ok(false);
                 }
-                int = !int;
-            }
-            if graphics::graphics(&state.mem, &mut io).await {
-                debug = true;
             }
         }
+    }
+    println!();
+    println!("{} cycles", state.cycles);
 
+    Ok(bdos.passed())
+}
 
-        engine.draw();
-    }
+/// Runs a rom headless for `frames` video frames as fast as the host allows
+/// and returns the emulated clock rate in MHz.
+pub fn benchmark(state: State, board: Board, frames: u64) -> Result<f64, Box<dyn Error>> {
+    let mut runner = Runner::new(state, board);
+
+    let start = Instant::now();
+    runner.run_frames(frames)?;
+    let elapsed = start.elapsed().as_secs_f64();
+    let cycles = runner.state.cycles;
+    let mhz = cycles as f64 / elapsed / 1e6;
+
+    println!("{} frames, {} cycles in {:.3}s", frames, cycles, elapsed);
+    println!("{:.1} MHz emulated ({:.0}x real time)", mhz, mhz * 1e6 / CPU_HZ as f64);
+    Ok(mhz)
 }
 
-fn debug_state(
-    engine: &mut ConsoleEngine,
-    state: &State,
-    io: &IO,
-    pulse: &mut f32,
-    show_keybinds: &bool,
-    mv: &i32,
-) {
-    engine.rect_border(
-        1,
-        1,
-        engine.get_width() as i32 - 2,
-        engine.get_height() as i32 - 2,
-        BorderStyle::new_double().with_colors(DULL, Color::Reset),
-    );
-
-    
-    engine.print_screen(
-        3,
-        2,
-        &disass(&state, &io, engine.get_height(), engine.get_width(), &mv),
-    );
-
-    engine.print_screen(79, engine.get_height() as i32 / 2 - 6, &pulse_anim(pulse));
-    *pulse += 0.1;
-
-
-    if *show_keybinds {
-        engine.print_screen(engine.get_width() as i32 - 26, 2, &keybinds());
-    }
+/// Runs `frames` video frames with no front end and prints where the
+/// machine ended up, for CI and servers without a display.
+pub fn headless(state: State, board: Board, frames: u64) -> Result<Runner, Box<dyn Error>> {
+    let mut runner = Runner::new(state, board);
+    runner.run_frames(frames)?;
+
+    println!("{} frames, {} cycles", runner.frame(), runner.state.cycles);
+    println!("PC {:04x}, state {:016x}", runner.state.pc, runner.fingerprint());
+    Ok(runner)
+}
+
+/// Replays a movie headless from power on and returns the fingerprint of
+/// the final state, or the frame the replay desynced at.
+pub fn verify_movie(mut state: State, mut board: Board, movie_path: &str) -> Result<u64, Box<dyn Error>> {
+    let movie = Movie::load(std::path::Path::new(movie_path), &board)?;
 
-    engine.print_fbg(
-        engine.get_width() as i32 - 19,
-        engine.get_height() as i32 - 3,
-        "keybinds: (h)elp",
-        DARKENAB,
-        Color::Reset,
-    );
+    let hash = machine::movie::replay(&movie, &mut state, &mut board)?;
+    println!("{} frames replayed, state {:016x}", movie.length, hash);
+    Ok(hash)
 }
 
-fn running_state(engine: &mut ConsoleEngine, state: &mut State, io: &mut IO, _debug: &mut bool) {
-    engine.fill_rect(
-        1,
-        1,
-        engine.get_width() as i32 - 2,
-        engine.get_height() as i32 - 2,
-        pixel::pxl_bg(' ', DISABLED),
-    );
-    engine.rect_border(
-        1,
-        1,
-        engine.get_width() as i32 - 2,
-        engine.get_height() as i32 - 2,
-        BorderStyle::new_double().with_colors(NORMAL, Color::Reset),
-    );
-    engine.fill_rect(
-        engine.get_width() as i32 / 2 - 20,
-        engine.get_height() as i32 / 2 - 2,
-        engine.get_width() as i32 / 2 + 18,
-        engine.get_height() as i32 / 2 + 2,
-        pixel::pxl_bg(' ', Color::Reset),
-    );
-    engine.rect_border(
-        engine.get_width() as i32 / 2 - 20,
-        engine.get_height() as i32 / 2 - 2,
-        engine.get_width() as i32 / 2 + 18,
-        engine.get_height() as i32 / 2 + 2,
-        BorderStyle::new_double().with_colors(NORMAL, Color::Reset),
-    );
-    engine.print_fbg(
-        engine.get_width() as i32 / 2 - 18,
-        engine.get_height() as i32 / 2,
-        "Running - (d)ebug on game to return",
-        NORMAL,
-        Color::Reset,
-    );
-
-    engine.print_screen(3, 3, &display_ports(&io));
-
-    for _ in 0..5000 {
-        state.run_op(io);
-
-        // Breakpoints
-        // if state.pc == 0x0bf4 {
-        //     *debug = !*debug;
-        //     break;
-        // }
+/// Prints `count` instructions from `start` as `cpu` decodes them
+pub fn disassemble(cpu: Cpu, mem: &[u8], start: u16, count: usize, out: &mut impl Write) -> std::io::Result<()> {
+    let mut adr = start;
+    for _ in 0..count {
+        let inst = decode_as(cpu, mem, adr);
+        writeln!(out, "{:04x}  {:<12} {}", adr, hex_bytes(&inst), inst)?;
+        adr = adr.wrapping_add(inst.length as u16);
     }
-}
\ No newline at end of file
+    Ok(())
+}
+
+/// Runs `frames` video frames, printing every instruction before it runs
+/// along with the registers
+pub fn trace(state: &mut State, board: &mut Board, frames: u64, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    let end = Scheduler::frame_of(state.cycles) + frames;
+    while Scheduler::frame_of(state.cycles) < end {
+        let inst = decode_as(state.cpu, &board.mem.data, state.pc);
+        writeln!(
+            out,
+            "{:>10} {:04x}  {:<12} {:<18} A={:02x} BC={:04x} DE={:04x} HL={:04x} SP={:04x} F={:08b}",
+            state.cycles,
+            state.pc,
+            hex_bytes(&inst),
+            inst.to_string(),
+            state.a,
+            state.pair(RegPair::B),
+            state.pair(RegPair::D),
+            state.pair(RegPair::H),
+            state.sp,
+            state.flags.reg,
+        )?;
+        Scheduler::step(state, board)?;
+    }
+    Ok(())
+}
+
+fn hex_bytes(inst: &Instruction) -> String {
+    let bytes = inst.bytes[..inst.length as usize].iter();
+    bytes.map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
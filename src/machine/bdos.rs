@@ -0,0 +1,167 @@
+use std::io::Write;
+
+use crate::emulator::utils::join_bytes;
+use crate::{Bus, State};
+
+pub const WARM_BOOT: u16 = 0x0000;
+pub const BDOS_ENTRY: u16 = 0x0005;
+pub const TPA_START: u16 = 0x0100;
+
+/// Top of the memory reported to programs through the BDOS jump vector
+pub const BDOS_TOP: u16 = 0xf000;
+
+/// Minimal CP/M BDOS stub, enough for the console output done by the
+/// standard CPU exercisers (CPUDIAG, 8080PRE, TST8080, 8080EXM).
+pub struct Bdos {
+    pub output: String,
+    /// Whether the program finished by jumping to `WARM_BOOT`
+    pub returned: bool,
+}
+
+impl Default for Bdos {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bdos {
+    pub fn new() -> Self {
+        Bdos { output: String::new(), returned: false }
+    }
+
+    /// Lays out a .COM program at the start of the TPA with the BDOS
+    /// vector at 0x0005 pointing to the top of memory
+    pub fn load_com(program: &[u8]) -> Vec<u8> {
+        let mut memory = vec![0; 0x10000];
+        let start = TPA_START as usize;
+        let len = program.len().min(memory.len() - start);
+        memory[start..start + len].copy_from_slice(&program[..len]);
+        Bdos::install(&mut memory);
+        memory
+    }
+
+    /// Puts the BDOS vector in a memory image already holding a program
+    pub fn install(memory: &mut [u8]) {
+        // JMP BDOS_TOP, read by programs that size the stack from 0x0006
+        memory[BDOS_ENTRY as usize] = 0xc3;
+        memory[BDOS_ENTRY as usize + 1] = BDOS_TOP as u8;
+        memory[BDOS_ENTRY as usize + 2] = (BDOS_TOP >> 8) as u8;
+    }
+
+    /// Services a trapped `CALL 5` and returns to the caller
+    pub fn call(&mut self, state: &mut State, bus: &mut impl Bus) {
+        match state.c {
+            // C_WRITE: character in E
+            2 => self.print(state.e as char),
+            // C_WRITESTR: '$' terminated string at DE
+            9 => {
+                let start = join_bytes(state.d, state.e);
+                for i in 0..=u16::MAX {
+                    let ch = bus.read(start.wrapping_add(i));
+                    if ch == b'$' {
+                        break;
+                    }
+                    self.print(ch as char);
+                }
+            }
+            _ => {}
+        }
+
+        // RET
+        state.pc = state.pop(bus);
+    }
+
+    /// Exercisers report failures with "FAILED" or "ERROR" somewhere in
+    /// their output. A pass needs them to have reported something and
+    /// gone back to CP/M, not halted or crashed.
+    pub fn passed(&self) -> bool {
+        let output = self.output.to_uppercase();
+        self.returned && !output.trim().is_empty() && !output.contains("FAIL") && !output.contains("ERROR")
+    }
+
+    fn print(&mut self, ch: char) {
+        self.output.push(ch);
+        print!("{}", ch);
+        let _ = std::io::stdout().flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Memory;
+
+    #[test]
+    fn prints_and_returns() {
+        // MVI C,09 ; LXI D,010b ; CALL 0005 ; JMP 0000 ; "OK$"
+        let program = [0x0e, 0x09, 0x11, 0x0b, 0x01, 0xcd, 0x05, 0x00, 0xc3, 0x00, 0x00, b'O', b'K', b'$'];
+        let mut mem = Memory::new(Bdos::load_com(&program));
+        let mut state = State::new();
+        let mut bdos = Bdos::new();
+        state.pc = TPA_START;
+
+        while state.pc != BDOS_ENTRY {
+            state.run_op(&mut mem).unwrap();
+        }
+        bdos.call(&mut state, &mut mem);
+        assert_eq!(bdos.output, "OK");
+        assert_eq!(state.pc, 0x0108);
+        assert!(!bdos.passed());
+        bdos.returned = true;
+        assert!(bdos.passed());
+
+        for ch in "ERROR".chars() {
+            state.push(&mut mem, 0x0108);
+            (state.c, state.e) = (2, ch as u8);
+            bdos.call(&mut state, &mut mem);
+        }
+        assert_eq!(bdos.output, "OKERROR");
+        assert!(!bdos.passed());
+    }
+
+    /// Runs `program` as a .COM file through `selftest`
+    fn selftest(name: &str, program: &[u8]) -> bool {
+        let path = std::env::temp_dir().join(format!("rust8080-{}.com", name));
+        std::fs::write(&path, program).unwrap();
+        let passed = crate::selftest(path.to_str().unwrap()).unwrap();
+        let _ = std::fs::remove_file(&path);
+        passed
+    }
+
+    #[test]
+    fn checks_flags_through_selftest() {
+        #[rustfmt::skip]
+        let mut diag = vec![
+            0x31, 0x00, 0x20,   // LXI SP,2000
+            0x3e, 0xff,         // MVI A,FF
+            0xc6, 0x01,         // ADI 01, zero with carry and even parity
+            0xc2, 0x2c, 0x01,   // JNZ fail
+            0xd2, 0x2c, 0x01,   // JNC fail
+            0xe2, 0x2c, 0x01,   // JPO fail
+            0xfa, 0x2c, 0x01,   // JM fail
+            0xd6, 0x01,         // SUI 01, negative with a borrow
+            0xca, 0x2c, 0x01,   // JZ fail
+            0xd2, 0x2c, 0x01,   // JNC fail
+            0xf2, 0x2c, 0x01,   // JP fail
+            0xe2, 0x2c, 0x01,   // JPO fail
+            0x11, 0x37, 0x01,   // LXI D,ok
+            0x0e, 0x09,         // MVI C,09
+            0xcd, 0x05, 0x00,   // CALL 0005
+            0xc3, 0x00, 0x00,   // JMP 0000
+            0x11, 0x4a, 0x01,   // fail: LXI D,bad
+            0x0e, 0x09,         // MVI C,09
+            0xcd, 0x05, 0x00,   // CALL 0005
+            0xc3, 0x00, 0x00,   // JMP 0000
+        ];
+        diag.extend_from_slice(b"CPU IS OPERATIONAL$CPU HAS FAILED$");
+        assert!(selftest("diag", &diag));
+
+        // Expecting the zero flag clear after ADI makes it report a failure
+        diag[7] = 0xca;
+        assert!(!selftest("diag-fail", &diag));
+
+        // Halting, or going back to CP/M without a word, is no pass either
+        assert!(!selftest("halt", &[0x76]));
+        assert!(!selftest("silent", &[0xc3, 0x00, 0x00]));
+    }
+}
@@ -0,0 +1,59 @@
+use crate::emulator::{arch::memory::Memory, bus::Bus};
+use crate::{Interrupt, IO};
+
+use super::drivers::{Invaders, Machine};
+use super::savestate::fingerprint;
+use super::scheduler::Beam;
+
+/// Midway 8080 style board: ROM and RAM behind a memory map, and the input
+/// and shift register ports of `IO`, as its `Machine` has them. `new` builds
+/// it as wired for Space Invaders.
+pub struct Board {
+    pub mem: Memory,
+    pub io: IO,
+    pub machine: &'static dyn Machine,
+    /// Fingerprint of the ROM image, save states only load on a match
+    pub rom_id: u64,
+}
+
+impl Board {
+    pub fn new(rom: Vec<u8>) -> Board {
+        Board::with_machine(rom, &Invaders)
+    }
+
+    pub fn with_machine(rom: Vec<u8>, machine: &'static dyn Machine) -> Board {
+        let rom_id = fingerprint(&rom);
+        let mut mem = Memory::new(rom);
+        mem.map = machine.memory_map();
+
+        Board { mem, io: IO::with_map(machine.io()), machine, rom_id }
+    }
+}
+
+impl Bus for Board {
+    fn read(&mut self, adr: u16) -> u8 {
+        self.mem.read(adr)
+    }
+
+    fn write(&mut self, adr: u16, val: u8) {
+        self.mem.write(adr, val)
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        self.machine.input(&mut self.io, port)
+    }
+
+    fn output(&mut self, port: u8, val: u8) {
+        self.machine.output(&mut self.io, port, val)
+    }
+
+    fn take_fault(&mut self) -> Option<u16> {
+        self.mem.fault.take()
+    }
+}
+
+impl Beam for Board {
+    fn frame_interrupts(&self) -> (Interrupt, Interrupt) {
+        self.machine.interrupts()
+    }
+}
@@ -0,0 +1,387 @@
+use super::io::{IoMap, GENERIC_IO, INVADERS_IO};
+use super::memory::{midway_map, VRAM_START};
+use super::scheduler::{END_SCREEN_INT, MID_SCREEN_INT};
+use crate::{Interrupt, MemoryMap, IO};
+
+/// A rom chip: name, address, size and CRC32
+pub type RomChip = (&'static str, u16, usize, u32);
+
+/// How the frame buffer is shown
+pub struct Video {
+    /// Start of 224 columns of 256 pixels, one bit each, stored with the
+    /// screen turned on its side as the monitor is in the cabinet
+    pub vram: u16,
+    /// Colour of the screen from the top down to each row, as given by the
+    /// cabinet's overlay
+    pub colours: &'static [(u16, [u8; 3])],
+}
+
+const WHITE: [u8; 3] = [0xff, 0xff, 0xff];
+const GREEN: [u8; 3] = [0x00, 0xe4, 0x30];
+const RED: [u8; 3] = [0xe4, 0x20, 0x20];
+
+/// A board the emulator runs: everything that differs between the games
+/// built on the Midway 8080 hardware
+pub trait Machine: Sync {
+    /// Name given to `--machine`, the MAME short name where there is one
+    fn name(&self) -> &'static str;
+
+    fn title(&self) -> &'static str;
+
+    /// Chips of the rom set, how a zip of it is laid out
+    fn roms(&self) -> &'static [RomChip];
+
+    fn memory_map(&self) -> MemoryMap {
+        midway_map()
+    }
+
+    /// Inputs, shift register ports and DIP switches
+    fn io(&self) -> &'static IoMap {
+        &INVADERS_IO
+    }
+
+    /// IN from `port`
+    fn input(&self, io: &mut IO, port: u8) -> u8 {
+        io.machine_in(port)
+    }
+
+    /// OUT of `val` to `port`
+    fn output(&self, io: &mut IO, port: u8, val: u8) {
+        io.machine_out(port, val)
+    }
+
+    /// Interrupts raised as the beam passes the middle of the screen and
+    /// as it enters vblank
+    fn interrupts(&self) -> (Interrupt, Interrupt) {
+        (MID_SCREEN_INT, END_SCREEN_INT)
+    }
+
+    fn video(&self) -> Video {
+        Video { vram: VRAM_START, colours: &[(256, WHITE)] }
+    }
+}
+
+pub const INVADERS_ROMS: &[RomChip] = &[
+    ("invaders.h", 0x0000, 0x0800, 0x734f5ad8),
+    ("invaders.g", 0x0800, 0x0800, 0x6bfaca4a),
+    ("invaders.f", 0x1000, 0x0800, 0x0ccead96),
+    ("invaders.e", 0x1800, 0x0800, 0x14e538b0),
+];
+
+pub const INVADPT2_ROMS: &[RomChip] = &[
+    ("pv01", 0x0000, 0x0800, 0x7288a511),
+    ("pv02", 0x0800, 0x0800, 0x097dd8d5),
+    ("pv03", 0x1000, 0x0800, 0x1766337e),
+    ("pv04", 0x1800, 0x0800, 0x8f0e62e0),
+    ("pv05", 0x4000, 0x0800, 0x19b505e9),
+];
+
+pub const LRESCUE_ROMS: &[RomChip] = &[
+    ("lrescue.1", 0x0000, 0x0800, 0x2bbc4778),
+    ("lrescue.2", 0x0800, 0x0800, 0x49e79706),
+    ("lrescue.3", 0x1000, 0x0800, 0x1ac969be),
+    ("lrescue.4", 0x1800, 0x0800, 0x782fee3c),
+    ("lrescue.5", 0x4000, 0x0800, 0x58fde8bc),
+    ("lrescue.6", 0x4800, 0x0800, 0xbfb0f65d),
+];
+
+pub const BALLBOMB_ROMS: &[RomChip] = &[
+    ("tn01", 0x0000, 0x0800, 0x551585b5),
+    ("tn02", 0x0800, 0x0800, 0x7e1f734f),
+    ("tn03", 0x1000, 0x0800, 0xd93e20bc),
+    ("tn04", 0x1800, 0x0800, 0xd0689a22),
+    ("tn05-1", 0x4000, 0x0800, 0x5d5e94f1),
+];
+
+// The Taito boards take the controls as Space Invaders does, but read the
+// coin switch low while a coin drops
+
+/// Taito's sequel: one lives switch for 3 or 4, the preset mode that skips
+/// the high score name entry and a coinage switch
+const INVADPT2_IO: IoMap = IoMap {
+    reset: (0b10001000, 0b00000000),
+    dips: &[("Lives", 2, 0b00000001), ("Preset mode", 2, 0b00001000), ("Coinage", 2, 0b10000000)],
+    active_low: (0b00000001, 0),
+    sounds: &[
+        ("Saucer", 3, 0),
+        ("Shot", 3, 1),
+        ("Base hit", 3, 2),
+        ("Invader hit", 3, 3),
+        ("Bonus base", 3, 4),
+        ("Fleet 1", 5, 0),
+        ("Fleet 2", 5, 1),
+        ("Fleet 3", 5, 2),
+        ("Fleet 4", 5, 3),
+        ("Saucer hit", 5, 4),
+    ],
+    ..INVADERS_IO
+};
+
+/// Lunar Rescue: lives from 3 to 6 and the bonus life switch, with the
+/// lander's sounds on port 3
+const LRESCUE_IO: IoMap = IoMap {
+    reset: (0b10001000, 0b00000000),
+    dips: &[("Lives", 2, 0b00000011), ("Bonus life", 2, 0b00001000)],
+    active_low: (0b00000001, 0),
+    sounds: &[
+        ("Thrust", 3, 0),
+        ("Shot", 3, 1),
+        ("Death", 3, 2),
+        ("Alien hit", 3, 3),
+        ("Footstep", 3, 4),
+        ("Rescued", 3, 6),
+    ],
+    ..INVADERS_IO
+};
+
+/// Balloon Bomber: lives from 3 to 6 and the bonus base switch. Its sound
+/// board leaves bit 1 of port 3 unused and starts the bonus base sound with
+/// bit 5, where the others gate their amplifier.
+const BALLBOMB_IO: IoMap = IoMap {
+    reset: (0b10001000, 0b00000000),
+    dips: &[("Lives", 2, 0b00000011), ("Bonus base", 2, 0b00001000)],
+    active_low: (0b00000001, 0),
+    sounds: &[
+        ("Balloon hit", 3, 0),
+        ("Shot", 3, 2),
+        ("Base hit", 3, 3),
+        ("Bomb hit", 3, 4),
+        ("Bonus base", 3, 5),
+        ("Plane hit", 5, 0),
+    ],
+    ..INVADERS_IO
+};
+
+/// Midway's Space Invaders, and the board the others are variations on
+pub struct Invaders;
+
+impl Machine for Invaders {
+    fn name(&self) -> &'static str {
+        "invaders"
+    }
+
+    fn title(&self) -> &'static str {
+        "Space Invaders"
+    }
+
+    fn roms(&self) -> &'static [RomChip] {
+        INVADERS_ROMS
+    }
+
+    /// Black and white monitor under a strip of red cellophane for the
+    /// saucer and green for the bases and the player
+    fn video(&self) -> Video {
+        Video { vram: VRAM_START, colours: &[(32, WHITE), (64, RED), (184, WHITE), (240, GREEN), (256, WHITE)] }
+    }
+}
+
+/// Taito's sequel, with a fifth chip in the socket at 0x4000. Its colour
+/// PROM is not emulated.
+pub struct InvadersPart2;
+
+impl Machine for InvadersPart2 {
+    fn name(&self) -> &'static str {
+        "invadpt2"
+    }
+
+    fn title(&self) -> &'static str {
+        "Space Invaders Part II"
+    }
+
+    fn roms(&self) -> &'static [RomChip] {
+        INVADPT2_ROMS
+    }
+
+    fn io(&self) -> &'static IoMap {
+        &INVADPT2_IO
+    }
+}
+
+/// Taito's Lunar Rescue, with 4K more rom at 0x4000
+pub struct LunarRescue;
+
+impl Machine for LunarRescue {
+    fn name(&self) -> &'static str {
+        "lrescue"
+    }
+
+    fn title(&self) -> &'static str {
+        "Lunar Rescue"
+    }
+
+    fn roms(&self) -> &'static [RomChip] {
+        LRESCUE_ROMS
+    }
+
+    fn io(&self) -> &'static IoMap {
+        &LRESCUE_IO
+    }
+}
+
+/// Taito's Balloon Bomber, with a fifth chip at 0x4000
+pub struct BalloonBomber;
+
+impl Machine for BalloonBomber {
+    fn name(&self) -> &'static str {
+        "ballbomb"
+    }
+
+    fn title(&self) -> &'static str {
+        "Balloon Bomber"
+    }
+
+    fn roms(&self) -> &'static [RomChip] {
+        BALLBOMB_ROMS
+    }
+
+    fn io(&self) -> &'static IoMap {
+        &BALLBOMB_IO
+    }
+}
+
+/// 64K of RAM and inputs on ports 1 and 2, for roms that are not known
+pub struct Generic;
+
+impl Machine for Generic {
+    fn name(&self) -> &'static str {
+        "generic"
+    }
+
+    fn title(&self) -> &'static str {
+        "Generic 8080 machine"
+    }
+
+    fn roms(&self) -> &'static [RomChip] {
+        &[]
+    }
+
+    fn memory_map(&self) -> MemoryMap {
+        MemoryMap::flat()
+    }
+
+    fn io(&self) -> &'static IoMap {
+        &GENERIC_IO
+    }
+}
+
+/// Every driver, Space Invaders first as the default layout for a zip
+pub const MACHINES: &[&dyn Machine] = &[&Invaders, &InvadersPart2, &LunarRescue, &BalloonBomber, &Generic];
+
+/// Driver by `--machine` name
+pub fn machine(name: &str) -> Option<&'static dyn Machine> {
+    MACHINES.iter().copied().find(|m| m.name() == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::io::Actions;
+    use crate::{Board, Bus};
+
+    /// Ports 0, 1 and 2 of `machine` at power on and with a coin dropping
+    fn coin_ports(machine: &'static dyn Machine) -> ([u8; 3], [u8; 3]) {
+        let mut board = Board::with_machine(vec![0; 0x10000], machine);
+        let idle = [0, 1, 2].map(|port| board.input(port));
+        board.io.io_op(Actions::Credit);
+        (idle, [0, 1, 2].map(|port| board.input(port)))
+    }
+
+    #[test]
+    fn roms_land_in_rom() {
+        for &machine in MACHINES {
+            let mut board = Board::with_machine(vec![0xaa; 0x10000], machine);
+            for &(name, adr, size, _) in machine.roms() {
+                let last = adr + size as u16 - 1;
+                board.write(adr, 0x55);
+                board.write(last, 0x55);
+                assert_eq!((board.read(adr), board.read(last)), (0xaa, 0xaa), "{} {}", machine.name(), name);
+            }
+        }
+    }
+
+    #[test]
+    fn invaders_ports() {
+        let (idle, coin) = coin_ports(&Invaders);
+        assert_eq!(idle, [0x00, 0b10001000, 0b00000001]);
+        assert_eq!(coin[1], 0b10001001);
+
+        let mut board = Board::with_machine(vec![0; 0x10000], &Invaders);
+        board.output(3, 0x12);
+        board.output(5, 0x34);
+        assert_eq!((board.io.w3.reg, board.io.w5.reg), (0x12, 0x34));
+        board.output(2, 0x05);
+        assert_eq!(board.io.w2.reg, 0x05);
+        assert_eq!(dips_on(&Invaders), [("Lives", 3), ("Bonus life at 1000", 1), ("No coin info", 1)]);
+    }
+
+    /// DIP switch settings of `machine` with every switch on
+    fn dips_on(machine: &'static dyn Machine) -> Vec<(&'static str, u8)> {
+        let mut board = Board::with_machine(vec![0; 0x10000], machine);
+        board.io.r2.reg = 0xff;
+        board.io.dips().collect()
+    }
+
+    /// Sound the latched `bit` of output `port` starts on `machine`
+    fn sound(machine: &dyn Machine, port: u8, bit: u8) -> Option<&'static str> {
+        machine.io().sounds.iter().find(|&&(_, p, b)| (p, b) == (port, bit)).map(|&(name, _, _)| name)
+    }
+
+    #[test]
+    fn invadpt2_ports() {
+        let (idle, coin) = coin_ports(&InvadersPart2);
+        assert_eq!(idle, [0x00, 0b10001001, 0b00000000]);
+        assert_eq!(coin[1], 0b10001000);
+        assert_eq!(dips_on(&InvadersPart2), [("Lives", 1), ("Preset mode", 1), ("Coinage", 1)]);
+        assert_eq!(sound(&InvadersPart2, 3, 0), Some("Saucer"));
+    }
+
+    #[test]
+    fn lrescue_ports() {
+        let (idle, coin) = coin_ports(&LunarRescue);
+        assert_eq!(idle, [0x00, 0b10001001, 0b00000000]);
+        assert_eq!(coin[1], 0b10001000);
+        assert_eq!(dips_on(&LunarRescue), [("Lives", 3), ("Bonus life", 1)]);
+        assert_eq!((sound(&LunarRescue, 3, 0), sound(&LunarRescue, 3, 6)), (Some("Thrust"), Some("Rescued")));
+        assert_eq!(sound(&LunarRescue, 5, 0), None);
+    }
+
+    #[test]
+    fn ballbomb_ports() {
+        let (idle, coin) = coin_ports(&BalloonBomber);
+        assert_eq!(idle, [0x00, 0b10001001, 0b00000000]);
+        assert_eq!(coin[1], 0b10001000);
+        assert_eq!(dips_on(&BalloonBomber), [("Lives", 3), ("Bonus base", 1)]);
+        assert_eq!(sound(&BalloonBomber, 3, 1), None);
+        assert_eq!(sound(&BalloonBomber, 3, 5), Some("Bonus base"));
+        assert_eq!(sound(&BalloonBomber, 5, 0), Some("Plane hit"));
+
+        let mut board = Board::with_machine(vec![0; 0x10000], &BalloonBomber);
+        board.output(3, 0b00100001);
+        board.output(6, 0xff);
+        assert_eq!((board.io.w3.reg, board.io.w6.reg), (0b00100001, 0xff));
+    }
+
+    #[test]
+    fn io_maps_agree() {
+        for &machine in MACHINES {
+            let io = machine.io();
+            let latched = io.sound.map_or(vec![], |(a, b)| vec![a, b]);
+            for &(name, port, _) in io.sounds {
+                assert!(latched.contains(&port), "{} {}", machine.name(), name);
+            }
+            // A DIP switch never shares a bit with a control
+            for &(name, port, mask) in io.dips {
+                let clash = io.inputs.iter().any(|&(_, p, bit)| p == port && mask >> bit & 1 == 1);
+                assert!(!clash, "{} {}", machine.name(), name);
+            }
+        }
+    }
+
+    #[test]
+    fn generic_ports() {
+        let mut board = Board::with_machine(vec![0; 0x10000], &Generic);
+        board.output(2, 0x05);
+        board.output(4, 0xff);
+        assert_eq!([0, 3, 4].map(|port| board.input(port)), [0, 0, 0]);
+    }
+}
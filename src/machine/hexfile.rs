@@ -0,0 +1,187 @@
+use std::fs;
+
+use super::romset::{Chip, RomError, RomSet};
+use crate::emulator::arch::state::MEM_SIZE;
+
+/// What a record failed on, with its line number from 1
+type LineError = (usize, &'static str);
+
+/// Data and start address read from a text object file
+#[derive(Debug, Default)]
+struct Image {
+    /// Address and data of each data record, in file order
+    records: Vec<(u16, Vec<u8>)>,
+    entry: Option<u16>,
+}
+
+impl Image {
+    fn data(&mut self, line: usize, adr: u32, data: Vec<u8>) -> Result<(), LineError> {
+        if adr as usize + data.len() > MEM_SIZE {
+            return Err((line, "data past the end of memory"));
+        }
+        if !data.is_empty() {
+            self.records.push((adr as u16, data));
+        }
+        Ok(())
+    }
+
+    fn entry(&mut self, line: usize, adr: u32) -> Result<(), LineError> {
+        let adr = u16::try_from(adr).map_err(|_| (line, "start address past the end of memory"))?;
+        self.entry = Some(adr);
+        Ok(())
+    }
+
+    /// Joins records that follow on from each other into chips named after
+    /// the file, so overlapping records show up as overlapping chips
+    fn into_rom_set(mut self, path: &str) -> Result<RomSet, RomError> {
+        self.records.sort_by_key(|&(adr, _)| adr);
+        let mut blocks: Vec<(Chip, Vec<u8>)> = Vec::new();
+        for (adr, data) in self.records {
+            match blocks.last_mut() {
+                Some((chip, block)) if chip.adr as usize + block.len() == adr as usize => {
+                    block.extend_from_slice(&data)
+                }
+                _ => blocks.push((Chip::new(path, adr), data)),
+            }
+        }
+        let mut set = RomSet::place(blocks)?;
+        set.entry = self.entry;
+        Ok(set)
+    }
+}
+
+/// Hex digit pairs of a record after its start code, with the checksum
+fn bytes(line: usize, digits: &str) -> Result<Vec<u8>, LineError> {
+    if !digits.len().is_multiple_of(2) || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err((line, "not hex digits"));
+    }
+    let pairs = (0..digits.len()).step_by(2);
+    Ok(pairs.map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap()).collect())
+}
+
+fn be(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |n, &b| n << 8 | b as u32)
+}
+
+/// Reads Intel HEX, as written by 8080 cross-assemblers
+fn intel_hex(text: &str) -> Result<Image, LineError> {
+    let mut image = Image::default();
+    // Upper address from extended segment (02) or linear (04) records
+    let mut base = 0;
+    for (i, rec) in text.lines().map(str::trim).enumerate() {
+        let line = i + 1;
+        if rec.is_empty() {
+            continue;
+        }
+        let Some(digits) = rec.strip_prefix(':') else {
+            return Err((line, "record does not start with `:`"));
+        };
+        let b = bytes(line, digits)?;
+        if b.len() < 5 || b.len() != b[0] as usize + 5 {
+            return Err((line, "record length does not match its byte count"));
+        }
+        if b.iter().fold(0u8, |sum, &x| sum.wrapping_add(x)) != 0 {
+            return Err((line, "bad checksum"));
+        }
+
+        let adr = be(&b[1..3]);
+        let data = &b[4..b.len() - 1];
+        match (b[3], data.len()) {
+            (0x00, _) => image.data(line, base + adr, data.to_vec())?,
+            (0x01, _) => return Ok(image),
+            (0x02, 2) => base = be(data) << 4,
+            (0x03, 4) => image.entry(line, (be(&data[..2]) << 4) + be(&data[2..]))?,
+            (0x04, 2) => base = be(data) << 16,
+            (0x05, 4) => image.entry(line, be(data))?,
+            (0x02..=0x05, _) => return Err((line, "wrong length for its record type")),
+            _ => return Err((line, "unknown record type")),
+        }
+    }
+    Ok(image)
+}
+
+/// Reads Motorola S-records, S1/S2/S3 data and S7/S8/S9 start addresses
+fn s_record(text: &str) -> Result<Image, LineError> {
+    let mut image = Image::default();
+    for (i, rec) in text.lines().map(str::trim).enumerate() {
+        let line = i + 1;
+        if rec.is_empty() {
+            continue;
+        }
+        let mut chars = rec.chars();
+        let (Some('S' | 's'), Some(kind)) = (chars.next(), chars.next()) else {
+            return Err((line, "record does not start with `S`"));
+        };
+        let b = bytes(line, chars.as_str())?;
+        if b.len() < 2 || b.len() != b[0] as usize + 1 {
+            return Err((line, "record length does not match its byte count"));
+        }
+        if b.iter().fold(0u8, |sum, &x| sum.wrapping_add(x)) != 0xff {
+            return Err((line, "bad checksum"));
+        }
+
+        let adr_len = match kind {
+            '0' | '1' | '5' | '9' => 2,
+            '2' | '6' | '8' => 3,
+            '3' | '7' => 4,
+            _ => return Err((line, "unknown record type")),
+        };
+        if b.len() < adr_len + 2 {
+            return Err((line, "record too short for its address"));
+        }
+        let adr = be(&b[1..1 + adr_len]);
+        let data = &b[1 + adr_len..b.len() - 1];
+        match kind {
+            '1' | '2' | '3' => image.data(line, adr, data.to_vec())?,
+            '7' | '8' | '9' => {
+                image.entry(line, adr)?;
+                return Ok(image);
+            }
+            // Header and record counts
+            _ => (),
+        }
+    }
+    Ok(image)
+}
+
+impl RomSet {
+    /// Loads an Intel HEX file, starting at its start address record if
+    /// it has one
+    pub fn from_intel_hex(path: &str) -> Result<RomSet, RomError> {
+        RomSet::from_text(path, intel_hex)
+    }
+
+    /// Loads a Motorola S-record file, starting at its S7/S8/S9 address
+    pub fn from_s_record(path: &str) -> Result<RomSet, RomError> {
+        RomSet::from_text(path, s_record)
+    }
+
+    fn from_text(path: &str, parse: fn(&str) -> Result<Image, LineError>) -> Result<RomSet, RomError> {
+        let text = fs::read_to_string(path).map_err(|err| RomError::Io(path.to_string(), err))?;
+        let image = parse(&text).map_err(|(line, what)| RomError::Record(path.to_string(), line, what))?;
+        image.into_rom_set(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_records_and_entry() {
+        // MVI A,55h / HLT at 0100h, started from the type 05 record
+        let hex = ":030100003E5576F3\n:0400000500000100F6\n:00000001FF\n";
+        let image = intel_hex(hex).unwrap();
+        assert_eq!(image.records, vec![(0x0100, vec![0x3e, 0x55, 0x76])]);
+        assert_eq!(image.entry, Some(0x0100));
+
+        let srec = "S0030000FC\nS10601003E5576EF\nS9030100FB\n";
+        let image = s_record(srec).unwrap();
+        assert_eq!(image.records, vec![(0x0100, vec![0x3e, 0x55, 0x76])]);
+        assert_eq!(image.entry, Some(0x0100));
+
+        let bad = ":030100003E5576F3\n:030103003E5576F1\n";
+        assert_eq!(intel_hex(bad).unwrap_err(), (2, "bad checksum"));
+        assert_eq!(s_record("S0030000FC\nS1060100").unwrap_err(), (2, "record length does not match its byte count"));
+    }
+}
@@ -1,5 +1,73 @@
 use super::port::Port;
 
+/// How the ports of a board are wired
+pub struct IoMap {
+    /// Ports that set the shift amount, load shift data and read the result
+    pub shift: Option<(u8, u8, u8)>,
+    /// Player inputs and the input port, 1 or 2, and bit each one holds
+    pub inputs: &'static [(Actions, u8, u8)],
+    /// Power-on values of input ports 1 and 2, DIP switches included
+    pub reset: (u8, u8),
+    /// DIP switches: name, input port and the bits they take
+    pub dips: &'static [(&'static str, u8, u8)],
+    /// Bits of input ports 1 and 2 that read low while held
+    pub active_low: (u8, u8),
+    /// Output ports latched for the two sound boards
+    pub sound: Option<(u8, u8)>,
+    /// Sounds the latched bits start: name, output port and bit
+    pub sounds: &'static [(&'static str, u8, u8)],
+    /// Output port that kicks the watchdog
+    pub watchdog: Option<u8>,
+}
+
+/// Space Invaders: inputs on ports 1 and 2, the shift register on 2, 4 and 3,
+/// sound on 3 and 5 and the watchdog on 6
+pub const INVADERS_IO: IoMap = IoMap {
+    shift: Some((2, 4, 3)),
+    inputs: &[
+        (Actions::Credit, 1, 0),
+        (Actions::P2Start, 1, 1),
+        (Actions::P1Start, 1, 2),
+        (Actions::P1Shot, 1, 4),
+        (Actions::P1Left, 1, 5),
+        (Actions::P1Right, 1, 6),
+        (Actions::Tilt, 2, 2),
+        (Actions::P2Shot, 2, 4),
+        (Actions::P2Left, 2, 5),
+        (Actions::P2Right, 2, 6),
+    ],
+    reset: (0b10001000, 0b00000001),
+    dips: &[("Lives", 2, 0b00000011), ("Bonus life at 1000", 2, 0b00001000), ("No coin info", 2, 0b10000000)],
+    active_low: (0, 0),
+    sound: Some((3, 5)),
+    sounds: &[
+        ("UFO", 3, 0),
+        ("Shot", 3, 1),
+        ("Player die", 3, 2),
+        ("Invader die", 3, 3),
+        ("Extra life", 3, 4),
+        ("Fleet 1", 5, 0),
+        ("Fleet 2", 5, 1),
+        ("Fleet 3", 5, 2),
+        ("Fleet 4", 5, 3),
+        ("UFO hit", 5, 4),
+    ],
+    watchdog: Some(6),
+};
+
+/// Inputs as on Space Invaders, with no shift register, DIP switches or
+/// sound
+pub const GENERIC_IO: IoMap = IoMap {
+    shift: None,
+    inputs: INVADERS_IO.inputs,
+    reset: (0, 0),
+    dips: &[],
+    active_low: (0, 0),
+    sound: None,
+    sounds: &[],
+    watchdog: None,
+};
+
 pub struct IO {
     pub r1: Port,
     pub r2: Port,
@@ -11,9 +79,12 @@ pub struct IO {
     pub w5: Port,
     pub w6: Port,
 
-    pub shift: u16
+    pub shift: u16,
+
+    pub map: &'static IoMap,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Actions {
     Credit,
     P2Start,
@@ -29,11 +100,34 @@ pub enum Actions {
     Write4(u8),
 }
 
+/// Player inputs, in the order of their bits in an input mask
+pub const INPUTS: [Actions; 9] = [
+    Actions::Credit,
+    Actions::P2Start,
+    Actions::P1Start,
+    Actions::P1Shot,
+    Actions::P1Left,
+    Actions::P1Right,
+    Actions::P2Shot,
+    Actions::P2Left,
+    Actions::P2Right,
+];
+
+impl Default for IO {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl IO {
     pub fn new() -> Self {
+        Self::with_map(&INVADERS_IO)
+    }
+
+    pub fn with_map(map: &'static IoMap) -> Self {
         Self { 
-            r1: Port::new(0b10001000),
-            r2: Port::new(0b00000001),
+            r1: Port::new(map.reset.0),
+            r2: Port::new(map.reset.1),
             r3: Port::new(0),
             w2: Port::new(0),
             w3: Port::new(0),
@@ -41,35 +135,31 @@ impl IO {
             w5: Port::new(0),
             w6: Port::new(0),
             shift: 0x0000, 
+            map,
+        }
+    }
+
+    /// Input port and bit a player input holds
+    fn input_bit(&self, action: Actions) -> Option<(u8, u8)> {
+        let mut inputs = self.map.inputs.iter();
+        inputs.find(|&&(a, _, _)| a == action).map(|&(_, port, bit)| (port, bit))
+    }
+
+    fn input_port(&mut self, port: u8) -> &mut Port {
+        match port {
+            1 => &mut self.r1,
+            _ => &mut self.r2,
         }
     }
 
     pub fn io_reset_input(&mut self) {
-        self.r1.reset_bit(0);
-        self.r1.reset_bit(1);
-        self.r1.reset_bit(2);
-
-        self.r1.reset_bit(4);
-        self.r1.reset_bit(5);
-        self.r1.reset_bit(6);
-        
-        self.r2.reset_bit(4);
-        self.r2.reset_bit(5);
-        self.r2.reset_bit(6);
+        for &(_, port, bit) in self.map.inputs {
+            self.input_port(port).reset_bit(bit);
+        }
     }
     
     pub fn io_op(&mut self, action: Actions) {
         match action {
-            Actions::Credit => self.r1.set_bit(0),
-            Actions::P2Start => self.r1.set_bit(1),
-            Actions::P1Start => self.r1.set_bit(2),
-            Actions::P1Shot => self.r1.set_bit(4),
-            Actions::P1Left => self.r1.set_bit(5),
-            Actions::P1Right => self.r1.set_bit(6),
-            Actions::Tilt => {},
-            Actions::P2Shot => self.r2.set_bit(4),
-            Actions::P2Left => self.r2.set_bit(5),
-            Actions::P2Right => self.r2.set_bit(6),
             Actions::Write2(n) => {
                 self.w2.reg = n & 0b111;
                 let offset = n & 0b111;
@@ -81,28 +171,69 @@ impl IO {
                 self.shift |= (n as u16) << 8;
                 self.r3.reg = n;
             },
+            input => {
+                if let Some((port, bit)) = self.input_bit(input) {
+                    self.input_port(port).set_bit(bit);
+                }
+            }
         }
     }
 
+    /// Player inputs held down, one bit per `INPUTS` entry
+    pub fn inputs(&self) -> u16 {
+        INPUTS.iter().enumerate().fold(0, |mask, (i, action)| {
+            let held = match self.input_bit(*action) {
+                Some((1, bit)) => self.r1.bit(bit),
+                Some((_, bit)) => self.r2.bit(bit),
+                None => 0,
+            };
+            mask | (held as u16) << i
+        })
+    }
+
+    /// Each DIP switch and its setting, the bits it takes shifted down
+    pub fn dips(&self) -> impl Iterator<Item = (&'static str, u8)> + '_ {
+        self.map.dips.iter().map(|&(name, port, mask)| {
+            let reg = match port {
+                1 => self.r1.reg,
+                _ => self.r2.reg,
+            };
+            (name, (reg & mask) >> mask.trailing_zeros())
+        })
+    }
+
+    /// Replaces the held player inputs with `mask`
+    pub fn set_inputs(&mut self, mask: u16) {
+        self.io_reset_input();
+        for (i, &action) in INPUTS.iter().enumerate() {
+            if mask >> i & 1 == 1 {
+                self.io_op(action);
+            }
+        }
+    }
+
+    /// IN from `port`. Ports the board does not decode read 0.
     pub fn machine_in(&mut self, port: u8) -> u8 {
-        match port {
-            1 => self.r1.reg,
-            2 => self.r2.reg,
-            3 => {
+        match (port, self.map.shift) {
+            (1, _) => self.r1.reg ^ self.map.active_low.0,
+            (2, _) => self.r2.reg ^ self.map.active_low.1,
+            (p, Some((_, _, result))) if p == result => {
                 let offset = self.w2.reg;
                 self.r3.reg = (self.shift >> (8 - offset)) as u8;
                 self.r3.reg
             },
-            _ => self.r1.reg
+            _ => 0,
         }
     }
 
     pub fn machine_out(&mut self, port: u8, a: u8) {
-        match port {
-            2 => self.io_op(Actions::Write2(a)),
-            3 => {},
-            4 => self.io_op(Actions::Write4(a)),
-            5 => {},
+        let map = self.map;
+        match (map.shift, map.sound) {
+            (Some((amount, _, _)), _) if port == amount => self.io_op(Actions::Write2(a)),
+            (Some((_, data, _)), _) if port == data => self.io_op(Actions::Write4(a)),
+            (_, Some((sound1, _))) if port == sound1 => self.w3.reg = a,
+            (_, Some((_, sound2))) if port == sound2 => self.w5.reg = a,
+            _ if map.watchdog == Some(port) => self.w6.reg = a,
             _ => {}
         };
     }
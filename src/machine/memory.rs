@@ -0,0 +1,54 @@
+use crate::emulator::arch::memory::{Access, MemoryMap};
+
+pub const ROM_START: u16 = 0x0000;
+pub const RAM_START: u16 = 0x2000;
+pub const VRAM_START: u16 = 0x2400;
+pub const RAM_SIZE: usize = 0x2000;
+
+/// Memory map of the Midway 8080 board used by Space Invaders.
+///
+/// - 0x0000-0x1fff: program ROM
+/// - 0x2000-0x23ff: work RAM
+/// - 0x2400-0x3fff: video RAM
+/// - 0x4000-0x5fff: ROM socket, empty on Space Invaders
+/// - 0x6000-0x7fff: mirror of RAM
+///
+/// A15 is not decoded, so 0x8000-0xffff mirrors the lower 32K.
+pub fn midway_map() -> MemoryMap {
+    let mut map = MemoryMap::flat();
+    map.rom(ROM_START, 0x2000)
+        .ram(RAM_START, RAM_SIZE)
+        .rom(0x4000, 0x2000)
+        .map(0x6000, RAM_SIZE, RAM_START, Access::Ram)
+        .mirror(0x8000, 0x8000, 0x0000);
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::arch::memory::{Memory, WritePolicy};
+
+    #[test]
+    fn midway() {
+        let mut mem = Memory::new(vec![0xaa; 0x2000]);
+        mem.map = midway_map();
+
+        // ROM ignores writes, including through the A15 mirror
+        mem.write(0x0100, 0x55);
+        mem.write(0x8100, 0x55);
+        assert_eq!(mem.read(0x0100), 0xaa);
+        assert_eq!(mem.read(0x8100), 0xaa);
+
+        // RAM shows up at 0x6000 and again at 0xa000 and 0xe000
+        mem.write(0x6400, 0x12);
+        assert_eq!(mem.data[0x2400], 0x12);
+        assert_eq!(mem.read(0xa400), 0x12);
+        mem.write(0xfffe, 0x34);
+        assert_eq!(mem.read(0x3ffe), 0x34);
+
+        mem.map.policy = WritePolicy::Trap;
+        mem.write(0x1000, 0x00);
+        assert_eq!(mem.fault, Some(0x1000));
+    }
+}
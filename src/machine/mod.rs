@@ -1,3 +1,16 @@
+#[cfg(any(feature = "tui", feature = "window"))]
 pub mod video;
 pub mod port;
-pub mod io;
\ No newline at end of file
+pub mod io;
+pub mod bdos;
+pub mod scheduler;
+pub mod memory;
+pub mod board;
+pub mod savestate;
+pub mod rewind;
+pub mod movie;
+pub mod runner;
+pub mod romset;
+pub mod hexfile;
+pub mod romdb;
+pub mod drivers;
@@ -0,0 +1,281 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::savestate::{self, fingerprint, Reader, Writer};
+use super::scheduler::Scheduler;
+use crate::{Board, CpuError, State};
+
+const MAGIC: &[u8; 4] = b"R80M";
+
+pub const VERSION: u16 = 1;
+
+/// Frames between the state fingerprints a movie is checked against
+pub const CHECK_INTERVAL: u64 = 60;
+
+/// Frames between writes of a movie being recorded
+const AUTOSAVE_INTERVAL: u64 = 600;
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(std::io::Error),
+    Format(&'static str),
+    Version(u16),
+    /// Recorded on a different ROM
+    WrongRom,
+    /// The replay no longer matches the recording from this frame on
+    Desync(u64),
+    Cpu(CpuError),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Io(err) => write!(f, "{}", err),
+            MovieError::Format(what) => write!(f, "Bad movie: {}", what),
+            MovieError::Version(v) => write!(f, "Unsupported movie version {}", v),
+            MovieError::WrongRom => write!(f, "Movie is for a different rom"),
+            MovieError::Desync(frame) => write!(f, "Desync at frame {}", frame),
+            MovieError::Cpu(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+impl From<std::io::Error> for MovieError {
+    fn from(err: std::io::Error) -> Self {
+        MovieError::Io(err)
+    }
+}
+
+/// Player inputs by emulated frame from power on, with fingerprints of
+/// the machine state to tell where a replay stops matching.
+pub struct Movie {
+    rom_id: u64,
+    /// Frames recorded
+    pub length: u64,
+    /// Input mask of `IO::inputs` and the frame it takes effect on
+    inputs: Vec<(u64, u16)>,
+    /// State fingerprint at the start of every `CHECK_INTERVAL`th frame
+    checks: Vec<(u64, u64)>,
+}
+
+impl Movie {
+    pub fn new(board: &Board) -> Movie {
+        Movie { rom_id: board.rom_id, length: 0, inputs: Vec::new(), checks: Vec::new() }
+    }
+
+    /// Records the inputs and state at the start of `frame`. Recording an
+    /// earlier frame again, after a rewind, drops everything past it.
+    pub fn record(&mut self, frame: u64, state: &State, board: &Board) {
+        self.inputs.retain(|&(f, _)| f < frame);
+        self.checks.retain(|&(f, _)| f < frame);
+
+        let mask = board.io.inputs();
+        if self.inputs.last().map(|&(_, m)| m) != Some(mask) {
+            self.inputs.push((frame, mask));
+        }
+        if frame.is_multiple_of(CHECK_INTERVAL) {
+            self.checks.push((frame, state_fingerprint(state, board)));
+        }
+        self.length = frame + 1;
+    }
+
+    pub fn inputs_at(&self, frame: u64) -> u16 {
+        match self.inputs.partition_point(|&(f, _)| f <= frame) {
+            0 => 0,
+            n => self.inputs[n - 1].1,
+        }
+    }
+
+    /// Applies the recorded inputs for the start of `frame` and checks the
+    /// state against the recording
+    pub fn play(&self, frame: u64, state: &State, board: &mut Board) -> Result<(), MovieError> {
+        board.io.set_inputs(self.inputs_at(frame));
+        match self.checks.binary_search_by_key(&frame, |&(f, _)| f) {
+            Ok(i) if self.checks[i].1 != state_fingerprint(state, board) => Err(MovieError::Desync(frame)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Writer(Vec::new());
+        out.0.extend_from_slice(MAGIC);
+        out.u16(VERSION);
+        out.u64(self.rom_id);
+        out.u64(self.length);
+        out.u32(self.inputs.len() as u32);
+        for &(frame, mask) in &self.inputs {
+            out.u64(frame);
+            out.u16(mask);
+        }
+        out.u32(self.checks.len() as u32);
+        for &(frame, hash) in &self.checks {
+            out.u64(frame);
+            out.u64(hash);
+        }
+        out.0
+    }
+
+    /// Reads a movie recorded on the ROM `board` was built with
+    pub fn decode(bytes: &[u8], board: &Board) -> Result<Movie, MovieError> {
+        let mut r = Reader(bytes);
+        if r.take(4) != Some(&MAGIC[..]) {
+            return Err(MovieError::Format("missing header"));
+        }
+        match r.u16() {
+            VERSION => (),
+            version => return Err(MovieError::Version(version)),
+        }
+        if r.u64() != board.rom_id {
+            return Err(MovieError::WrongRom);
+        }
+
+        let mut movie = Movie::new(board);
+        movie.length = r.u64();
+        let n = r.u32() as usize;
+        let mut inputs = Reader(r.take(n * 10).ok_or(MovieError::Format("truncated"))?);
+        movie.inputs = (0..n).map(|_| (inputs.u64(), inputs.u16())).collect();
+        let n = r.u32() as usize;
+        let mut checks = Reader(r.take(n * 16).ok_or(MovieError::Format("truncated"))?);
+        movie.checks = (0..n).map(|_| (checks.u64(), checks.u64())).collect();
+
+        // Lookups by frame rely on the order
+        if !movie.inputs.windows(2).all(|w| w[0].0 < w[1].0)
+            || !movie.checks.windows(2).all(|w| w[0].0 < w[1].0)
+        {
+            return Err(MovieError::Format("frames out of order"));
+        }
+        Ok(movie)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), MovieError> {
+        fs::write(path, self.encode())?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path, board: &Board) -> Result<Movie, MovieError> {
+        Movie::decode(&fs::read(path)?, board)
+    }
+}
+
+/// Fingerprint of everything a replay must reproduce
+fn state_fingerprint(state: &State, board: &Board) -> u64 {
+    fingerprint(&savestate::encode(state, board))
+}
+
+/// Replays a whole movie headless from power on, checking it as it goes.
+/// Returns the fingerprint of the final state.
+pub fn replay(movie: &Movie, state: &mut State, board: &mut Board) -> Result<u64, MovieError> {
+    let mut scheduler = Scheduler::new();
+    while Scheduler::frame_of(state.cycles) < movie.length {
+        movie.play(Scheduler::frame_of(state.cycles), state, board)?;
+        scheduler.run_frame(state, board).map_err(MovieError::Cpu)?;
+    }
+    Ok(state_fingerprint(state, board))
+}
+
+/// Movie recorded or replayed by the interactive front ends
+pub enum Playback {
+    /// `error` holds the last failed autosave
+    Record { movie: Movie, path: PathBuf, error: Option<String> },
+    Replay { movie: Movie, desync: Option<u64> },
+}
+
+impl Playback {
+    pub fn record(path: &str, board: &Board) -> Playback {
+        Playback::Record { movie: Movie::new(board), path: PathBuf::from(path), error: None }
+    }
+
+    pub fn replay(path: &str, board: &Board) -> Result<Playback, MovieError> {
+        let movie = Movie::load(Path::new(path), board)?;
+        Ok(Playback::Replay { movie, desync: None })
+    }
+
+    /// Call before each frame run by the scheduler
+    pub fn frame(&mut self, state: &State, board: &mut Board) {
+        let frame = Scheduler::frame_of(state.cycles);
+        match self {
+            Playback::Record { movie, path, error } => {
+                movie.record(frame, state, board);
+                // The game window can close the process at any time
+                if frame.is_multiple_of(AUTOSAVE_INTERVAL) {
+                    *error = movie.save(path).err().map(|err| err.to_string());
+                }
+            }
+            Playback::Replay { movie, desync } => {
+                if let Err(MovieError::Desync(frame)) = movie.play(frame, state, board) {
+                    desync.get_or_insert(frame);
+                }
+            }
+        }
+    }
+
+    /// Writes out a recording
+    pub fn finish(&self) -> Result<(), MovieError> {
+        match self {
+            Playback::Record { movie, path, .. } => movie.save(path),
+            Playback::Replay { .. } => Ok(()),
+        }
+    }
+
+    pub fn status(&self, state: &State) -> String {
+        let frame = Scheduler::frame_of(state.cycles);
+        match self {
+            Playback::Record { error: Some(err), .. } => err.clone(),
+            Playback::Record { .. } => format!("REC {}", frame),
+            Playback::Replay { desync: Some(at), .. } => format!("DESYNC {}", at),
+            Playback::Replay { movie, .. } if frame >= movie.length => "REPLAY END".to_string(),
+            Playback::Replay { movie, .. } => format!("REPLAY {}/{}", frame, movie.length),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::io::Actions;
+
+    fn power_on() -> (State, Board) {
+        let rom = crate::load_rom("rom/spaceinvaders/space-invaders.rom").unwrap();
+        (State::new(), Board::new(rom))
+    }
+
+    #[test]
+    fn replays_bit_for_bit() {
+        let (mut state, mut board) = power_on();
+        let mut movie = Movie::new(&board);
+        let mut scheduler = Scheduler::new();
+        // Credit, start, then walk left while firing
+        for frame in 0..400 {
+            board.io.io_reset_input();
+            match frame {
+                100..=105 => board.io.io_op(Actions::Credit),
+                200..=205 => board.io.io_op(Actions::P1Start),
+                300.. => {
+                    board.io.io_op(Actions::P1Left);
+                    if frame % 20 < 5 {
+                        board.io.io_op(Actions::P1Shot);
+                    }
+                }
+                _ => (),
+            }
+            movie.record(Scheduler::frame_of(state.cycles), &state, &board);
+            scheduler.run_frame(&mut state, &mut board).unwrap();
+        }
+        let recorded = state_fingerprint(&state, &board);
+
+        let (mut state, mut board) = power_on();
+        let mut movie = Movie::decode(&movie.encode(), &board).unwrap();
+        assert_eq!(movie.inputs_at(150), 0);
+        assert_eq!(replay(&movie, &mut state, &mut board).unwrap(), recorded);
+
+        // Dropping the credit is caught at the next check
+        movie.inputs[1].1 = 0;
+        let (mut state, mut board) = power_on();
+        let result = replay(&movie, &mut state, &mut board);
+        assert!(matches!(result, Err(MovieError::Desync(120))));
+    }
+}
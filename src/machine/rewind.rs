@@ -0,0 +1,241 @@
+use std::collections::VecDeque;
+
+use super::savestate;
+use super::scheduler::{Scheduler, CPU_HZ, FRAME_HZ};
+use crate::{Board, State};
+
+/// Snapshots kept, ten seconds at one per frame
+pub const CAPACITY: usize = 600;
+
+/// Cycles in a frame, how far `step_back_frame` goes
+const FRAME_CYCLES: u64 = CPU_HZ / FRAME_HZ;
+
+/// What happened to the machine since a snapshot, replayed in order
+#[derive(Debug, Clone, Copy)]
+enum Event {
+    /// Input ports r1 and r2 as set by the front end
+    Input(u8, u8),
+    /// Instructions stepped one by one, `scheduled` when the frame
+    /// interrupts fired as under the scheduler
+    Ops { n: u32, scheduled: bool },
+    /// One `Scheduler::run_frame`
+    Frame,
+}
+
+struct Entry {
+    snapshot: Vec<u8>,
+    cycles: u64,
+    log: Vec<Event>,
+}
+
+/// Ring buffer of snapshots taken once per emulated frame, each with the
+/// inputs and runs that lead from it to the next. Going back restores the
+/// closest snapshot and replays its log up to the wanted point.
+///
+/// Changes the log cannot express, such as interrupts raised by hand or a
+/// run that faulted, must start a new snapshot through `mark`.
+pub struct Rewind {
+    entries: VecDeque<Entry>,
+    inputs: (u8, u8),
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Rewind {
+    pub fn new() -> Self {
+        Rewind { entries: VecDeque::new(), inputs: (0, 0) }
+    }
+
+    /// Forgets the history, for when the machine jumps elsewhere
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Starts a new snapshot at the current state
+    pub fn mark(&mut self, state: &State, board: &Board) {
+        if self.entries.len() == CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(Entry {
+            snapshot: savestate::encode(state, board),
+            cycles: state.cycles,
+            log: Vec::new(),
+        });
+        self.inputs = (board.io.r1.reg, board.io.r2.reg);
+    }
+
+    /// Call before running the machine. Snapshots on each new frame and
+    /// logs the inputs when they changed.
+    pub fn record(&mut self, state: &State, board: &Board) {
+        let inputs = (board.io.r1.reg, board.io.r2.reg);
+        match self.entries.back_mut() {
+            Some(entry)
+                if state.cycles >= entry.cycles
+                    && Scheduler::frame_of(state.cycles) == Scheduler::frame_of(entry.cycles) =>
+            {
+                if inputs != self.inputs {
+                    entry.log.push(Event::Input(inputs.0, inputs.1));
+                    self.inputs = inputs;
+                }
+            }
+            _ => self.mark(state, board),
+        }
+    }
+
+    /// Logs `n` instructions run with `State::run_op`
+    pub fn ops(&mut self, n: u32) {
+        let Some(entry) = self.entries.back_mut() else { return };
+        match entry.log.last_mut() {
+            Some(Event::Ops { n: count, scheduled: false }) => *count += n,
+            _ if n > 0 => entry.log.push(Event::Ops { n, scheduled: false }),
+            _ => (),
+        }
+    }
+
+    /// Logs a completed `Scheduler::run_frame`
+    pub fn frame(&mut self) {
+        if let Some(entry) = self.entries.back_mut() {
+            entry.log.push(Event::Frame);
+        }
+    }
+
+    /// Goes back to the start of the previous instruction. Returns false
+    /// once the history is used up.
+    pub fn step_back(&mut self, state: &mut State, board: &mut Board) -> bool {
+        while let Some(entry) = self.entries.back_mut() {
+            let total = replay(entry, state, board, |_, _| false);
+            if total > 0 {
+                replay(entry, state, board, |_, ops| ops == total - 1);
+                return true;
+            }
+            // Already at this snapshot, the one before leads up to it
+            if self.entries.len() == 1 {
+                return false;
+            }
+            self.entries.pop_back();
+        }
+        false
+    }
+
+    /// Goes back one frame's worth of cycles, or as far as the history goes
+    pub fn step_back_frame(&mut self, state: &mut State, board: &mut Board) -> bool {
+        let target = state.cycles.saturating_sub(FRAME_CYCLES);
+        while self.entries.len() > 1 && self.entries.back().is_some_and(|e| e.cycles > target) {
+            self.entries.pop_back();
+        }
+        let Some(entry) = self.entries.back_mut() else { return false };
+        let now = state.cycles;
+        replay(entry, state, board, |state, _| state.cycles >= target);
+        state.cycles < now
+    }
+}
+
+/// Restores the snapshot of `entry` and replays its log until `stop`, given
+/// the state and the instructions run so far, returns true. The log is cut
+/// down to what was replayed. Returns the number of instructions run.
+fn replay(
+    entry: &mut Entry,
+    state: &mut State,
+    board: &mut Board,
+    mut stop: impl FnMut(&State, u32) -> bool,
+) -> u32 {
+    let trap = state.trap_undocumented;
+    // Snapshots come from this board, so they always decode
+    savestate::decode(&entry.snapshot, state, board).expect("rewind snapshot");
+    state.trap_undocumented = trap;
+
+    let mut ops = 0;
+    for (i, &event) in entry.log.iter().enumerate() {
+        let (n, scheduled) = match event {
+            Event::Input(r1, r2) => {
+                (board.io.r1.reg, board.io.r2.reg) = (r1, r2);
+                continue;
+            }
+            Event::Ops { n, scheduled } => (n, scheduled),
+            Event::Frame => (u32::MAX, true),
+        };
+
+        let mut done = 0;
+        let mut ended = false;
+        while done < n && !ended {
+            if stop(state, ops) {
+                break;
+            }
+            let step = match scheduled {
+                true => Scheduler::step(state, board),
+                false => state.run_op(board).map(|_| false),
+            };
+            match step {
+                Ok(end) => ended = end,
+                Err(_) => break,
+            }
+            done += 1;
+            ops += 1;
+        }
+
+        if done < n && !ended {
+            entry.log.truncate(i);
+            if done > 0 {
+                entry.log.push(Event::Ops { n: done, scheduled });
+            }
+            return ops;
+        }
+    }
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(state: &State, board: &Board) -> (u16, u64, u8, Vec<u8>) {
+        (state.pc, state.cycles, state.a, board.mem.data.clone())
+    }
+
+    #[test]
+    fn steps_back_through_frames() {
+        let rom = crate::load_rom("rom/spaceinvaders/space-invaders.rom").unwrap();
+        let (mut state, mut board) = (State::new(), Board::new(rom));
+        let mut scheduler = Scheduler::new();
+        let mut rewind = Rewind::new();
+
+        for frame in 0..6 {
+            if frame == 5 {
+                board.io.r1.set_bit(0);
+            }
+            rewind.record(&state, &board);
+            scheduler.run_frame(&mut state, &mut board).unwrap();
+            rewind.frame();
+        }
+        let mut steps = vec![snapshot(&state, &board)];
+        for _ in 0..3 {
+            rewind.record(&state, &board);
+            state.run_op(&mut board).unwrap();
+            rewind.ops(1);
+            steps.push(snapshot(&state, &board));
+        }
+
+        for expected in steps.iter().rev().skip(1) {
+            assert!(rewind.step_back(&mut state, &mut board));
+            assert!(snapshot(&state, &board) == *expected);
+        }
+
+        // Into the frame run by the scheduler, stepping forward lands back
+        assert!(rewind.step_back(&mut state, &mut board));
+        assert_eq!(board.io.r1.reg & 1, 1);
+        Scheduler::step(&mut state, &mut board).unwrap();
+        assert!(snapshot(&state, &board) == steps[0]);
+
+        let before = state.cycles;
+        assert!(rewind.step_back_frame(&mut state, &mut board));
+        assert!(state.cycles >= before - FRAME_CYCLES && state.cycles < before - FRAME_CYCLES + 24);
+    }
+}
@@ -0,0 +1,179 @@
+use super::bdos::TPA_START;
+use super::drivers::{
+    BalloonBomber, Generic, Invaders, InvadersPart2, LunarRescue, Machine, RomChip, BALLBOMB_ROMS, INVADERS_ROMS,
+    INVADPT2_ROMS, LRESCUE_ROMS,
+};
+
+/// What a rom runs on
+#[derive(Clone, Copy)]
+pub enum System {
+    Board(&'static dyn Machine),
+    /// A CP/M program, run with the BDOS stub of `selftest`
+    Cpm,
+}
+
+impl System {
+    /// Board to inspect the rom on, CP/M programs get the generic one
+    pub fn machine(self) -> &'static dyn Machine {
+        match self {
+            System::Board(machine) => machine,
+            System::Cpm => &Generic,
+        }
+    }
+}
+
+pub struct KnownRom {
+    /// MAME short name
+    pub name: &'static str,
+    pub title: &'static str,
+    pub system: System,
+    /// Name, address, size and CRC32 of each chip, as dumped from the board.
+    /// A CP/M program is one image at the start of the TPA.
+    pub chips: &'static [RomChip],
+    /// SHA1 of the chips end to end, checked as well where it is given
+    pub sha1: Option<&'static str>,
+}
+
+impl KnownRom {
+    /// How far below the addresses of its chips the set sits in `memory`.
+    /// A CP/M program is also looked for at 0, where a copy of it saved as
+    /// a .bin or .rom file loads.
+    pub fn offset(&self, memory: &[u8]) -> Option<u16> {
+        let offsets: &[u16] = match self.system {
+            System::Board(_) => &[0],
+            System::Cpm => &[0, TPA_START],
+        };
+        offsets.iter().copied().find(|&offset| self.matches_at(memory, offset))
+    }
+
+    fn matches_at(&self, memory: &[u8], offset: u16) -> bool {
+        let mut image = Vec::new();
+        let chips = self.chips.iter().all(|&(_, adr, size, crc)| {
+            let Some(start) = adr.checked_sub(offset) else { return false };
+            let data = memory.get(start as usize..start as usize + size);
+            image.extend_from_slice(data.unwrap_or_default());
+            data.is_some_and(|data| crc32(data) == crc)
+        });
+        chips && self.sha1.is_none_or(|hash| sha1(&image) == hash)
+    }
+
+    /// Moves a CP/M program loaded at 0 up to the TPA it runs from, leaving
+    /// page zero clear. Returns whether it was moved.
+    pub fn relocate(&self, memory: &mut [u8]) -> bool {
+        if !matches!(self.system, System::Cpm) || self.offset(memory) != Some(TPA_START) {
+            return false;
+        }
+        let end = self.chips.iter().map(|&(_, adr, size, _)| adr as usize + size).max().unwrap_or(0);
+        memory.copy_within(..end - TPA_START as usize, TPA_START as usize);
+        memory[..TPA_START as usize].fill(0);
+        true
+    }
+}
+
+pub const ROMS: &[KnownRom] = &[
+    KnownRom {
+        name: "invaders",
+        title: "Space Invaders (Midway)",
+        system: System::Board(&Invaders),
+        chips: INVADERS_ROMS,
+        sha1: Some("2c6e7301635fcb5c9b845a97fcb2632eb7fbcbf8"),
+    },
+    KnownRom {
+        name: "sitv",
+        title: "Space Invaders (TV Version)",
+        system: System::Board(&Invaders),
+        chips: &[
+            ("tv0h.s1", 0x0000, 0x0800, 0xfef18aad),
+            ("tv02.rp1", 0x0800, 0x0800, 0x3c759a90),
+            ("tv03.n1", 0x1000, 0x0800, 0x0ad3657f),
+            ("tv04.m1", 0x1800, 0x0800, 0xcd2c67f6),
+        ],
+        sha1: None,
+    },
+    KnownRom {
+        name: "invadpt2",
+        title: "Space Invaders Part II (Taito)",
+        system: System::Board(&InvadersPart2),
+        chips: INVADPT2_ROMS,
+        sha1: None,
+    },
+    KnownRom {
+        name: "lrescue",
+        title: "Lunar Rescue",
+        system: System::Board(&LunarRescue),
+        chips: LRESCUE_ROMS,
+        sha1: None,
+    },
+    KnownRom {
+        name: "ballbomb",
+        title: "Balloon Bomber",
+        system: System::Board(&BalloonBomber),
+        chips: BALLBOMB_ROMS,
+        sha1: None,
+    },
+];
+
+/// Looks a loaded 64K image up in `ROMS`
+pub fn identify(memory: &[u8]) -> Option<&'static KnownRom> {
+    find(ROMS, memory)
+}
+
+fn find<'a>(roms: &'a [KnownRom], memory: &[u8]) -> Option<&'a KnownRom> {
+    roms.iter().find(|rom| rom.offset(memory).is_some())
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+pub fn sha1(data: &[u8]) -> String {
+    sha1_smol::Sha1::from(data).digest().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifies_invaders() {
+        let rom = crate::load_rom("rom/spaceinvaders/space-invaders.rom").unwrap();
+        assert_eq!(identify(&rom).map(|r| r.name), Some("invaders"));
+        assert_eq!(sha1(&rom[..0x800]), "ff6200af4c9110d8181249cbcef1a8a40fa40b7f");
+
+        let mut other = rom.clone();
+        other[0x1000] ^= 1;
+        assert!(identify(&other).is_none());
+    }
+
+    #[test]
+    fn identifies_cpm_program_copies() {
+        // MVI C,02 ; MVI E,'K' ; CALL 0005 ; JMP 0000
+        let program = [0x0e, 0x02, 0x1e, b'K', 0xcd, 0x05, 0x00, 0xc3, 0x00, 0x00];
+        let chips = vec![("test.com", TPA_START, program.len(), crc32(&program))];
+        let mut rom = KnownRom {
+            name: "test",
+            title: "Test program",
+            system: System::Cpm,
+            chips: Vec::leak(chips),
+            sha1: Some(String::leak(sha1(&program))),
+        };
+
+        // Saved as a raw .bin it loads at 0, and is moved up to run
+        let path = std::env::temp_dir().join("rust8080-test.bin");
+        std::fs::write(&path, program).unwrap();
+        let mut memory = crate::RomSet::from_file(path.to_str().unwrap(), 0).unwrap().memory;
+        let _ = std::fs::remove_file(&path);
+        let roms = std::slice::from_ref(&rom);
+        assert_eq!(find(roms, &memory).map(|r| r.name), Some("test"));
+        assert_eq!(rom.offset(&memory), Some(TPA_START));
+
+        assert!(rom.relocate(&mut memory));
+        assert_eq!(rom.offset(&memory), Some(0));
+        assert_eq!(&memory[0x100..0x10a], program);
+        assert!(memory[..0x100].iter().all(|&b| b == 0));
+        assert!(!rom.relocate(&mut memory));
+
+        rom.sha1 = Some("0000000000000000000000000000000000000000");
+        assert!(rom.offset(&memory).is_none());
+    }
+}
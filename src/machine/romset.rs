@@ -0,0 +1,251 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek};
+use std::path::Path;
+use std::str::FromStr;
+
+use zip::ZipArchive;
+
+use super::drivers::MACHINES;
+use crate::emulator::arch::state::MEM_SIZE;
+
+/// One ROM chip: a file, or a member of a zip, and where it goes in memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chip {
+    pub name: String,
+    pub adr: u16,
+    /// Checked when known, as for the sets dumped from real boards
+    pub size: Option<usize>,
+}
+
+impl Chip {
+    pub fn new(name: &str, adr: u16) -> Chip {
+        Chip { name: name.to_string(), adr, size: None }
+    }
+}
+
+/// Parses `file:address`, the address in decimal or 0x hex
+impl FromStr for Chip {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Chip, String> {
+        let Some((name, adr)) = spec.rsplit_once(':') else {
+            return Err(format!("`{}` is not file:address", spec));
+        };
+        let parsed = match adr.strip_prefix("0x").or_else(|| adr.strip_prefix("0X")) {
+            Some(hex) => u16::from_str_radix(hex, 16),
+            None => adr.parse(),
+        };
+        match parsed {
+            Ok(adr) if !name.is_empty() => Ok(Chip::new(name, adr)),
+            _ => Err(format!("`{}` is not file:address with an address from 0 to 0xffff", spec)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(String, io::Error),
+    Zip(String, zip::result::ZipError),
+    /// A chip of the set is not there
+    Missing(Chip),
+    /// The chip is not the size the set says
+    Size(Chip, usize),
+    /// The chip runs past the top of memory
+    TooBig(Chip, usize),
+    /// Two chips load over the same addresses
+    Overlap(Chip, Chip),
+    /// A bad record in a hex file, with its line
+    Record(String, usize, &'static str),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Io(path, err) => write!(f, "{}: {}", path, err),
+            RomError::Zip(path, err) => write!(f, "{}: {}", path, err),
+            RomError::Missing(chip) => write!(f, "Missing rom chip {} for {:#06x}", chip.name, chip.adr),
+            RomError::Size(chip, len) => write!(
+                f,
+                "Rom chip {} is {} bytes, expected {}",
+                chip.name,
+                len,
+                chip.size.unwrap_or_default()
+            ),
+            RomError::TooBig(chip, len) => write!(
+                f,
+                "{} is {} bytes and does not fit in memory when loaded at {:#06x}",
+                chip.name, len, chip.adr
+            ),
+            RomError::Overlap(a, b) => write!(
+                f,
+                "Rom chips {} at {:#06x} and {} at {:#06x} overlap",
+                a.name, a.adr, b.name, b.adr
+            ),
+            RomError::Record(path, line, what) => write!(f, "{}:{}: {}", path, line, what),
+        }
+    }
+}
+
+impl Error for RomError {}
+
+/// A memory image put together from one or more chips
+#[derive(Debug)]
+pub struct RomSet {
+    pub memory: Vec<u8>,
+    /// Each chip loaded and its length, by address
+    pub chips: Vec<(Chip, usize)>,
+    /// Start address given by the file, for hex files
+    pub entry: Option<u16>,
+}
+
+impl RomSet {
+    /// Loads a single raw binary at `adr`
+    pub fn from_file(path: &str, adr: u16) -> Result<RomSet, RomError> {
+        let data = fs::read(path).map_err(|err| RomError::Io(path.to_string(), err))?;
+        RomSet::place(vec![(Chip::new(path, adr), data)])
+    }
+
+    /// Loads each chip from a file of its name
+    pub fn from_files(chips: &[Chip]) -> Result<RomSet, RomError> {
+        let data = chips.iter().map(|chip| match fs::read(&chip.name) {
+            Ok(data) => Ok((chip.clone(), data)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Err(RomError::Missing(chip.clone())),
+            Err(err) => Err(RomError::Io(chip.name.clone(), err)),
+        });
+        RomSet::place(data.collect::<Result<_, _>>()?)
+    }
+
+    /// Loads the chips from the members of a zip archive. When `chips` is
+    /// empty the layout is that of the machine whose set the archive holds,
+    /// or of Space Invaders.
+    pub fn from_zip(path: &str, chips: &[Chip]) -> Result<RomSet, RomError> {
+        let file = File::open(path).map_err(|err| RomError::Io(path.to_string(), err))?;
+        RomSet::read_zip(file, path, chips)
+    }
+
+    fn read_zip(archive: impl Read + Seek, path: &str, chips: &[Chip]) -> Result<RomSet, RomError> {
+        let zip_err = |err| RomError::Zip(path.to_string(), err);
+        let mut archive = ZipArchive::new(archive).map_err(zip_err)?;
+        let chips = match chips {
+            [] => {
+                let sets = MACHINES.iter().map(|m| m.roms()).filter(|chips| !chips.is_empty());
+                let held = sets.clone().find(|chips| chips.iter().all(|c| member(&archive, c.0).is_some()));
+                // Otherwise Space Invaders, first in `MACHINES`, names what is missing
+                let set = held.or(sets.clone().next()).unwrap_or_default();
+                set.iter().map(|&(name, adr, size, _)| Chip { size: Some(size), ..Chip::new(name, adr) }).collect()
+            }
+            chips => chips.to_vec(),
+        };
+
+        let mut data = Vec::new();
+        for chip in chips {
+            let Some(i) = member(&archive, &chip.name) else {
+                return Err(RomError::Missing(chip));
+            };
+            let mut bytes = Vec::new();
+            let mut file = archive.by_index(i).map_err(zip_err)?;
+            file.read_to_end(&mut bytes).map_err(|err| RomError::Io(format!("{}/{}", path, chip.name), err))?;
+            drop(file);
+            data.push((chip, bytes));
+        }
+        RomSet::place(data)
+    }
+
+    /// Lays the chips out in a 64K image, checking each one fits
+    pub(super) fn place(mut data: Vec<(Chip, Vec<u8>)>) -> Result<RomSet, RomError> {
+        data.sort_by_key(|(chip, _)| chip.adr);
+        for (chip, bytes) in &data {
+            if chip.size.is_some_and(|size| size != bytes.len()) {
+                return Err(RomError::Size(chip.clone(), bytes.len()));
+            }
+            if chip.adr as usize + bytes.len() > MEM_SIZE {
+                return Err(RomError::TooBig(chip.clone(), bytes.len()));
+            }
+        }
+        for pair in data.windows(2) {
+            let ((a, a_bytes), (b, _)) = (&pair[0], &pair[1]);
+            if a.adr as usize + a_bytes.len() > b.adr as usize {
+                return Err(RomError::Overlap(a.clone(), b.clone()));
+            }
+        }
+
+        let mut memory = vec![0; MEM_SIZE];
+        let mut chips = Vec::new();
+        for (chip, bytes) in data {
+            let start = chip.adr as usize;
+            memory[start..start + bytes.len()].copy_from_slice(&bytes);
+            chips.push((chip, bytes.len()));
+        }
+        Ok(RomSet { memory, chips, entry: None })
+    }
+}
+
+/// Index of the archive member with the file name `name`. Sets are often
+/// zipped with a folder, or in upper case.
+fn member<R: Read + Seek>(archive: &ZipArchive<R>, name: &str) -> Option<usize> {
+    (0..archive.len()).find(|&i| {
+        archive.name_for_index(i).is_some_and(|path| {
+            let file = path.rsplit('/').next().unwrap_or(path);
+            file.eq_ignore_ascii_case(name)
+        })
+    })
+}
+
+/// How a rom file is laid out, going by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Binary,
+    /// CP/M program, a raw binary loaded at the start of the TPA
+    Com,
+    Zip,
+    IntelHex,
+    SRecord,
+}
+
+impl Format {
+    pub fn of(path: &str) -> Format {
+        let ext = Path::new(path).extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+        match ext.to_ascii_lowercase().as_str() {
+            "com" => Format::Com,
+            "zip" => Format::Zip,
+            "hex" | "ihx" | "ihex" => Format::IntelHex,
+            "s19" | "s28" | "s37" | "srec" | "mot" => Format::SRecord,
+            _ => Format::Binary,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+
+    fn zip_of(files: &[(&str, &[u8])]) -> Cursor<Vec<u8>> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap()
+    }
+
+    #[test]
+    fn splits_match_the_whole_rom() {
+        let whole = fs::read("rom/spaceinvaders/space-invaders.rom").unwrap();
+        let names = ["invaders.h", "invaders.g", "invaders.f", "invaders.e"];
+        let chips: Vec<_> = names.iter().enumerate().map(|(i, &name)| (name, &whole[i * 0x800..][..0x800])).collect();
+        let set = RomSet::read_zip(zip_of(&chips), "invaders.zip", &[]).unwrap();
+        assert_eq!(set.memory[..whole.len()], whole[..]);
+        assert_eq!(set.chips.len(), 4);
+
+        let missing = RomSet::read_zip(zip_of(&chips[..3]), "invaders.zip", &[]);
+        assert!(matches!(missing, Err(RomError::Missing(chip)) if chip.name == "invaders.e"));
+
+        let layout = ["a:0".parse().unwrap(), "b:0x7ff".parse().unwrap()];
+        let overlap = RomSet::read_zip(zip_of(&[("a", &[0; 0x800]), ("b", &[0; 1])]), "set.zip", &layout);
+        assert!(matches!(overlap, Err(RomError::Overlap(..))));
+    }
+}
@@ -0,0 +1,65 @@
+use super::savestate::{encode, fingerprint};
+use super::scheduler::Scheduler;
+use crate::{Board, CpuError, State};
+
+/// Runs a board frame by frame as fast as the host allows, with no front
+/// end attached
+pub struct Runner {
+    pub state: State,
+    pub board: Board,
+    pub scheduler: Scheduler,
+}
+
+impl Runner {
+    pub fn new(state: State, board: Board) -> Runner {
+        Runner { state, board, scheduler: Scheduler::new() }
+    }
+
+    pub fn run_frames(&mut self, frames: u64) -> Result<(), CpuError> {
+        for _ in 0..frames {
+            self.scheduler.run_frame(&mut self.state, &mut self.board)?;
+        }
+        Ok(())
+    }
+
+    /// Frame the machine is in
+    pub fn frame(&self) -> u64 {
+        Scheduler::frame_of(self.state.cycles)
+    }
+
+    /// Video RAM, one bit per pixel with the screen rotated as on the board
+    pub fn screen(&self) -> &[u8] {
+        let vram = self.board.machine.video().vram as usize;
+        &self.board.mem.data[vram..vram + 224 * 32]
+    }
+
+    /// Fingerprint of the whole machine state, equal between two runs
+    /// exactly when they are in sync
+    pub fn fingerprint(&self) -> u64 {
+        fingerprint(&encode(&self.state, &self.board))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invaders() -> Runner {
+        let rom = crate::load_rom("rom/spaceinvaders/space-invaders.rom").unwrap();
+        Runner::new(State::new(), Board::new(rom))
+    }
+
+    #[test]
+    fn runs_in_sync() {
+        let (mut a, mut b) = (invaders(), invaders());
+        a.run_frames(30).unwrap();
+        b.run_frames(10).unwrap();
+        assert_ne!(a.fingerprint(), b.fingerprint());
+        b.run_frames(20).unwrap();
+
+        assert_eq!(a.frame(), 30);
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        assert_eq!(a.screen().len(), 224 * 32);
+        assert!(a.screen().iter().any(|&byte| byte != 0));
+    }
+}
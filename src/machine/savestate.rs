@@ -0,0 +1,479 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::emulator::arch::{i8085::Pins, interrupt::Interrupt, state::Cpu, z80};
+use crate::{Board, State, IO};
+
+const MAGIC: &[u8; 4] = b"R80S";
+
+/// Bumped only when old states can no longer be read. New data goes in
+/// new chunks, or at the end of an existing one, and keeps the version.
+pub const VERSION: u16 = 1;
+
+/// Number of save slots behind the hotkeys
+pub const SLOTS: u8 = 4;
+
+const CPU: &[u8; 4] = b"CPU ";
+const PINS: &[u8; 4] = b"8085";
+const Z80: &[u8; 4] = b"Z80 ";
+const MEM: &[u8; 4] = b"MEM ";
+const IO_PORTS: &[u8; 4] = b"IO  ";
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    /// Not a save state, or cut short
+    Format(&'static str),
+    Version(u16),
+    /// Saved while running a different ROM
+    WrongRom,
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "{}", err),
+            SaveError::Format(what) => write!(f, "Bad save state: {}", what),
+            SaveError::Version(v) => write!(f, "Unsupported save state version {}", v),
+            SaveError::WrongRom => write!(f, "Save state is for a different rom"),
+        }
+    }
+}
+
+impl Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(err: std::io::Error) -> Self {
+        SaveError::Io(err)
+    }
+}
+
+/// FNV-1a hash, identifies the ROM image a state belongs to
+pub fn fingerprint(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Serializes the CPU, memory and IO of a running machine.
+///
+/// The file is the magic, the version and the ROM id followed by tagged,
+/// length prefixed chunks. Readers skip chunks they do not know and
+/// ignore trailing bytes of the ones they do.
+pub fn encode(state: &State, board: &Board) -> Vec<u8> {
+    let mut out = Writer(Vec::with_capacity(0x4000));
+    out.0.extend_from_slice(MAGIC);
+    out.u16(VERSION);
+    out.u64(board.rom_id);
+
+    out.chunk(CPU, |w| {
+        w.u8(cpu_id(state.cpu));
+        for r in [state.a, state.b, state.c, state.d, state.e, state.h, state.l, state.flags.reg] {
+            w.u8(r);
+        }
+        w.u16(state.sp);
+        w.u16(state.pc);
+        w.u8(state.enable);
+        w.bool(state.ei_delay);
+        w.bool(state.halted);
+        match state.interrupt {
+            None => w.u8(0),
+            Some(Interrupt::Rst(n)) => {
+                w.u8(1);
+                w.u16(n as u16);
+            }
+            Some(Interrupt::Call(adr)) => {
+                w.u8(2);
+                w.u16(adr);
+            }
+        }
+        w.u64(state.cycles);
+    });
+
+    match state.cpu {
+        Cpu::I8080 => (),
+        Cpu::I8085 => out.chunk(PINS, |w| {
+            let pins = &state.pins;
+            for pin in [pins.trap, pins.rst7_5, pins.rst7_5_line, pins.rst6_5, pins.rst5_5, pins.sid, pins.sod] {
+                w.bool(pin);
+            }
+            w.u8(pins.mask);
+            w.u8(match pins.trap_ie {
+                None => 0,
+                Some(false) => 1,
+                Some(true) => 2,
+            });
+        }),
+        Cpu::Z80 => out.chunk(Z80, |w| {
+            let z = &state.z80;
+            for pair in [z.af_, z.bc_, z.de_, z.hl_, z.ix, z.iy] {
+                w.u16(pair);
+            }
+            for r in [z.i, z.r, z.im] {
+                w.u8(r);
+            }
+            w.bool(z.iff2);
+            w.bool(z.nmi);
+        }),
+    }
+
+    out.chunk(MEM, |w| pack(&board.mem.data, &mut w.0));
+
+    out.chunk(IO_PORTS, |w| {
+        let io = &board.io;
+        for port in [&io.r1, &io.r2, &io.r3, &io.w2, &io.w3, &io.w4, &io.w5, &io.w6] {
+            w.u8(port.reg);
+        }
+        w.u16(io.shift);
+    });
+
+    out.0
+}
+
+/// Restores a machine from `encode`d bytes. Nothing is changed unless the
+/// whole state could be read.
+pub fn decode(bytes: &[u8], state: &mut State, board: &mut Board) -> Result<(), SaveError> {
+    let mut header = Reader(bytes);
+    if header.take(4) != Some(&MAGIC[..]) {
+        return Err(SaveError::Format("missing header"));
+    }
+    match header.u16() {
+        VERSION => (),
+        version => return Err(SaveError::Version(version)),
+    }
+    if header.u64() != board.rom_id {
+        return Err(SaveError::WrongRom);
+    }
+
+    let mut cpu = None;
+    let mut pins = Pins::default();
+    let mut z80 = z80::Registers::default();
+    let mut mem = None;
+    let mut io = IO::with_map(board.io.map);
+
+    let mut chunks = header;
+    while !chunks.0.is_empty() {
+        let tag = chunks.take(4).ok_or(SaveError::Format("truncated chunk"))?;
+        let len = chunks.u32() as usize;
+        let mut r = Reader(chunks.take(len).ok_or(SaveError::Format("truncated chunk"))?);
+
+        match tag {
+            t if t == CPU => {
+                let mut s = State::with_cpu(cpu_from_id(r.u8()).ok_or(SaveError::Format("unknown cpu"))?);
+                for reg in [&mut s.a, &mut s.b, &mut s.c, &mut s.d, &mut s.e, &mut s.h, &mut s.l, &mut s.flags.reg] {
+                    *reg = r.u8();
+                }
+                s.sp = r.u16();
+                s.pc = r.u16();
+                s.enable = r.u8();
+                s.ei_delay = r.bool();
+                s.halted = r.bool();
+                s.interrupt = match (r.u8(), r.u16()) {
+                    (1, n) => Some(Interrupt::Rst(n as u8)),
+                    (2, adr) => Some(Interrupt::Call(adr)),
+                    _ => None,
+                };
+                s.cycles = r.u64();
+                cpu = Some(s);
+            }
+            t if t == PINS => {
+                for pin in [
+                    &mut pins.trap,
+                    &mut pins.rst7_5,
+                    &mut pins.rst7_5_line,
+                    &mut pins.rst6_5,
+                    &mut pins.rst5_5,
+                    &mut pins.sid,
+                    &mut pins.sod,
+                ] {
+                    *pin = r.bool();
+                }
+                pins.mask = r.u8();
+                pins.trap_ie = match r.u8() {
+                    1 => Some(false),
+                    2 => Some(true),
+                    _ => None,
+                };
+            }
+            t if t == Z80 => {
+                for pair in [&mut z80.af_, &mut z80.bc_, &mut z80.de_, &mut z80.hl_, &mut z80.ix, &mut z80.iy] {
+                    *pair = r.u16();
+                }
+                for reg in [&mut z80.i, &mut z80.r, &mut z80.im] {
+                    *reg = r.u8();
+                }
+                z80.iff2 = r.bool();
+                z80.nmi = r.bool();
+            }
+            t if t == MEM => {
+                let data = unpack(r.0, board.mem.data.len()).ok_or(SaveError::Format("bad memory image"))?;
+                mem = Some(data);
+            }
+            t if t == IO_PORTS => {
+                for port in [&mut io.r1, &mut io.r2, &mut io.r3, &mut io.w2, &mut io.w3, &mut io.w4, &mut io.w5, &mut io.w6] {
+                    port.reg = r.u8();
+                }
+                io.shift = r.u16();
+            }
+            _ => (),
+        }
+    }
+
+    let mut cpu = cpu.ok_or(SaveError::Format("no cpu state"))?;
+    let mem = mem.ok_or(SaveError::Format("no memory image"))?;
+    cpu.pins = pins;
+    cpu.z80 = z80;
+    cpu.trap_undocumented = state.trap_undocumented;
+
+    *state = cpu;
+    board.mem.data = mem;
+    board.io = io;
+    Ok(())
+}
+
+pub fn save(path: &Path, state: &State, board: &Board) -> Result<(), SaveError> {
+    fs::write(path, encode(state, board))?;
+    Ok(())
+}
+
+pub fn load(path: &Path, state: &mut State, board: &mut Board) -> Result<(), SaveError> {
+    decode(&fs::read(path)?, state, board)
+}
+
+/// Save state hotkey, F1-F4 pick a slot, F5 saves and F9 loads
+pub enum SlotKey {
+    Select(u8),
+    Save,
+    Load,
+}
+
+/// Hotkey driven save slots, stored next to the ROM as `<rom>.<n>.sav`
+pub struct Slots {
+    base: PathBuf,
+    pub current: u8,
+    /// Outcome of the last save or load, for the front end to show
+    pub message: Option<String>,
+}
+
+impl Slots {
+    pub fn new(rom_path: &str) -> Slots {
+        Slots { base: PathBuf::from(rom_path), current: 1, message: None }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.base.with_extension(format!("{}.sav", self.current))
+    }
+
+    pub fn select(&mut self, slot: u8) {
+        if (1..=SLOTS).contains(&slot) {
+            self.current = slot;
+            self.message = Some(format!("Slot {}", slot));
+        }
+    }
+
+    pub fn save(&mut self, state: &State, board: &Board) {
+        self.message = Some(match save(&self.path(), state, board) {
+            Ok(()) => format!("Saved slot {}", self.current),
+            Err(err) => err.to_string(),
+        });
+    }
+
+    /// Returns whether a state was loaded
+    pub fn load(&mut self, state: &mut State, board: &mut Board) -> bool {
+        let result = load(&self.path(), state, board);
+        self.message = Some(match &result {
+            Ok(()) => format!("Loaded slot {}", self.current),
+            Err(err) => err.to_string(),
+        });
+        result.is_ok()
+    }
+
+    /// Returns whether a state was loaded
+    pub fn handle(&mut self, key: SlotKey, state: &mut State, board: &mut Board) -> bool {
+        match key {
+            SlotKey::Select(slot) => self.select(slot),
+            SlotKey::Save => self.save(state, board),
+            SlotKey::Load => return self.load(state, board),
+        }
+        false
+    }
+}
+
+fn cpu_id(cpu: Cpu) -> u8 {
+    match cpu {
+        Cpu::I8080 => 0,
+        Cpu::I8085 => 1,
+        Cpu::Z80 => 2,
+    }
+}
+
+fn cpu_from_id(id: u8) -> Option<Cpu> {
+    match id {
+        0 => Some(Cpu::I8080),
+        1 => Some(Cpu::I8085),
+        2 => Some(Cpu::Z80),
+        _ => None,
+    }
+}
+
+/// Run length encoding: a control byte n < 128 is followed by n + 1
+/// literal bytes, n >= 128 by one byte repeated n - 125 times
+fn pack(data: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < data.len() {
+        let run = data[i..].iter().take(130).take_while(|&&b| b == data[i]).count();
+        if run >= 3 {
+            out.push((run + 125) as u8);
+            out.push(data[i]);
+            i += run;
+            continue;
+        }
+
+        // Literals up to the next run of three
+        let start = i;
+        while i < data.len() && i - start < 128 {
+            if i + 2 < data.len() && data[i] == data[i + 1] && data[i] == data[i + 2] {
+                break;
+            }
+            i += 1;
+        }
+        out.push((i - start - 1) as u8);
+        out.extend_from_slice(&data[start..i]);
+    }
+}
+
+fn unpack(mut packed: &[u8], len: usize) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(len);
+    while let Some((&n, rest)) = packed.split_first() {
+        if n < 128 {
+            let (lit, rest) = (rest.get(..n as usize + 1)?, &rest[n as usize + 1..]);
+            data.extend_from_slice(lit);
+            packed = rest;
+        } else {
+            let (&byte, rest) = rest.split_first()?;
+            data.resize(data.len() + n as usize - 125, byte);
+            packed = rest;
+        }
+    }
+    (data.len() == len).then_some(data)
+}
+
+pub(super) struct Writer(pub(super) Vec<u8>);
+
+impl Writer {
+    pub(super) fn u8(&mut self, val: u8) {
+        self.0.push(val);
+    }
+
+    pub(super) fn bool(&mut self, val: bool) {
+        self.0.push(val as u8);
+    }
+
+    pub(super) fn u16(&mut self, val: u16) {
+        self.0.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub(super) fn u32(&mut self, val: u32) {
+        self.0.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub(super) fn u64(&mut self, val: u64) {
+        self.0.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub(super) fn chunk(&mut self, tag: &[u8; 4], body: impl FnOnce(&mut Writer)) {
+        self.0.extend_from_slice(tag);
+        let at = self.0.len();
+        self.0.extend_from_slice(&[0; 4]);
+        body(self);
+        let len = (self.0.len() - at - 4) as u32;
+        self.0[at..at + 4].copy_from_slice(&len.to_le_bytes());
+    }
+}
+
+/// Reads fields in order, fields missing from an older state read as 0
+pub(super) struct Reader<'a>(pub(super) &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub(super) fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.0.get(..n)?;
+        self.0 = &self.0[n..];
+        Some(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> [u8; N] {
+        self.take(N).map_or([0; N], |b| b.try_into().unwrap())
+    }
+
+    pub(super) fn u8(&mut self) -> u8 {
+        self.array::<1>()[0]
+    }
+
+    pub(super) fn bool(&mut self) -> bool {
+        self.u8() != 0
+    }
+
+    pub(super) fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.array())
+    }
+
+    pub(super) fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.array())
+    }
+
+    pub(super) fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.array())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Scheduler;
+
+    fn running_board() -> (State, Board) {
+        let rom = crate::load_rom("rom/spaceinvaders/space-invaders.rom").unwrap();
+        let (mut state, mut board) = (State::new(), Board::new(rom));
+        let mut scheduler = Scheduler::new();
+        for _ in 0..120 {
+            scheduler.run_frame(&mut state, &mut board).unwrap();
+        }
+        (state, board)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (mut state, mut board) = running_board();
+        let mut saved = encode(&state, &board);
+        assert!(saved.len() < 0x4000);
+        // Chunks from a later version are skipped
+        saved.extend_from_slice(b"NEW \x02\x00\x00\x00ab");
+
+        let (mut copy, mut copy_board) = (State::new(), Board::new(vec![0; 0x10000]));
+        copy_board.rom_id = board.rom_id;
+        decode(&saved, &mut copy, &mut copy_board).unwrap();
+
+        let (mut a, mut b) = (Scheduler::new(), Scheduler::new());
+        for _ in 0..60 {
+            a.run_frame(&mut state, &mut board).unwrap();
+            b.run_frame(&mut copy, &mut copy_board).unwrap();
+        }
+        assert_eq!((state.pc, state.cycles), (copy.pc, copy.cycles));
+        assert!(board.mem.data == copy_board.mem.data);
+        assert_eq!(board.io.shift, copy_board.io.shift);
+    }
+
+    #[test]
+    fn rejects_other_rom() {
+        let (state, board) = running_board();
+        let saved = encode(&state, &board);
+
+        let (mut other, mut other_board) = (State::new(), Board::new(vec![0; 0x10000]));
+        assert!(matches!(decode(&saved, &mut other, &mut other_board), Err(SaveError::WrongRom)));
+        other_board.rom_id = board.rom_id;
+        assert!(matches!(decode(&saved[..40], &mut other, &mut other_board), Err(SaveError::Format(_))));
+        assert_eq!(other.cycles, 0);
+    }
+}
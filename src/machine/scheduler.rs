@@ -0,0 +1,142 @@
+use crate::{Bus, CpuError, Interrupt, Memory, State};
+
+pub const CPU_HZ: u64 = 2_000_000;
+pub const FRAME_HZ: u64 = 60;
+
+pub const MID_SCREEN_INT: Interrupt = Interrupt::Rst(1);
+pub const END_SCREEN_INT: Interrupt = Interrupt::Rst(2);
+
+/// A bus with video hardware that interrupts the CPU during each frame
+pub trait Beam: Bus {
+    /// Interrupts raised mid-screen and at vblank
+    fn frame_interrupts(&self) -> (Interrupt, Interrupt) {
+        (MID_SCREEN_INT, END_SCREEN_INT)
+    }
+}
+
+impl Beam for Memory {}
+
+/// Drives the CPU in cycle budgets of one video frame, firing the
+/// mid-screen (RST 1) and vblank (RST 2) interrupts, or whichever the
+/// `Beam` raises, where the beam would be on the real board.
+pub struct Scheduler {
+    pub frame: u64,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler { frame: 0 }
+    }
+
+    /// Cycle count at which frame `n` starts. Computed from the frame index
+    /// so the 33333.3 cycles per frame never drift.
+    pub fn frame_start(n: u64) -> u64 {
+        n * CPU_HZ / FRAME_HZ
+    }
+
+    /// Frame the cycle count falls in
+    pub fn frame_of(cycles: u64) -> u64 {
+        let frame = cycles * FRAME_HZ / CPU_HZ;
+        // frame_start rounds down, so a count on it can belong to the next
+        if cycles >= Self::frame_start(frame + 1) {
+            frame + 1
+        } else {
+            frame
+        }
+    }
+
+    /// Runs a single instruction, firing the frame interrupts when it
+    /// crosses the middle or the end of its frame just like `run_frame`.
+    /// Returns whether the frame ended.
+    pub fn step(state: &mut State, bus: &mut impl Beam) -> Result<bool, CpuError> {
+        let frame = Self::frame_of(state.cycles);
+        let start = Self::frame_start(frame);
+        let end = Self::frame_start(frame + 1);
+        let mid = start + (end - start) / 2;
+
+        let (mid_int, end_int) = bus.frame_interrupts();
+
+        let before = state.cycles;
+        state.run_op(bus)?;
+        if before < mid && state.cycles >= mid {
+            state.request_interrupt(mid_int);
+        }
+        if state.cycles >= end {
+            state.request_interrupt(end_int);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Runs the rest of the current frame. On a CPU error the frame is
+    /// abandoned with the PC left on the faulting instruction.
+    pub fn run_frame(&mut self, state: &mut State, bus: &mut impl Beam) -> Result<(), CpuError> {
+        // Stepping in the debugger advances the clock behind our back,
+        // so pick up from whichever frame the CPU is in now.
+        if state.cycles < Self::frame_start(self.frame)
+            || state.cycles >= Self::frame_start(self.frame + 1)
+        {
+            self.frame = Self::frame_of(state.cycles);
+        }
+
+        let start = Self::frame_start(self.frame);
+        let end = Self::frame_start(self.frame + 1);
+        let mid = start + (end - start) / 2;
+        let (mid_int, end_int) = bus.frame_interrupts();
+
+        if state.cycles < mid {
+            self.run_until(state, bus, mid)?;
+            state.request_interrupt(mid_int);
+        }
+
+        self.run_until(state, bus, end)?;
+        state.request_interrupt(end_int);
+
+        self.frame += 1;
+        Ok(())
+    }
+
+    fn run_until(&self, state: &mut State, bus: &mut impl Bus, target: u64) -> Result<(), CpuError> {
+        while state.cycles < target {
+            state.run_op(bus)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_interrupts() {
+        let mut mem = vec![0; 0x10000];
+        // EI ; JMP 0001
+        mem[..4].copy_from_slice(&[0xfb, 0xc3, 0x01, 0x00]);
+        // RST 1: INR B ; EI ; RET
+        mem[0x08..0x0b].copy_from_slice(&[0x04, 0xfb, 0xc9]);
+        // RST 2: INR C ; EI ; RET
+        mem[0x10..0x13].copy_from_slice(&[0x0c, 0xfb, 0xc9]);
+
+        let mut state = State::new();
+        let mut mem = Memory::new(mem);
+        let mut scheduler = Scheduler::new();
+
+        scheduler.run_frame(&mut state, &mut mem).unwrap();
+        assert_eq!(state.b, 1);
+        assert_eq!(state.c, 0);
+        assert_eq!(state.interrupt, Some(END_SCREEN_INT));
+        assert!(state.cycles >= Scheduler::frame_start(1));
+
+        scheduler.run_frame(&mut state, &mut mem).unwrap();
+        assert_eq!(state.b, 2);
+        assert_eq!(state.c, 1);
+        assert_eq!(scheduler.frame, 2);
+    }
+}
@@ -1,22 +1,22 @@
 use std::process;
 use macroquad::prelude::*;
-use crate::{IO, machine::io::Actions};
+use crate::{Board, IO, machine::{drivers::Video, io::Actions, savestate::SlotKey}};
 
-pub async fn graphics(mem: &Vec<u8>, io: &mut IO) -> bool {
+pub async fn graphics(board: &mut Board) -> bool {
     clear_background(BLACK);
 
-    handle_input(io);
+    handle_input(&mut board.io);
 
     if is_quit_requested() { process::exit(-1) }
 
-    draw_screen(&mem);
+    draw_screen(&board.mem.data, &board.machine.video());
     next_frame().await;
 
     if is_key_down(KeyCode::D) { return true }
     false
 }
 
-fn draw_screen(mem: &Vec<u8>) {
+fn draw_screen(mem: &[u8], video: &Video) {
     let w = screen_width();
     let h = screen_height();
     let facx = w/224.0;
@@ -24,19 +24,22 @@ fn draw_screen(mem: &Vec<u8>) {
 
     for j in 0..224 {
         for i in 0..32 {
-            let byte = mem[0x2400 + (31 - i) + j*32];
+            let byte = mem[video.vram as usize + (31 - i) + j*32];
             
             for bit in 0..8 {
                 let val = ((byte << bit) >> 7) & 0b1 == 0b1;
                 
                 if val {
+                    let row = (i*8 + bit) as u16;
+                    let band = video.colours.iter().find(|&&(last, _)| row < last);
+                    let [r, g, b] = band.map_or([0xff; 3], |&(_, rgb)| rgb);
                     // x and y swapped as video buffer is transposed
                     draw_rectangle( 
                         facy* (j as f32), 
                         facx*((i*8 + bit) as f32), 
                         facy, 
                         facx, 
-                        GREEN
+                        Color::from_rgba(r, g, b, 0xff)
                     );
                 }
             }
@@ -78,4 +81,24 @@ fn handle_input(io: &mut IO) {
     if is_key_down(KeyCode::Key0) {
         io.io_op(Actions::P2Start);
     }
-}
\ No newline at end of file
+}
+
+/// Save state hotkeys pressed in the game window since the last frame
+pub fn slot_key() -> Option<SlotKey> {
+    let slots = [KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4];
+    if let Some(n) = slots.iter().position(|&key| is_key_pressed(key)) {
+        return Some(SlotKey::Select(n as u8 + 1));
+    }
+    if is_key_pressed(KeyCode::F5) {
+        return Some(SlotKey::Save);
+    }
+    if is_key_pressed(KeyCode::F9) {
+        return Some(SlotKey::Load);
+    }
+    None
+}
+
+/// Backspace held in the game window plays the game backwards
+pub fn rewind_held() -> bool {
+    is_key_down(KeyCode::Backspace)
+}
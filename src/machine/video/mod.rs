@@ -1 +1,21 @@
-pub mod graphics;
\ No newline at end of file
+#[cfg(feature = "window")]
+pub mod graphics;
+
+/// Stand-ins for the game window, so the debugger builds without it
+#[cfg(not(feature = "window"))]
+pub mod graphics {
+    use crate::machine::savestate::SlotKey;
+    use crate::Board;
+
+    pub async fn graphics(_board: &mut Board) -> bool {
+        false
+    }
+
+    pub fn slot_key() -> Option<SlotKey> {
+        None
+    }
+
+    pub fn rewind_held() -> bool {
+        false
+    }
+}
\ No newline at end of file
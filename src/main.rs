@@ -1,7 +1,19 @@
+use clap::builder::{PossibleValue, PossibleValuesParser, TypedValueParser};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+#[cfg(feature = "window")]
 use macroquad::window::Conf;
-use rust8080;
+use std::error::Error;
+use std::io::{self, BufWriter};
 use std::process;
 
+use rust8080::{Board, Chip, Cpu, Format, Generic, KnownRom, RomSet, State, System, MACHINES, TPA_START};
+
+/// One minute of emulated time
+const BENCH_FRAMES: u64 = 3600;
+
+const DEFAULT_ROM: &str = "./rom/spaceinvaders/space-invaders.rom";
+
+#[cfg(feature = "window")]
 fn window_conf() -> Conf {
     Conf {
         window_title: "8080 Emulator".to_owned(),
@@ -13,24 +25,448 @@ fn window_conf() -> Conf {
     }
 }
 
-#[macroquad::main(window_conf)]
-async fn main() {
-    let mut file: String = "./rom/spaceinvaders/space-invaders.rom".to_string();
+/// Intel 8080 emulator with a TUI debugger. Without a command the rom opens
+/// paused in the debugger.
+#[derive(Parser)]
+#[command(version, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    let cmd_line = std::env::args();
-    
-    file = match rust8080::get_file(cmd_line) {
-        Some(x) => x,
-        None => file,
-    };
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a rom in the game window, with the debugger a key away
+    Run(RunArgs),
+    /// Open a rom paused in the TUI debugger
+    Debug(RunArgs),
+    /// Print a disassembly of a rom
+    Disasm {
+        #[command(flatten)]
+        rom: RomArgs,
+        /// First address to disassemble, decimal or 0x hex [default: start address]
+        #[arg(long, value_parser = parse_adr)]
+        start: Option<u16>,
+        /// Instructions to print
+        #[arg(long, default_value_t = 32)]
+        count: usize,
+    },
+    /// Print every instruction run, with the registers
+    Trace {
+        #[command(flatten)]
+        rom: RomArgs,
+        /// Video frames to trace
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+        frames: u64,
+    },
+    /// Print what the rom is and how it would be loaded
+    Info {
+        #[command(flatten)]
+        rom: RomArgs,
+    },
+    /// Run a CP/M CPU exerciser (CPUDIAG, 8080PRE, TST8080, 8080EXM)
+    Selftest {
+        /// .COM file, loaded at 0x0100
+        com: String,
+    },
+    /// Run a rom headless as fast as possible and report the clock rate
+    Bench {
+        #[command(flatten)]
+        rom: RomArgs,
+        /// Video frames to run
+        #[arg(long, default_value_t = BENCH_FRAMES, value_parser = clap::value_parser!(u64).range(1..))]
+        frames: u64,
+    },
+}
+
+#[derive(Args)]
+struct RomArgs {
+    /// Raw binary, zip of a rom set, Intel HEX (.hex) or S-record (.s19, .srec)
+    /// file to load [default: the Space Invaders rom]
+    rom: Option<String>,
+
+    /// Rom chip at an address, file:address. Repeat for each chip of a
+    /// split set. Loads from the zip when one is given, else from files.
+    #[arg(long, value_name = "FILE:ADDR", conflicts_with = "load_addr")]
+    chip: Vec<Chip>,
+
+    /// Board the rom runs on [default: the one the rom is known to run on]
+    #[arg(long, value_parser = machines())]
+    machine: Option<String>,
+
+    /// CPU to emulate
+    #[arg(long, value_enum, default_value_t = CpuArg::I8080)]
+    cpu: CpuArg,
+
+    /// Address a raw binary is loaded at, and any rom started at unless
+    /// its file gives a start address, decimal or 0x hex [default: 0x100
+    /// for CP/M programs, else 0]
+    #[arg(long, value_parser = parse_adr)]
+    load_addr: Option<u16>,
+}
+
+impl RomArgs {
+    /// The rom, or the first chip of a set loaded from files, which names
+    /// the save states
+    fn path(&self) -> &str {
+        match (&self.rom, self.chip.first()) {
+            (Some(rom), _) => rom,
+            (None, Some(chip)) => &chip.name,
+            (None, None) => DEFAULT_ROM,
+        }
+    }
+
+    fn load(&self) -> Result<RomSet, Box<dyn Error>> {
+        let set = match &self.rom {
+            Some(rom) if Format::of(rom) == Format::Zip => RomSet::from_zip(rom, &self.chip)?,
+            Some(_) if !self.chip.is_empty() => {
+                return Err("--chip loads from a zip, or from files when no rom is given".into())
+            }
+            Some(rom) => match Format::of(rom) {
+                Format::IntelHex => RomSet::from_intel_hex(rom)?,
+                Format::SRecord => RomSet::from_s_record(rom)?,
+                _ => RomSet::from_file(rom, self.load_addr.unwrap_or(self.default_addr()))?,
+            },
+            None if !self.chip.is_empty() => RomSet::from_files(&self.chip)?,
+            None => RomSet::from_file(DEFAULT_ROM, self.load_addr.unwrap_or(0))?,
+        };
+        Ok(set)
+    }
+
+    /// CP/M loads and starts programs at the start of the TPA
+    fn default_addr(&self) -> u16 {
+        match self.machine.as_deref() == Some("cpm") || Format::of(self.path()) == Format::Com {
+            true => TPA_START,
+            false => 0,
+        }
+    }
+}
+
+#[derive(Args)]
+struct RunArgs {
+    #[command(flatten)]
+    rom: RomArgs,
+
+    /// Video frames to run with --headless
+    #[arg(long, requires = "headless", value_parser = clap::value_parser!(u64).range(1..))]
+    frames: Option<u64>,
+
+    /// Emulated speed, 1 is real time
+    #[arg(long, default_value_t = 1.0, value_parser = parse_speed, conflicts_with = "headless")]
+    speed: f64,
+
+    /// Start running the game rather than paused in the debugger
+    #[arg(long, conflicts_with = "headless")]
+    start_in_game: bool,
+
+    /// Run with no TUI or window and print where the machine ended up
+    #[arg(long)]
+    headless: bool,
+
+    /// Record the inputs of the session to a movie file
+    #[arg(long, value_name = "MOVIE", conflicts_with_all = ["replay", "headless"])]
+    record: Option<String>,
 
-    let memory = rust8080::load_rom(&file).unwrap_or_else(|err| {
-        eprintln!("Error loading rom: {}", err);
-        process::exit(-1);
+    /// Replay a movie file, checking it still matches
+    #[arg(long, value_name = "MOVIE")]
+    replay: Option<String>,
+}
+
+/// Names of the machine drivers, and cpm for CP/M programs
+fn machines() -> impl TypedValueParser<Value = String> {
+    let drivers = MACHINES.iter().map(|m| match m.name() {
+        "generic" => PossibleValue::new("generic").help(m.title()).alias("flat"),
+        name => PossibleValue::new(name).help(m.title()),
     });
+    let cpm = PossibleValue::new("cpm").help("CP/M program run with the BDOS stub, as by selftest");
+    // Aliases come through as typed
+    PossibleValuesParser::new(drivers.chain([cpm])).map(|name| match name.as_str() {
+        "flat" => "generic".to_string(),
+        _ => name,
+    })
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CpuArg {
+    #[value(name = "8080")]
+    I8080,
+    #[value(name = "8085")]
+    I8085,
+    #[value(name = "z80")]
+    Z80,
+}
+
+impl From<CpuArg> for Cpu {
+    fn from(cpu: CpuArg) -> Cpu {
+        match cpu {
+            CpuArg::I8080 => Cpu::I8080,
+            CpuArg::I8085 => Cpu::I8085,
+            CpuArg::Z80 => Cpu::Z80,
+        }
+    }
+}
+
+fn parse_adr(arg: &str) -> Result<u16, String> {
+    let parsed = match arg.strip_prefix("0x").or_else(|| arg.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => arg.parse(),
+    };
+    parsed.map_err(|_| format!("`{}` is not an address from 0 to 0xffff", arg))
+}
+
+fn parse_speed(arg: &str) -> Result<f64, String> {
+    match arg.parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed <= 100.0 => Ok(speed),
+        _ => Err(format!("`{}` is not a speed between 0 and 100", arg)),
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        None => run(cli.run, false),
+        Some(Command::Run(args)) => run(args, true),
+        Some(Command::Debug(args)) => run(args, false),
+        Some(Command::Disasm { rom, start, count }) => disasm(&rom, start, count),
+        Some(Command::Trace { rom, frames }) => trace(&rom, frames),
+        Some(Command::Info { rom }) => info(&rom),
+        Some(Command::Selftest { com }) => rust8080::selftest(&com).and_then(report),
+        Some(Command::Bench { rom, frames }) => {
+            machine(&rom).and_then(|(state, board, _)| rust8080::benchmark(state, board, frames).map(|_| ()))
+        }
+    };
 
-    let state = rust8080::State::new(memory);
-    let io = rust8080::IO::new(); 
-    rust8080::emulate(state, io).await;
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
     process::exit(0);
-}
\ No newline at end of file
+}
+
+/// Loads the rom and builds the machine it runs on
+fn machine(args: &RomArgs) -> Result<(State, Board, System), Box<dyn Error>> {
+    let set = args.load()?;
+    let known = rust8080::identify(&set.memory);
+    let system = system(args, known);
+    if args.machine.is_none() && known.is_none() && matches!(system, System::Board(_)) {
+        eprintln!("warning: {} is not a known rom, running it on the generic machine", args.path());
+    }
+    let (state, board) = boot(args, set.memory, set.entry, system, known);
+    Ok((state, board, system))
+}
+
+/// The machine given with --machine, else the one the rom is known to run
+/// on, else CP/M for a .com file
+fn system(args: &RomArgs, known: Option<&KnownRom>) -> System {
+    match (args.machine.as_deref(), known) {
+        (Some("cpm"), _) => System::Cpm,
+        (Some(name), _) => System::Board(rust8080::machine(name).expect("checked by clap")),
+        (None, Some(rom)) => rom.system,
+        (None, None) if Format::of(args.path()) == Format::Com => System::Cpm,
+        (None, None) => System::Board(&Generic),
+    }
+}
+
+/// Starts at the address the rom file gives, or else the load address. A
+/// known CP/M program loaded at 0 is moved up to the TPA and started there.
+fn boot(
+    args: &RomArgs,
+    mut memory: Vec<u8>,
+    entry: Option<u16>,
+    system: System,
+    known: Option<&KnownRom>,
+) -> (State, Board) {
+    let mut state = State::with_cpu(args.cpu.into());
+    let moved = matches!(system, System::Cpm) && known.is_some_and(|rom| rom.relocate(&mut memory));
+    state.pc = match (entry, args.load_addr, system) {
+        _ if moved => TPA_START,
+        (Some(adr), _, _) | (None, Some(adr), _) => adr,
+        (None, None, System::Cpm) => TPA_START,
+        (None, None, System::Board(_)) => args.default_addr(),
+    };
+    (state, Board::with_machine(memory, system.machine()))
+}
+
+fn run(args: RunArgs, in_game: bool) -> Result<(), Box<dyn Error>> {
+    let (state, board, system) = machine(&args.rom)?;
+    if let System::Cpm = system {
+        return rust8080::run_cpm(state, board.mem.data).and_then(report);
+    }
+
+    if args.headless {
+        return match &args.replay {
+            Some(movie) => rust8080::verify_movie(state, board, movie).map(|_| ()),
+            None => rust8080::headless(state, board, args.frames.unwrap_or(BENCH_FRAMES)).map(|_| ()),
+        };
+    }
+
+    start(state, board, &args, in_game || args.start_in_game)
+}
+
+#[cfg(any(feature = "tui", feature = "window"))]
+fn session(args: &RunArgs, board: &Board) -> Result<rust8080::Session, Box<dyn Error>> {
+    let playback = match (&args.record, &args.replay) {
+        (Some(movie), _) => Some(rust8080::Playback::record(movie, board)),
+        (_, Some(movie)) => Some(rust8080::Playback::replay(movie, board)?),
+        _ => None,
+    };
+    let mut session = rust8080::Session::new(rust8080::Slots::new(args.rom.path()), playback);
+    session.speed = args.speed;
+    Ok(session)
+}
+
+#[cfg(feature = "window")]
+fn start(state: State, board: Board, args: &RunArgs, in_game: bool) -> Result<(), Box<dyn Error>> {
+    let session = session(args, &board)?;
+    macroquad::Window::from_config(window_conf(), front_end(state, board, session, in_game));
+    Ok(())
+}
+
+#[cfg(all(feature = "tui", not(feature = "window")))]
+fn start(state: State, board: Board, args: &RunArgs, in_game: bool) -> Result<(), Box<dyn Error>> {
+    let session = session(args, &board)?;
+    block_on(front_end(state, board, session, in_game));
+    Ok(())
+}
+
+#[cfg(not(any(feature = "tui", feature = "window")))]
+fn start(_: State, _: Board, _: &RunArgs, _: bool) -> Result<(), Box<dyn Error>> {
+    Err("built without the tui and window features, run with --headless".into())
+}
+
+#[cfg(feature = "tui")]
+async fn front_end(state: State, board: Board, session: rust8080::Session, in_game: bool) {
+    rust8080::emulate(state, board, session, in_game).await;
+}
+
+/// Without the debugger the game window is all there is
+#[cfg(all(feature = "window", not(feature = "tui")))]
+async fn front_end(state: State, board: Board, session: rust8080::Session, _: bool) {
+    rust8080::play(state, board, session).await;
+}
+
+/// Drives the debugger without macroquad, whose frame loop is otherwise the
+/// executor. Nothing else ever waits, so polling until ready is enough.
+#[cfg(all(feature = "tui", not(feature = "window")))]
+fn block_on(fut: impl std::future::Future<Output = ()>) {
+    let mut fut = std::pin::pin!(fut);
+    let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+    while fut.as_mut().poll(&mut cx).is_pending() {}
+}
+
+fn disasm(args: &RomArgs, start: Option<u16>, count: usize) -> Result<(), Box<dyn Error>> {
+    let (state, board, _) = machine(args)?;
+    let start = start.unwrap_or(state.pc);
+    rust8080::disassemble(state.cpu, &board.mem.data, start, count, &mut io::stdout().lock())?;
+    Ok(())
+}
+
+fn trace(args: &RomArgs, frames: u64) -> Result<(), Box<dyn Error>> {
+    let (mut state, mut board, _) = machine(args)?;
+    match rust8080::trace(&mut state, &mut board, frames, &mut BufWriter::new(io::stdout().lock())) {
+        // Piped into head or less and closed early
+        Err(err) if err.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) => Ok(()),
+        result => result,
+    }
+}
+
+fn info(args: &RomArgs) -> Result<(), Box<dyn Error>> {
+    let set = args.load()?;
+    let known = rust8080::identify(&set.memory);
+    let system = system(args, known);
+    let (state, board) = boot(args, set.memory.clone(), set.entry, system, known);
+    let data: Vec<u8> = set.chips.iter().flat_map(|(chip, len)| &set.memory[chip.adr as usize..][..*len]).copied().collect();
+
+    println!("rom      {}", args.path());
+    match known {
+        Some(rom) => println!("known    {} ({})", rom.title, rom.name),
+        None => println!("known    no"),
+    }
+    println!("size     {} bytes", data.len());
+    for (chip, len) in &set.chips {
+        let end = chip.adr as usize + len.max(&1) - 1;
+        println!("loaded   {:#06x}-{:#06x}  {}", chip.adr, end, chip.name);
+    }
+    println!("crc32    {:08x}", rust8080::crc32(&data));
+    println!("sha1     {}", rust8080::sha1(&data));
+    println!("id       {:016x}", board.rom_id);
+    println!("cpu      {}", name(args.cpu));
+    match system {
+        System::Board(machine) => println!("machine  {} ({})", machine.name(), machine.title()),
+        System::Cpm => println!("machine  cpm"),
+    }
+    for (dip, setting) in board.io.dips() {
+        println!("dip      {} = {}", dip, setting);
+    }
+    let sounds = board.io.map.sounds.iter().map(|(name, port, bit)| format!("{}.{} {}", port, bit, name));
+    let sounds: Vec<String> = sounds.collect();
+    if !sounds.is_empty() {
+        println!("sounds   {}", sounds.join(", "));
+    }
+    println!("entry    {:04x}  {}", state.pc, rust8080::decode_as(state.cpu, &board.mem.data, state.pc));
+    Ok(())
+}
+
+/// Name of an option value as given on the command line
+fn name(value: impl ValueEnum) -> String {
+    value.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default()
+}
+
+/// Prints that a CP/M exerciser passed, or hands `main` the failure to
+/// exit with
+fn report(passed: bool) -> Result<(), Box<dyn Error>> {
+    match passed {
+        true => {
+            println!("selftest: PASS");
+            Ok(())
+        }
+        false => Err("selftest: FAIL".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::error::ErrorKind;
+    use clap::CommandFactory;
+
+    fn parse(args: &str) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(["rust8080"].into_iter().chain(args.split_whitespace()))
+    }
+
+    #[test]
+    fn command_line() {
+        Cli::command().debug_assert();
+
+        let Ok(Cli { command: Some(Command::Run(args)), .. }) =
+            parse("run --headless --frames 5 --machine flat --cpu z80 --load-addr 0x100 game.bin")
+        else {
+            panic!("run did not parse");
+        };
+        assert_eq!((args.frames, args.rom.load_addr), (Some(5), Some(0x100)));
+        assert_eq!(args.rom.machine.as_deref(), Some("generic"));
+        assert_eq!(args.rom.path(), "game.bin");
+
+        let chips = parse("info --chip a.h:0 --chip a.g:0x800").unwrap();
+        let Some(Command::Info { rom }) = chips.command else { panic!("info did not parse") };
+        assert_eq!(rom.chip, [Chip::new("a.h", 0), Chip::new("a.g", 0x800)]);
+
+        let kind = |args| parse(args).err().map(|err| err.kind());
+        assert_eq!(kind("run --frames 5"), Some(ErrorKind::MissingRequiredArgument));
+        assert_eq!(kind("run --headless --speed 2"), Some(ErrorKind::ArgumentConflict));
+        assert_eq!(kind("info --chip a:0 --load-addr 0"), Some(ErrorKind::ArgumentConflict));
+        assert_eq!(kind("info --load-addr 0x10000"), Some(ErrorKind::ValueValidation));
+        assert_eq!(kind("info --machine pacman"), Some(ErrorKind::InvalidValue));
+        assert_eq!(kind("trace --frames 0"), Some(ErrorKind::ValueValidation));
+    }
+
+    #[test]
+    fn failed_selftest_exits_through_main() {
+        assert!(report(true).is_ok());
+        assert_eq!(report(false).unwrap_err().to_string(), "selftest: FAIL");
+    }
+}
@@ -0,0 +1,212 @@
+use std::time::{Duration, Instant};
+
+use crate::machine::scheduler::FRAME_HZ;
+use crate::{Board, CpuError, Playback, Rewind, Scheduler, Slots, State};
+
+const MAX_CATCHUP_FRAMES: u32 = 4;
+
+/// What the interactive front ends keep between frames: pacing against
+/// the wall clock, save slots, the rewind history and the movie.
+pub struct Session {
+    pub scheduler: Scheduler,
+    pub clock: Instant,
+    pub slots: Slots,
+    pub rewind: Rewind,
+    pub playback: Option<Playback>,
+    /// Emulated time per second of wall-clock time
+    pub speed: f64,
+}
+
+impl Session {
+    pub fn new(slots: Slots, playback: Option<Playback>) -> Session {
+        Session {
+            scheduler: Scheduler::new(),
+            clock: Instant::now(),
+            slots,
+            rewind: Rewind::new(),
+            playback,
+            speed: 1.0,
+        }
+    }
+
+    /// Restarts pacing from now, after time spent not running frames
+    pub fn pause(&mut self) {
+        self.clock = Instant::now();
+    }
+
+    /// Emulates however many 60 Hz frames of wall-clock time have passed,
+    /// so game speed does not follow the host frame rate
+    pub fn run_due(&mut self, state: &mut State, board: &mut Board) -> Result<(), CpuError> {
+        let rate = FRAME_HZ as f64 * self.speed;
+        let due = (self.clock.elapsed().as_secs_f64() * rate) as u32;
+        if due > MAX_CATCHUP_FRAMES * (self.speed.ceil() as u32) {
+            self.clock = Instant::now();
+            return self.run_frame(state, board);
+        }
+        for _ in 0..due {
+            self.run_frame(state, board)?;
+        }
+        self.clock += Duration::from_secs_f64(due as f64 / rate);
+        Ok(())
+    }
+
+    /// Whether a movie is being recorded. The movie only holds the inputs
+    /// for whole frames, so nothing may run or undo instructions outside
+    /// the scheduler meanwhile, or its replay would go another way.
+    pub fn recording(&self) -> bool {
+        matches!(self.playback, Some(Playback::Record { .. }))
+    }
+
+    /// Runs up to `ops` instructions for the debugger, stopping at a fault.
+    /// Does nothing while recording.
+    pub fn step(&mut self, state: &mut State, board: &mut Board, ops: u32) -> Result<(), CpuError> {
+        if self.recording() {
+            return Ok(());
+        }
+        self.rewind.record(state, board);
+        let mut ran = 0;
+        let result = (0..ops).try_for_each(|_| {
+            state.run_op(board)?;
+            ran += 1;
+            Ok(())
+        });
+        self.rewind.ops(ran);
+        if result.is_err() {
+            self.rewind.mark(state, board);
+        }
+        result
+    }
+
+    /// Undoes one instruction, returns whether there was one to undo.
+    /// Does nothing while recording.
+    pub fn step_back(&mut self, state: &mut State, board: &mut Board) -> bool {
+        !self.recording() && self.rewind.step_back(state, board)
+    }
+
+    /// Plays one frame backwards, returns whether there was one. Does
+    /// nothing while recording.
+    pub fn rewind_frame(&mut self, state: &mut State, board: &mut Board) -> bool {
+        if self.recording() || !self.rewind.step_back_frame(state, board) {
+            return false;
+        }
+        self.pause();
+        true
+    }
+
+    /// Loads the current save slot, returns whether a state was loaded.
+    /// Does nothing while recording.
+    pub fn load(&mut self, state: &mut State, board: &mut Board) -> bool {
+        let loaded = !self.recording() && self.slots.load(state, board);
+        if loaded {
+            self.rewind.clear();
+        }
+        loaded
+    }
+
+    /// Runs a frame with the scheduler, keeping the rewind history and the
+    /// movie being recorded or replayed
+    fn run_frame(&mut self, state: &mut State, board: &mut Board) -> Result<(), CpuError> {
+        if let Some(playback) = &mut self.playback {
+            playback.frame(state, board);
+        }
+        self.rewind.record(state, board);
+        match self.scheduler.run_frame(state, board) {
+            Ok(()) => {
+                self.rewind.frame();
+                Ok(())
+            }
+            Err(err) => {
+                self.rewind.mark(state, board);
+                Err(err)
+            }
+        }
+    }
+
+    /// Writes out a movie being recorded
+    pub fn finish(self) {
+        if let Some(Err(err)) = self.playback.map(|p| p.finish()) {
+            eprintln!("Error saving movie: {}", err);
+        }
+    }
+}
+
+/// Runs the game in the window alone, for builds without the debugger
+#[cfg(feature = "window")]
+pub async fn play(mut state: State, mut board: Board, mut session: Session) {
+    use crate::machine::savestate::SlotKey;
+    use crate::machine::video::graphics;
+
+    loop {
+        if graphics::rewind_held() {
+            session.rewind_frame(&mut state, &mut board);
+        } else if let Err(err) = session.run_due(&mut state, &mut board) {
+            eprintln!("{}", err);
+            break;
+        }
+
+        graphics::graphics(&mut board).await;
+        match graphics::slot_key() {
+            Some(SlotKey::Load) => {
+                session.load(&mut state, &mut board);
+            }
+            Some(key) => {
+                session.slots.handle(key, &mut state, &mut board);
+            }
+            None => (),
+        }
+        if let Some(msg) = session.slots.message.take() {
+            println!("{}", msg);
+        }
+    }
+    session.finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::io::Actions;
+    use crate::machine::movie::{replay, Movie};
+    use crate::machine::savestate::{encode, fingerprint};
+
+    const ROM: &str = "rom/spaceinvaders/space-invaders.rom";
+
+    fn power_on() -> (State, Board) {
+        (State::new(), Board::new(crate::load_rom(ROM).unwrap()))
+    }
+
+    #[test]
+    fn debugger_keeps_off_a_recording() {
+        let path = std::env::temp_dir().join("rust8080-session-test.r80m");
+        let (mut state, mut board) = power_on();
+        let playback = Playback::record(path.to_str().unwrap(), &board);
+        let mut session = Session::new(Slots::new(ROM), Some(playback));
+
+        for frame in 0..200 {
+            board.io.io_reset_input();
+            if (100..106).contains(&frame) {
+                board.io.io_op(Actions::Credit);
+            }
+            session.run_frame(&mut state, &mut board).unwrap();
+
+            let before = fingerprint(&encode(&state, &board));
+            session.step(&mut state, &mut board, 100).unwrap();
+            assert!(!session.step_back(&mut state, &mut board));
+            assert!(!session.rewind_frame(&mut state, &mut board));
+            assert!(!session.load(&mut state, &mut board));
+            assert_eq!(fingerprint(&encode(&state, &board)), before);
+        }
+        let recorded = fingerprint(&encode(&state, &board));
+        let Some(Playback::Record { movie, .. }) = &session.playback else { unreachable!() };
+        let movie = Movie::decode(&movie.encode(), &board).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let (mut replayed, mut board) = power_on();
+        assert_eq!(replay(&movie, &mut replayed, &mut board).unwrap(), recorded);
+
+        // Without a movie the same keys do move the machine
+        let mut session = Session::new(Slots::new(ROM), None);
+        session.step(&mut replayed, &mut board, 100).unwrap();
+        assert_ne!(replayed.cycles, state.cycles);
+        assert!(session.step_back(&mut replayed, &mut board));
+    }
+}